@@ -0,0 +1,156 @@
+use common::physics::{ChatMessage, ClientMessage, Label, LabelAnchor, RigidBody, UserInfo, Vec2};
+use crate::font::{draw_text, text_width};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+const TEXT_SCALE: u32 = 2;
+const LINE_HEIGHT: i32 = 18;
+// 屏幕左下角显示的聊天条数
+const VISIBLE_MESSAGES: usize = 8;
+// 与服务器的校验保持一致
+const MAX_INPUT_CHARS: usize = 200;
+
+// 点阵字体只有 ASCII，其他字符显示为 '?'
+fn printable(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_ascii_graphic() || c == ' ' { c } else { '?' })
+        .collect()
+}
+
+// 聊天输入框：Enter 打开，再按 Enter 发送，Esc 取消
+pub struct ChatInput {
+    pub active: bool,
+    text: String,
+}
+
+impl ChatInput {
+    pub fn new() -> Self {
+        Self {
+            active: false,
+            text: String::new(),
+        }
+    }
+
+    pub fn open(&mut self) {
+        self.active = true;
+        self.text.clear();
+    }
+
+    pub fn cancel(&mut self) {
+        self.active = false;
+        self.text.clear();
+    }
+
+    pub fn push(&mut self, text: &str) {
+        for c in text.chars().filter(|c| !c.is_control()) {
+            if self.text.chars().count() < MAX_INPUT_CHARS {
+                self.text.push(c);
+            }
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        self.text.pop();
+    }
+
+    // 结束输入并把内容转换为要发送的消息：
+    // "/label 文字" 在鼠标处添加标注（鼠标下有物体时固定在物体上），"/unlabel" 删除最近的标注，
+    // "/save 名字"、"/load 名字" 保存和加载服务器上的场景，"/scenes" 列出场景
+    pub fn submit(&mut self, cursor: Vec2, bodies: &[RigidBody], labels: &[Label]) -> Option<ClientMessage> {
+        self.active = false;
+        let text = std::mem::take(&mut self.text);
+        let text = text.trim();
+        if text.is_empty() {
+            return None;
+        }
+        if let Some(rest) = text.strip_prefix("/label ") {
+            let anchor = match crate::body_at(bodies, cursor).and_then(|id| bodies.iter().find(|b| b.id == id)) {
+                Some(body) => LabelAnchor::Body {
+                    body_id: body.id,
+                    offset: cursor - body.position,
+                },
+                None => LabelAnchor::Point { position: cursor },
+            };
+            return Some(ClientMessage::AddLabel {
+                text: rest.trim().to_string(),
+                anchor,
+            });
+        }
+        if let Some(name) = text.strip_prefix("/save ") {
+            return Some(ClientMessage::SaveScene { name: name.trim().to_string() });
+        }
+        if let Some(name) = text.strip_prefix("/load ") {
+            return Some(ClientMessage::LoadScene { name: name.trim().to_string() });
+        }
+        if text == "/scenes" {
+            return Some(ClientMessage::ListScenes);
+        }
+        if text == "/unlabel" {
+            let nearest = labels
+                .iter()
+                .filter_map(|label| label_position(label, bodies).map(|p| (label.id, (p - cursor).length())))
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            return nearest.map(|(label_id, _)| ClientMessage::RemoveLabel { label_id });
+        }
+        Some(ClientMessage::Chat { text: text.to_string() })
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas<Window>, height: i32) {
+        if !self.active {
+            return;
+        }
+        let y = height - LINE_HEIGHT - 10;
+        canvas.set_draw_color(Color::RGBA(20, 20, 30, 220));
+        canvas.fill_rect(Rect::new(8, y - 4, 600, LINE_HEIGHT as u32 + 4)).ok();
+        let line = format!("> {}_", printable(&self.text));
+        draw_text(canvas, &line, 12, y, TEXT_SCALE, Color::RGB(255, 255, 255));
+    }
+}
+
+// 左下角的聊天记录，名字用发送者的光标颜色
+pub fn draw_log(canvas: &mut Canvas<Window>, messages: &[ChatMessage], users: &[UserInfo], height: i32) {
+    let visible = &messages[messages.len().saturating_sub(VISIBLE_MESSAGES)..];
+    // 给输入框留出一行
+    let bottom = height - 2 * LINE_HEIGHT - 14;
+    for (i, message) in visible.iter().rev().enumerate() {
+        let y = bottom - i as i32 * LINE_HEIGHT;
+        let name = match printable(&message.name).trim() {
+            "" => format!("USER {}", message.client_id),
+            name => name.to_string(),
+        };
+        let [r, g, b] = users
+            .iter()
+            .find(|u| u.client_id == message.client_id)
+            .map_or([200, 200, 200], |u| u.color);
+        let prefix = format!("{}: ", name);
+        draw_text(canvas, &prefix, 12, y, TEXT_SCALE, Color::RGB(r, g, b));
+        let x = 12 + text_width(&prefix, TEXT_SCALE);
+        draw_text(canvas, &printable(&message.text), x, y, TEXT_SCALE, Color::RGB(230, 230, 230));
+    }
+}
+
+// 按插值后的物体位置计算标注位置，保证标注跟着画出来的物体走
+fn label_position(label: &Label, bodies: &[RigidBody]) -> Option<Vec2> {
+    match label.anchor {
+        LabelAnchor::Body { body_id, offset } => {
+            bodies.iter().find(|b| b.id == body_id).map(|b| b.position + offset)
+        }
+        LabelAnchor::Point { position } => Some(position),
+    }
+}
+
+pub fn draw_labels(canvas: &mut Canvas<Window>, labels: &[Label], bodies: &[RigidBody]) {
+    for label in labels {
+        let Some(pos) = label_position(label, bodies) else {
+            continue;
+        };
+        let text = printable(&label.text);
+        let (x, y) = (pos.x as i32, pos.y as i32);
+        let width = text_width(&text, TEXT_SCALE);
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 180));
+        canvas.fill_rect(Rect::new(x - 3, y - 3, (width + 6) as u32, 20)).ok();
+        draw_text(canvas, &text, x, y, TEXT_SCALE, Color::RGB(255, 220, 120));
+    }
+}
@@ -0,0 +1,166 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+// 最近连接过的服务器最多保留几个
+const MAX_RECENT_SERVERS: usize = 8;
+
+const USAGE: &str = "用法: client [选项]
+  --server <地址:端口>   服务器地址，未指定时在终端中询问
+  --name <名字>          玩家名字
+  --room <房间>          直接加入（不存在则新建）该房间，不再询问
+  --size <宽x高>         窗口大小，例如 1600x1000，默认 1200x800
+  --fullscreen           全屏显示
+  --spectator            观众模式，只观看不操作
+  --assets <目录>        资源目录，默认 assets
+  --record <文件>        把收到的快照录制到文件，之后可以用 --play 回看
+  --play <文件>          回看快照录像，不连接服务器
+  -h, --help             显示本帮助
+对应的环境变量: SANDBOX_SERVER SANDBOX_NAME SANDBOX_ROOM SANDBOX_WINDOW_SIZE
+               SANDBOX_FULLSCREEN SANDBOX_SPECTATOR SANDBOX_ASSETS SANDBOX_RECORD";
+
+// 客户端启动参数，命令行优先于环境变量
+pub struct ClientConfig {
+    pub server: Option<String>,
+    pub name: String,
+    pub room: Option<String>,
+    pub window_size: (u32, u32),
+    pub fullscreen: bool,
+    pub spectator: bool,
+    pub assets: PathBuf,
+    pub record: Option<PathBuf>,
+    pub play: Option<PathBuf>,
+}
+
+impl ClientConfig {
+    // 返回 Ok(None) 表示只需显示帮助
+    pub fn from_args(args: &[String]) -> Result<Option<Self>, String> {
+        let env = |key: &str| std::env::var(key).ok().filter(|s| !s.trim().is_empty());
+        let env_flag = |key: &str| env(key).is_some_and(|s| matches!(s.trim(), "1" | "true" | "yes"));
+
+        let mut config = Self {
+            server: env("SANDBOX_SERVER"),
+            name: env("SANDBOX_NAME").unwrap_or_else(|| "玩家".to_string()),
+            room: env("SANDBOX_ROOM"),
+            // 例如 SANDBOX_WINDOW_SIZE=1600x1000
+            window_size: match env("SANDBOX_WINDOW_SIZE") {
+                Some(s) => parse_size(&s)?,
+                None => (1200, 800),
+            },
+            fullscreen: env_flag("SANDBOX_FULLSCREEN"),
+            // 观众模式只接收状态，例如投影仪上的展示端 SANDBOX_SPECTATOR=1
+            spectator: env_flag("SANDBOX_SPECTATOR"),
+            assets: env("SANDBOX_ASSETS").map_or_else(|| PathBuf::from("assets"), PathBuf::from),
+            record: env("SANDBOX_RECORD").map(PathBuf::from),
+            play: None,
+        };
+
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let mut value = || {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| format!("参数 {} 缺少取值\n{}", flag, USAGE))
+            };
+            match flag.as_str() {
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    return Ok(None);
+                }
+                "--server" => config.server = Some(value()?),
+                "--name" => config.name = value()?,
+                "--room" => config.room = Some(value()?),
+                "--size" => config.window_size = parse_size(&value()?)?,
+                "--fullscreen" => config.fullscreen = true,
+                "--spectator" => config.spectator = true,
+                "--assets" => config.assets = PathBuf::from(value()?),
+                "--record" => config.record = Some(PathBuf::from(value()?)),
+                "--play" => config.play = Some(PathBuf::from(value()?)),
+                _ => return Err(format!("未知参数: {}\n{}", flag, USAGE)),
+            }
+        }
+
+        // 只写主机名时使用默认端口
+        if let Some(server) = &mut config.server {
+            if !server.contains(':') {
+                server.push_str(":8080");
+            }
+        }
+        Ok(Some(config))
+    }
+}
+
+fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    let (w, h) = s
+        .trim()
+        .split_once(['x', 'X'])
+        .ok_or_else(|| format!("窗口大小格式应为 宽x高: {}", s))?;
+    match (w.trim().parse::<u32>(), h.trim().parse::<u32>()) {
+        (Ok(w), Ok(h)) if (320..=8192).contains(&w) && (240..=8192).contains(&h) => Ok((w, h)),
+        _ => Err(format!("无效的窗口大小: {}", s)),
+    }
+}
+
+// 最近服务器列表保存在用户目录下，可以用 SANDBOX_RECENT_FILE 指定其他位置
+fn recent_file() -> Option<PathBuf> {
+    if let Ok(path) = std::env::var("SANDBOX_RECENT_FILE") {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var("HOME")
+        .or_else(|_| std::env::var("USERPROFILE"))
+        .ok()
+        .map(|home| PathBuf::from(home).join(".physics_sandbox_servers.json"))
+}
+
+pub fn load_recent_servers() -> Vec<String> {
+    recent_file()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+// 把成功连接的服务器放到列表最前面
+pub fn save_recent_server(addr: &str) {
+    let Some(path) = recent_file() else {
+        return;
+    };
+    let mut servers = load_recent_servers();
+    servers.retain(|s| s != addr);
+    servers.insert(0, addr.to_string());
+    servers.truncate(MAX_RECENT_SERVERS);
+    if let Err(e) = std::fs::write(&path, serde_json::to_string_pretty(&servers).unwrap()) {
+        println!("无法保存最近服务器列表 {}: {}", path.display(), e);
+    }
+}
+
+fn prompt(text: &str) -> String {
+    print!("{}", text);
+    io::stdout().flush().unwrap();
+    let mut line = String::new();
+    io::stdin().read_line(&mut line).unwrap();
+    line.trim().to_string()
+}
+
+// 未指定服务器时在终端询问：可以输入最近服务器的编号，或者输入 IP 和端口
+pub fn prompt_server() -> String {
+    let recent = load_recent_servers();
+    if !recent.is_empty() {
+        println!("最近连接的服务器:");
+        for (i, addr) in recent.iter().enumerate() {
+            println!("  {}. {}", i + 1, addr);
+        }
+    }
+
+    let ip = prompt(if recent.is_empty() {
+        "请输入服务器IP (默认127.0.0.1): "
+    } else {
+        "请输入编号或服务器IP (默认127.0.0.1): "
+    });
+    if let Some(addr) = ip.parse::<usize>().ok().and_then(|n| recent.get(n.wrapping_sub(1))) {
+        return addr.clone();
+    }
+    let ip = if ip.is_empty() { "127.0.0.1".to_string() } else { ip };
+
+    let port = prompt("请输入端口 (默认8080): ");
+    let port = if port.is_empty() { "8080".to_string() } else { port };
+    format!("{}:{}", ip, port)
+}
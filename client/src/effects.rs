@@ -0,0 +1,75 @@
+use common::physics::{ContactEvent, ContactPhase, Vec2};
+use sdl2::pixels::Color;
+use sdl2::rect::Point;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use std::time::Instant;
+
+// 碰撞开始时在接触点画一个向外扩散的圆环，冲量越大圆环越大、越粗
+const IMPACT_DURATION: f32 = 0.4;
+// 冲量小于此值的轻碰不显示
+const MIN_IMPULSE: f32 = 20.0;
+const MAX_IMPACTS: usize = 64;
+const RING_SEGMENTS: usize = 24;
+
+struct Impact {
+    point: Vec2,
+    // 0 到 1，按冲量的对数缩放
+    strength: f32,
+    started: Instant,
+}
+
+pub struct Impacts {
+    list: Vec<Impact>,
+}
+
+impl Impacts {
+    pub fn new() -> Self {
+        Self { list: Vec::new() }
+    }
+
+    // 持续接触每个周期都有冲量，只在接触开始时显示
+    pub fn add(&mut self, events: &[ContactEvent]) {
+        let now = Instant::now();
+        for event in events {
+            let impulse = event.contact.normal_impulse;
+            if event.phase != ContactPhase::Begin || impulse < MIN_IMPULSE {
+                continue;
+            }
+            self.list.push(Impact {
+                point: event.contact.point,
+                strength: ((impulse / MIN_IMPULSE).ln() / 5.0).min(1.0),
+                started: now,
+            });
+        }
+        let excess = self.list.len().saturating_sub(MAX_IMPACTS);
+        self.list.drain(..excess);
+    }
+
+    pub fn draw(&mut self, canvas: &mut Canvas<Window>) {
+        self.list
+            .retain(|impact| impact.started.elapsed().as_secs_f32() < IMPACT_DURATION);
+        for impact in &self.list {
+            let t = impact.started.elapsed().as_secs_f32() / IMPACT_DURATION;
+            let max_radius = 10.0 + 50.0 * impact.strength;
+            let radius = max_radius * (0.3 + 0.7 * t);
+            // 没有开启混合，用逐渐变暗代替淡出
+            let fade = 1.0 - t;
+            canvas.set_draw_color(Color::RGB((255.0 * fade) as u8, (200.0 * fade) as u8, (60.0 * fade) as u8));
+            let rings = 1 + (impact.strength * 3.0) as i32;
+            for ring in 0..rings {
+                let r = radius - ring as f32;
+                let points: Vec<Point> = (0..=RING_SEGMENTS)
+                    .map(|i| {
+                        let angle = i as f32 / RING_SEGMENTS as f32 * std::f32::consts::TAU;
+                        Point::new(
+                            (impact.point.x + r * angle.cos()) as i32,
+                            (impact.point.y + r * angle.sin()) as i32,
+                        )
+                    })
+                    .collect();
+                canvas.draw_lines(points.as_slice()).ok();
+            }
+        }
+    }
+}
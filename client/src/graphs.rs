@@ -0,0 +1,166 @@
+use common::physics::Diagnostics;
+use crate::font::{draw_text, text_width};
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect};
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use std::collections::VecDeque;
+
+// 保留的样本数，60 Hz 时约 10 秒
+pub const GRAPH_SAMPLES: usize = 600;
+
+// 面板位于窗口右下角，三张图上下排列，最新的样本在右边
+const PANEL_X: i32 = 840;
+const PANEL_WIDTH: i32 = 350;
+const PANEL_BOTTOM: i32 = 790;
+const TITLE_HEIGHT: i32 = 20;
+const PLOT_HEIGHT: i32 = 80;
+const GRAPH_GAP: i32 = 8;
+const TEXT_SCALE: u32 = 2;
+
+struct Series {
+    label: &'static str,
+    color: Color,
+    value: fn(&Diagnostics) -> f32,
+}
+
+struct Graph {
+    title: &'static str,
+    series: Vec<Series>,
+}
+
+fn graphs() -> [Graph; 3] {
+    [
+        Graph {
+            title: "ENERGY",
+            series: vec![
+                Series {
+                    label: "KE",
+                    color: Color::RGB(255, 99, 71),
+                    value: |d| d.kinetic,
+                },
+                Series {
+                    label: "PE",
+                    color: Color::RGB(65, 135, 255),
+                    value: |d| d.potential,
+                },
+                Series {
+                    label: "E",
+                    color: Color::RGB(255, 255, 255),
+                    value: |d| d.total,
+                },
+            ],
+        },
+        Graph {
+            title: "MOMENTUM",
+            series: vec![
+                Series {
+                    label: "PX",
+                    color: Color::RGB(50, 205, 50),
+                    value: |d| d.momentum.x,
+                },
+                Series {
+                    label: "PY",
+                    color: Color::RGB(255, 220, 0),
+                    value: |d| d.momentum.y,
+                },
+            ],
+        },
+        Graph {
+            title: "ANGULAR",
+            series: vec![Series {
+                label: "L",
+                color: Color::RGB(186, 85, 211),
+                value: |d| d.angular_momentum,
+            }],
+        },
+    ]
+}
+
+// 绘制能量、动量和角动量的滚动曲线，每张图按当前显示的样本自动缩放
+pub fn draw(canvas: &mut Canvas<Window>, samples: &VecDeque<Diagnostics>) {
+    let graphs = graphs();
+    let graph_height = TITLE_HEIGHT + PLOT_HEIGHT + GRAPH_GAP;
+    let mut y = PANEL_BOTTOM - graph_height * graphs.len() as i32 + GRAPH_GAP;
+    for graph in &graphs {
+        draw_graph(canvas, graph, samples, y);
+        y += graph_height;
+    }
+}
+
+fn draw_graph(canvas: &mut Canvas<Window>, graph: &Graph, samples: &VecDeque<Diagnostics>, y: i32) {
+    canvas.set_draw_color(Color::RGBA(20, 20, 30, 200));
+    canvas
+        .fill_rect(Rect::new(PANEL_X, y, PANEL_WIDTH as u32, (TITLE_HEIGHT + PLOT_HEIGHT) as u32))
+        .ok();
+
+    // 标题行：名称和每条曲线的当前值，例如 "ENERGY KE 12K PE 280K E 292K"
+    let mut x = PANEL_X + 4;
+    draw_text(canvas, graph.title, x, y + 3, TEXT_SCALE, Color::RGB(200, 200, 200));
+    x += text_width(graph.title, TEXT_SCALE) + 12;
+    let Some(latest) = samples.back() else {
+        draw_text(canvas, "NO DATA", x, y + 3, TEXT_SCALE, Color::RGB(120, 120, 120));
+        return;
+    };
+    for series in &graph.series {
+        let text = format!("{} {}", series.label, compact((series.value)(latest)));
+        draw_text(canvas, &text, x, y + 3, TEXT_SCALE, series.color);
+        x += text_width(&text, TEXT_SCALE) + 12;
+    }
+
+    // 纵轴范围取所有曲线的最小和最大值，数值几乎不变时留出一点余量
+    let plot_top = y + TITLE_HEIGHT;
+    let (mut min, mut max) = (f32::INFINITY, f32::NEG_INFINITY);
+    for sample in samples {
+        for series in &graph.series {
+            let value = (series.value)(sample);
+            if value.is_finite() {
+                min = min.min(value);
+                max = max.max(value);
+            }
+        }
+    }
+    if !min.is_finite() {
+        return;
+    }
+    let margin = ((max - min) * 0.05).max(max.abs().max(min.abs()) * 1e-3).max(1e-3);
+    let (min, max) = (min - margin, max + margin);
+    let to_y = |value: f32| plot_top + PLOT_HEIGHT - ((value - min) / (max - min) * PLOT_HEIGHT as f32) as i32;
+
+    if min < 0.0 && max > 0.0 {
+        canvas.set_draw_color(Color::RGB(70, 70, 80));
+        canvas.draw_line((PANEL_X, to_y(0.0)), (PANEL_X + PANEL_WIDTH - 1, to_y(0.0))).ok();
+    }
+
+    let right = PANEL_X + PANEL_WIDTH - 1;
+    let spacing = PANEL_WIDTH as f32 / (GRAPH_SAMPLES - 1) as f32;
+    for series in &graph.series {
+        let points: Vec<Point> = samples
+            .iter()
+            .rev()
+            .enumerate()
+            .filter_map(|(i, sample)| {
+                let value = (series.value)(sample);
+                value
+                    .is_finite()
+                    .then(|| Point::new(right - (i as f32 * spacing) as i32, to_y(value)))
+            })
+            .collect();
+        canvas.set_draw_color(series.color);
+        canvas.draw_lines(points.as_slice()).ok();
+    }
+}
+
+// 点阵字体放不下很长的数字，大数值用 K / M 表示
+fn compact(value: f32) -> String {
+    let magnitude = value.abs();
+    if magnitude >= 1e6 {
+        format!("{:.1}M", value / 1e6)
+    } else if magnitude >= 1e4 {
+        format!("{:.0}K", value / 1e3)
+    } else if magnitude >= 100.0 {
+        format!("{:.0}", value)
+    } else {
+        format!("{:.1}", value)
+    }
+}
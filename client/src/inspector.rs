@@ -0,0 +1,283 @@
+use common::physics::{BodyProperties, BodyType, ClientMessage, Material, RigidBody, Shape, Vec2};
+use crate::font::{draw_text, text_width};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+// 面板位于窗口右上角
+const PANEL_X: i32 = 890;
+const PANEL_Y: i32 = 10;
+const PANEL_WIDTH: i32 = 300;
+const ROW_HEIGHT: i32 = 22;
+const TEXT_SCALE: u32 = 2;
+const BUTTON_SIZE: i32 = 18;
+
+// 可调节的属性
+#[derive(Clone, Copy)]
+enum Field {
+    BodyType,
+    Mass,
+    Width,
+    Height,
+    Radius,
+    Angle,
+    AngularVelocity,
+    VelocityX,
+    VelocityY,
+    Restitution,
+    Friction,
+}
+
+impl Field {
+    fn label(self) -> &'static str {
+        match self {
+            Field::BodyType => "TYPE",
+            Field::Mass => "MASS",
+            Field::Width => "WIDTH",
+            Field::Height => "HEIGHT",
+            Field::Radius => "RADIUS",
+            Field::Angle => "ANGLE",
+            Field::AngularVelocity => "SPIN",
+            Field::VelocityX => "VEL X",
+            Field::VelocityY => "VEL Y",
+            Field::Restitution => "BOUNCE",
+            Field::Friction => "FRICTION",
+        }
+    }
+
+    fn value(self, body: &RigidBody) -> String {
+        match self {
+            Field::BodyType => format!("{:?}", body.body_type),
+            Field::Mass => format!("{:.2}", body.mass),
+            Field::Width | Field::Height | Field::Radius => match (self, body.shape) {
+                (Field::Radius, Shape::Circle { radius }) => format!("{:.0}", radius),
+                (Field::Width, Shape::Rectangle { width, .. }) => format!("{:.0}", width),
+                (_, Shape::Rectangle { height, .. }) => format!("{:.0}", height),
+                _ => String::new(),
+            },
+            Field::Angle => format!("{:.0}", body.angle.to_degrees().rem_euclid(360.0)),
+            Field::AngularVelocity => format!("{:.1}", body.angular_velocity),
+            Field::VelocityX => format!("{:.0}", body.velocity.x),
+            Field::VelocityY => format!("{:.0}", body.velocity.y),
+            Field::Restitution => format!("{:.1}", body.material.restitution),
+            Field::Friction => format!("{:.1}", body.material.friction),
+        }
+    }
+
+    // 按一下 +/- 按钮得到的修改，sign 为 1 或 -1
+    fn adjust(self, body: &RigidBody, sign: f32) -> BodyProperties {
+        let mut properties = BodyProperties::default();
+        match self {
+            Field::BodyType => {
+                let types = [BodyType::Dynamic, BodyType::Kinematic, BodyType::Static];
+                let index = types.iter().position(|t| *t == body.body_type).unwrap_or(0) as i32;
+                let next = (index + sign as i32).rem_euclid(types.len() as i32) as usize;
+                properties.body_type = Some(types[next]);
+            }
+            Field::Mass => {
+                properties.mass = Some((body.mass * 1.25f32.powf(sign)).max(0.01));
+            }
+            Field::Width | Field::Height | Field::Radius => {
+                let resize = |v: f32| (v + 5.0 * sign).max(2.0);
+                properties.shape = Some(match body.shape {
+                    Shape::Circle { radius } => Shape::Circle { radius: resize(radius) },
+                    Shape::Rectangle { width, height } => match self {
+                        Field::Width => Shape::Rectangle { width: resize(width), height },
+                        _ => Shape::Rectangle { width, height: resize(height) },
+                    },
+                });
+            }
+            Field::Angle => properties.angle = Some(body.angle + 15f32.to_radians() * sign),
+            Field::AngularVelocity => properties.angular_velocity = Some(body.angular_velocity + 0.5 * sign),
+            Field::VelocityX => properties.velocity = Some(body.velocity + Vec2::new(50.0 * sign, 0.0)),
+            Field::VelocityY => properties.velocity = Some(body.velocity + Vec2::new(0.0, 50.0 * sign)),
+            Field::Restitution => {
+                properties.material = Some(Material {
+                    restitution: (body.material.restitution + 0.1 * sign).clamp(0.0, 1.0),
+                    ..body.material
+                });
+            }
+            Field::Friction => {
+                properties.material = Some(Material {
+                    friction: (body.material.friction + 0.1 * sign).clamp(0.0, 2.0),
+                    ..body.material
+                });
+            }
+        }
+        properties
+    }
+}
+
+fn fields(body: &RigidBody) -> Vec<Field> {
+    let mut fields = vec![Field::BodyType, Field::Mass];
+    match body.shape {
+        Shape::Circle { .. } => fields.push(Field::Radius),
+        Shape::Rectangle { .. } => fields.extend([Field::Width, Field::Height]),
+    }
+    fields.extend([
+        Field::Angle,
+        Field::AngularVelocity,
+        Field::VelocityX,
+        Field::VelocityY,
+        Field::Restitution,
+        Field::Friction,
+    ]);
+    fields
+}
+
+// 属性面板：选中物体后显示其服务器状态，点击 +/- 发送 SetBodyProperties
+pub struct Inspector {
+    selected: Option<u32>,
+}
+
+impl Inspector {
+    pub fn new() -> Self {
+        Self { selected: None }
+    }
+
+    pub fn select(&mut self, body_id: Option<u32>) {
+        self.selected = body_id;
+    }
+
+    // 选中的物体已被删除时取消选择
+    pub fn sync(&mut self, bodies: &[RigidBody]) {
+        if let Some(id) = self.selected {
+            if !bodies.is_empty() && !bodies.iter().any(|b| b.id == id) {
+                self.selected = None;
+            }
+        }
+    }
+
+    fn body<'a>(&self, bodies: &'a [RigidBody]) -> Option<&'a RigidBody> {
+        self.selected.and_then(|id| bodies.iter().find(|b| b.id == id))
+    }
+
+    fn panel_rect(&self, body: &RigidBody) -> Rect {
+        // 标题、属性行和底部的停止按钮
+        let rows = fields(body).len() as i32 + 2;
+        Rect::new(PANEL_X, PANEL_Y, PANEL_WIDTH as u32, (rows * ROW_HEIGHT + 8) as u32)
+    }
+
+    // 点是否落在面板上，落在面板上的点击不再用于拖拽物体
+    pub fn contains(&self, bodies: &[RigidBody], pos: Vec2) -> bool {
+        self.body(bodies)
+            .is_some_and(|body| self.panel_rect(body).contains_point((pos.x as i32, pos.y as i32)))
+    }
+
+    // 处理面板上的点击，返回要发送的修改
+    pub fn click(&self, bodies: &[RigidBody], pos: Vec2) -> Option<ClientMessage> {
+        let body = self.body(bodies)?;
+        let point = (pos.x as i32, pos.y as i32);
+        let mut properties = None;
+        for (row, field) in fields(body).into_iter().enumerate() {
+            let (minus, plus) = buttons(row_y(row + 1));
+            if minus.contains_point(point) {
+                properties = Some(field.adjust(body, -1.0));
+            } else if plus.contains_point(point) {
+                properties = Some(field.adjust(body, 1.0));
+            }
+        }
+        if stop_button(row_y(fields(body).len() + 1)).contains_point(point) {
+            properties = Some(BodyProperties {
+                velocity: Some(Vec2::zero()),
+                angular_velocity: Some(0.0),
+                ..Default::default()
+            });
+        }
+        properties.map(|properties| ClientMessage::SetBodyProperties {
+            body_id: body.id,
+            properties,
+        })
+    }
+
+    // 把选中的物体移动到指定位置
+    pub fn move_to(&self, pos: Vec2) -> Option<ClientMessage> {
+        self.selected.map(|body_id| ClientMessage::SetBodyProperties {
+            body_id,
+            properties: BodyProperties {
+                position: Some(pos),
+                velocity: Some(Vec2::zero()),
+                ..Default::default()
+            },
+        })
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas<Window>, bodies: &[RigidBody]) {
+        let Some(body) = self.body(bodies) else {
+            return;
+        };
+
+        // 选中框
+        let (half_w, half_h) = body_half_extent(body);
+        canvas.set_draw_color(Color::RGB(0, 255, 255));
+        canvas
+            .draw_rect(Rect::new(
+                (body.position.x - half_w) as i32 - 4,
+                (body.position.y - half_h) as i32 - 4,
+                (half_w * 2.0) as u32 + 8,
+                (half_h * 2.0) as u32 + 8,
+            ))
+            .ok();
+
+        canvas.set_draw_color(Color::RGBA(20, 20, 30, 220));
+        canvas.fill_rect(self.panel_rect(body)).ok();
+        canvas.set_draw_color(Color::RGB(0, 255, 255));
+        canvas.draw_rect(self.panel_rect(body)).ok();
+
+        let white = Color::RGB(230, 230, 230);
+        let title = match body.owner {
+            Some(owner) => format!("BODY #{}  OWNER {}", body.id, owner),
+            None => format!("BODY #{}", body.id),
+        };
+        draw_text(canvas, &title, PANEL_X + 8, row_y(0), TEXT_SCALE, Color::RGB(0, 255, 255));
+
+        let fields = fields(body);
+        for (row, field) in fields.iter().enumerate() {
+            let y = row_y(row + 1);
+            draw_text(canvas, field.label(), PANEL_X + 8, y, TEXT_SCALE, white);
+            draw_text(canvas, &field.value(body), PANEL_X + 110, y, TEXT_SCALE, white);
+            let (minus, plus) = buttons(y);
+            draw_button(canvas, minus, "-");
+            draw_button(canvas, plus, "+");
+        }
+        draw_button(canvas, stop_button(row_y(fields.len() + 1)), "STOP");
+    }
+}
+
+fn row_y(row: usize) -> i32 {
+    PANEL_Y + 6 + row as i32 * ROW_HEIGHT
+}
+
+fn buttons(y: i32) -> (Rect, Rect) {
+    let size = BUTTON_SIZE as u32;
+    let right = PANEL_X + PANEL_WIDTH - 8;
+    (
+        Rect::new(right - 2 * BUTTON_SIZE - 6, y - 2, size, size),
+        Rect::new(right - BUTTON_SIZE, y - 2, size, size),
+    )
+}
+
+fn stop_button(y: i32) -> Rect {
+    Rect::new(PANEL_X + 8, y - 2, 60, BUTTON_SIZE as u32)
+}
+
+fn draw_button(canvas: &mut Canvas<Window>, rect: Rect, text: &str) {
+    canvas.set_draw_color(Color::RGB(60, 60, 80));
+    canvas.fill_rect(rect).ok();
+    canvas.set_draw_color(Color::RGB(150, 150, 170));
+    canvas.draw_rect(rect).ok();
+    let x = rect.x() + (rect.width() as i32 - text_width(text, TEXT_SCALE)) / 2 + 1;
+    draw_text(canvas, text, x, rect.y() + 2, TEXT_SCALE, Color::RGB(255, 255, 255));
+}
+
+fn body_half_extent(body: &RigidBody) -> (f32, f32) {
+    match body.shape {
+        Shape::Circle { radius } => (radius, radius),
+        // 旋转后的矩形用外接圆半径框住
+        Shape::Rectangle { width, height } => {
+            let r = (width * width + height * height).sqrt() / 2.0;
+            (r, r)
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use common::physics::{RigidBody, WorldState};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// 最多缓存的快照数量（60Hz 下约 1 秒）
+const MAX_SNAPSHOTS: usize = 64;
+
+// 客户端快照缓冲：按服务器时间戳排队，渲染时在稍早的时间点上插值两帧之间的状态
+pub struct SnapshotBuffer {
+    snapshots: VecDeque<WorldState>,
+    delay: f64,
+    max_extrapolation: f64,
+    epoch: Instant,
+    // 服务器时间 - 本地时间 的估计值
+    clock_offset: Option<f64>,
+}
+
+impl SnapshotBuffer {
+    pub fn new(delay: Duration, max_extrapolation: Duration) -> Self {
+        Self {
+            snapshots: VecDeque::new(),
+            delay: delay.as_secs_f64(),
+            max_extrapolation: max_extrapolation.as_secs_f64(),
+            epoch: Instant::now(),
+            clock_offset: None,
+        }
+    }
+
+    // 返回快照是否被接受
+    pub fn push(&mut self, state: WorldState) -> bool {
+        if let Some(last) = self.snapshots.back() {
+            // 回退之前发出、迟到的快照属于被放弃的时间线
+            if state.rewinds < last.rewinds {
+                return false;
+            }
+            // 世界被回退后不能在两条时间线之间插值
+            if state.rewinds > last.rewinds {
+                self.snapshots.clear();
+            } else if state.tick <= last.tick && state.server_time <= last.server_time {
+                // 丢弃乱序或重复的快照
+                return false;
+            }
+        }
+
+        // 取到达最快的样本作为时钟偏移，并缓慢回落以跟随时钟漂移
+        let sample = state.server_time - self.epoch.elapsed().as_secs_f64();
+        self.clock_offset = Some(match self.clock_offset {
+            Some(offset) if sample <= offset => offset * 0.99 + sample * 0.01,
+            _ => sample,
+        });
+
+        self.snapshots.push_back(state);
+        while self.snapshots.len() > MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+        true
+    }
+
+    // 切换房间后旧世界的快照不再有效
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+
+    // 最新收到的快照，用于读取暂停状态和时间倍率
+    pub fn latest(&self) -> Option<&WorldState> {
+        self.snapshots.back()
+    }
+
+    // 按本地时钟估计的服务器当前时间
+    pub fn server_now(&self) -> Option<f64> {
+        self.clock_offset
+            .map(|offset| self.epoch.elapsed().as_secs_f64() + offset)
+    }
+
+    // 渲染时刻 = 服务器当前时间 - 插值延迟
+    pub fn render_time(&self) -> Option<f64> {
+        self.server_now().map(|now| now - self.delay)
+    }
+
+    // 计算当前渲染时刻的物体状态
+    pub fn sample(&self) -> Vec<RigidBody> {
+        let (Some(render_time), Some(newest)) = (self.render_time(), self.snapshots.back()) else {
+            return Vec::new();
+        };
+
+        // 渲染时刻晚于最新快照：用速度做有限时长的外推
+        if render_time >= newest.server_time {
+            let dt = (render_time - newest.server_time).min(self.max_extrapolation) as f32;
+            return newest.bodies.iter().map(|body| extrapolate(body, dt)).collect();
+        }
+
+        let Some(index) = self.snapshots.iter().position(|s| s.server_time > render_time) else {
+            return newest.bodies.clone();
+        };
+        if index == 0 {
+            return self.snapshots[0].bodies.clone();
+        }
+        let from = &self.snapshots[index - 1];
+        let to = &self.snapshots[index];
+        let span = to.server_time - from.server_time;
+        let t = if span > 0.0 {
+            ((render_time - from.server_time) / span) as f32
+        } else {
+            1.0
+        };
+
+        to.bodies
+            .iter()
+            .map(|body| match from.bodies.iter().find(|b| b.id == body.id) {
+                Some(previous) => lerp_body(previous, body, t),
+                None => body.clone(),
+            })
+            .collect()
+    }
+}
+
+fn lerp_body(from: &RigidBody, to: &RigidBody, t: f32) -> RigidBody {
+    let mut body = to.clone();
+    body.position = from.position + (to.position - from.position) * t;
+    body.velocity = from.velocity + (to.velocity - from.velocity) * t;
+    body.angle = from.angle + (to.angle - from.angle) * t;
+    body.angular_velocity = from.angular_velocity + (to.angular_velocity - from.angular_velocity) * t;
+    body
+}
+
+fn extrapolate(body: &RigidBody, dt: f32) -> RigidBody {
+    let mut body = body.clone();
+    body.position = body.position + body.velocity * dt;
+    body.angle += body.angular_velocity * dt;
+    body
+}
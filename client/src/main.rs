@@ -1,8 +1,30 @@
-mod physics;
-
-use physics::{ClientMessage, RigidBody, Vec2, WorldState, Shape};
+mod chat;
+mod config;
+mod effects;
+mod font;
+mod graphs;
+mod inspector;
+mod interpolation;
+mod network;
+mod playback;
+mod prediction;
+mod presence;
+mod recording;
+mod transport;
+
+use chat::ChatInput;
+use common::physics::{self, ClientMessage, ForceField, Joint, RigidBody, Role, Vec2, Shape, Topic};
+use common::transport::TransportKind;
+use config::ClientConfig;
+use effects::Impacts;
+use font::draw_text;
+use inspector::Inspector;
+use interpolation::SnapshotBuffer;
+use network::{send_message, send_unreliable, ConnectOptions, NetStatus, Writer};
+use prediction::Predictor;
+use recording::StateRecorder;
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::mouse::MouseButton;
 use sdl2::pixels::Color;
 use sdl2::image::LoadTexture;
@@ -10,34 +32,96 @@ use std::sync::{Arc, Mutex};
 use std::collections::HashMap;
 use std::thread;
 use std::time::{Duration, Instant};
-use std::net::TcpStream;
-use std::io::{BufReader, BufRead, Write};
+
+// 光标上报间隔，以及光标不动时的重发间隔
+const PRESENCE_INTERVAL: Duration = Duration::from_millis(100);
+const PRESENCE_REFRESH: Duration = Duration::from_secs(1);
+// 世界大小，取自与服务器共用的物理模块
+const WORLD_WIDTH: u32 = physics::WORLD_WIDTH as u32;
+const WORLD_HEIGHT: u32 = physics::WORLD_HEIGHT as u32;
+// 每个物体保留的拖尾点数
+const TRAIL_LEN: usize = 30;
+// 按 [ 回退的秒数
+const REWIND_SECS: f32 = 2.0;
+
+// 拖尾轨迹：物体id -> 轨迹点
+type Trails = HashMap<u32, Vec<Vec2>>;
 
 fn main() {
-    use std::io::{self, Write};
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config = match ClientConfig::from_args(&args) {
+        Ok(Some(config)) => config,
+        Ok(None) => return,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
     println!("启动物理客户端...");
 
-    print!("请输入服务器IP (默认127.0.0.1): ");
-    io::stdout().flush().unwrap();
-    let mut ip = String::new();
-    io::stdin().read_line(&mut ip).unwrap();
-    let ip = ip.trim();
-    let ip = if ip.is_empty() { "127.0.0.1" } else { ip };
-
-    print!("请输入端口 (默认8080): ");
-    io::stdout().flush().unwrap();
-    let mut port = String::new();
-    io::stdin().read_line(&mut port).unwrap();
-    let port = port.trim();
-    let port = if port.is_empty() { "8080" } else { port };
-
-    let addr = format!("{}:{}", ip, port);
-    println!("连接到服务器: {}", addr);
-
-    let stream = match TcpStream::connect(&addr) {
-        Ok(s) => {
+    // --play：回看录像，不连接服务器
+    if let Some(path) = &config.play {
+        match recording::load(path) {
+            Ok(frames) => {
+                println!("录像共 {} 帧", frames.len());
+                playback::run(&config, frames);
+            }
+            Err(e) => {
+                println!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // 命令行或环境变量都没有指定服务器时才在终端询问
+    let interactive = config.server.is_none();
+    let addr = config.server.clone().unwrap_or_else(config::prompt_server);
+
+    // 传输协议与模拟丢包率通过环境变量选择，例如 SANDBOX_TRANSPORT=udp SANDBOX_PACKET_LOSS=0.2
+    let transport_kind = std::env::var("SANDBOX_TRANSPORT")
+        .ok()
+        .and_then(|s| TransportKind::parse(&s))
+        .unwrap_or(TransportKind::Tcp);
+    let packet_loss = std::env::var("SANDBOX_PACKET_LOSS")
+        .ok()
+        .and_then(|s| s.trim().parse::<f32>().ok())
+        .unwrap_or(0.0);
+    println!("连接到服务器: {} ({:?})", addr, transport_kind);
+
+    // 心跳间隔与服务器超时，例如 SANDBOX_HEARTBEAT_MS=1000 SANDBOX_SERVER_TIMEOUT_SECS=5
+    let heartbeat_interval = std::env::var("SANDBOX_HEARTBEAT_MS")
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(Duration::from_secs(1));
+    let server_timeout = std::env::var("SANDBOX_SERVER_TIMEOUT_SECS")
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(Duration::from_secs(5));
+    // 教师口令，与服务器的 SANDBOX_TEACHER_TOKEN 一致时获得教师权限
+    let token = std::env::var("SANDBOX_TOKEN").ok().filter(|s| !s.trim().is_empty());
+    // 光标颜色，例如 SANDBOX_COLOR=255,128,0，未设置时由服务器分配
+    let color = std::env::var("SANDBOX_COLOR").ok().and_then(|s| {
+        let parts: Vec<u8> = s.split(',').filter_map(|p| p.trim().parse().ok()).collect();
+        <[u8; 3]>::try_from(parts).ok()
+    });
+
+    let options = ConnectOptions {
+        addr,
+        transport: transport_kind,
+        packet_loss,
+        name: config.name.clone(),
+        token,
+        spectator: config.spectator,
+        color,
+    };
+
+    let mut connection = match network::connect(&options) {
+        Ok(c) => {
             println!("连接服务器成功");
-            s
+            c
         }
         Err(e) => {
             println!("连接服务器失败: {}", e);
@@ -45,78 +129,90 @@ fn main() {
         }
     };
 
-    let world_state = Arc::new(Mutex::new(WorldState { bodies: Vec::new() }));
-    let writer = Arc::new(Mutex::new(stream.try_clone().unwrap()));
+    config::save_recent_server(&options.addr);
+
+    // 非交互启动且未指定房间时留在大厅
+    let mut pending = Vec::new();
+    if interactive || config.room.is_some() {
+        match network::choose_room(&mut connection, config.room.as_deref()) {
+            Ok(received) => pending = received,
+            Err(e) => {
+                println!("选择房间失败: {}", e);
+                return;
+            }
+        }
+    }
+
+    // 插值延迟与最大外推时长（毫秒），例如 SANDBOX_INTERP_DELAY_MS=100 SANDBOX_EXTRAPOLATE_MS=250
+    let interp_delay = std::env::var("SANDBOX_INTERP_DELAY_MS")
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .unwrap_or(100);
+    let max_extrapolation = std::env::var("SANDBOX_EXTRAPOLATE_MS")
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .unwrap_or(250);
+
+    let world_state = Arc::new(Mutex::new(SnapshotBuffer::new(
+        Duration::from_millis(interp_delay),
+        Duration::from_millis(max_extrapolation),
+    )));
+    let writer: Writer = Arc::new(Mutex::new(None));
+    let status = Arc::new(Mutex::new(NetStatus::new()));
+
+    {
+        let writer = writer.clone();
+        let status = status.clone();
+        thread::spawn(move || {
+            network::heartbeat_loop(heartbeat_interval, server_timeout, writer, status);
+        });
+    }
+
+    let recorder = config.record.as_ref().and_then(|path| match StateRecorder::create(path) {
+        Ok(recorder) => {
+            println!("快照录像写入 {}", path.display());
+            Some(recorder)
+        }
+        Err(e) => {
+            println!("无法创建录像文件 {}: {}", path.display(), e);
+            None
+        }
+    });
 
     let network_world = world_state.clone();
+    let network_writer = writer.clone();
+    let network_status = status.clone();
     thread::spawn(move || {
-        network_loop(stream, network_world);
+        network::network_loop(
+            options,
+            connection,
+            pending,
+            network_world,
+            network_writer,
+            network_status,
+            recorder,
+        );
     });
 
-    render_loop(world_state, writer);
-}
-
-fn network_loop(stream: TcpStream, world_state: Arc<Mutex<WorldState>>) {
-    let mut reader = BufReader::new(stream);
-    let mut line = String::new();
-
-    loop {
-        line.clear();
-        match reader.read_line(&mut line) {
-            Ok(0) => {
-                println!("服务器断开连接");
-                break;
-            }
-            Ok(_) => {
-                if !line.trim().is_empty() {
-                    match serde_json::from_str::<WorldState>(&line.trim()) {
-                        Ok(state) => {
-                            let mut ws = world_state.lock().unwrap();
-                            *ws = state;
-                            println!("收到新世界状态，物体数量: {}", ws.bodies.len());
-                            for b in &ws.bodies {
-                                println!("ID: {}, 位置: {:?}, 形状: {:?}", b.id, b.position, b.shape);
-                            }
-                        }
-                        Err(e) => {
-                            println!("收到无法解析的世界状态: {}", line.trim());
-                            println!("解析错误: {:?}", e);
-                        }
-                    }
-                }
-            }
-            Err(_) => {
-                println!("网络读取错误");
-                break;
-            }
-        }
-    }
+    render_loop(&config, world_state, writer, status);
 }
 
 fn render_loop(
-    world_state: Arc<Mutex<WorldState>>,
-    writer: Arc<Mutex<TcpStream>>,
+    config: &ClientConfig,
+    world_state: Arc<Mutex<SnapshotBuffer>>,
+    writer: Writer,
+    status: Arc<Mutex<NetStatus>>,
 ) {
     let sdl_context = sdl2::init().unwrap();
     let _image_context = sdl2::image::init(sdl2::image::InitFlag::PNG | sdl2::image::InitFlag::JPG).unwrap();
     let video_subsystem = sdl_context.video().unwrap();
-    let window = video_subsystem
-        .window("简单物理沙盒 - 按R添加矩形", 1200, 800)
-        .position_centered()
-        .resizable()
-        .build()
-        .unwrap();
-
-    let mut canvas = window.into_canvas()
-        .accelerated()
-        .present_vsync()
-        .build()
-        .unwrap();
+    let mut canvas = open_canvas(&video_subsystem, config, "简单物理沙盒 - 按R添加矩形");
 
     let texture_creator = canvas.texture_creator();
+    let background_path = config.assets.join("background.png");
     let background_texture = texture_creator
-        .load_texture("assets/background.png")
-        .expect("无法加载背景图片");
+        .load_texture(&background_path)
+        .unwrap_or_else(|e| panic!("无法加载背景图片 {}: {}", background_path.display(), e));
 
     let mut event_pump = sdl_context.event_pump().unwrap();
 
@@ -125,16 +221,62 @@ fn render_loop(
     let mut drag_start = Vec2::zero();
     let mut add_rectangle_requested = false;
     let mut add_circle_requested = false;
+    let mut delete_requested = false;
+    // 右键拖出的删除区域起点
+    let mut select_start: Option<Vec2> = None;
 
     let target_fps = 60;
     let frame_duration = Duration::from_nanos(1_000_000_000 / target_fps);
 
-    // 拖尾轨迹：物体id -> 轨迹点
-    let mut trails: HashMap<u32, Vec<Vec2>> = HashMap::new();
+    // 窗口标题显示房间、往返时延和连接状态
+    let mut window_title = String::new();
+
+    let mut trails = Trails::new();
+    // 上一帧插值得到的物体，用于点击检测，保证点到的就是画出来的
+    let mut bodies: Vec<RigidBody> = Vec::new();
+    // 本地预测刚施加冲量的物体，等待服务器确认
+    let mut predictor = Predictor::new();
+    // 选中物体的属性面板
+    let mut inspector = Inspector::new();
+    let mut move_requested = false;
+    // 在线用户列表开关，以及上次上报的光标状态
+    let mut show_users = false;
+    // 能量和动量曲线开关，打开时才向服务器订阅
+    let mut show_graphs = false;
+    let mut impacts = Impacts::new();
+    let mut last_presence: Option<(Vec2, Option<Vec2>)> = None;
+    let mut last_presence_sent = Instant::now();
+    // 聊天输入，输入期间键盘只用于打字
+    let mut chat_input = ChatInput::new();
+    let text_input = video_subsystem.text_input();
+    text_input.stop();
+    // 服务器回退世界的次数，变化时丢弃旧时间线上的轨迹和预测
+    let mut last_rewinds = 0;
 
     'running: loop {
         let frame_start = Instant::now();
 
+        let (spectator, tick_rate, rtt_ms) = {
+            let status = status.lock().unwrap();
+            (status.spectator, status.tick_rate, status.rtt_ms)
+        };
+
+        // 服务器当前的模拟状态
+        let (paused, time_scale, tick) = match world_state.lock().unwrap().latest() {
+            Some(state) => {
+                if state.rewinds != last_rewinds {
+                    last_rewinds = state.rewinds;
+                    trails.clear();
+                    predictor = Predictor::new();
+                }
+                predictor.set_world(state);
+                predictor.set_tick_rate(tick_rate);
+                predictor.set_rtt(rtt_ms);
+                (state.paused, state.time_scale, state.tick)
+            }
+            None => (false, 1.0, 0),
+        };
+
         // 先收集所有事件，避免在事件循环中访问 event_pump 的其他方法
         let mut events = Vec::new();
         for event in event_pump.poll_iter() {
@@ -143,12 +285,50 @@ fn render_loop(
 
         // 处理收集的事件
         for event in events {
+            if chat_input.active {
+                match event {
+                    Event::Quit { .. } => break 'running,
+                    Event::TextInput { text, .. } => chat_input.push(&text),
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Backspace),
+                        ..
+                    } => chat_input.backspace(),
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Escape),
+                        ..
+                    } => {
+                        chat_input.cancel();
+                        text_input.stop();
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Return | Keycode::KpEnter),
+                        ..
+                    } => {
+                        text_input.stop();
+                        let cursor = mouse_position(&event_pump, &canvas);
+                        let labels = world_state.lock().unwrap().latest().map(|s| s.labels.clone()).unwrap_or_default();
+                        if let Some(msg) = chat_input.submit(cursor, &bodies, &labels) {
+                            send_message(&writer, &msg);
+                        }
+                    }
+                    _ => {}
+                }
+                continue;
+            }
             match event {
                 Event::Quit { .. }
                 | Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => break 'running,
+                // Enter 打开聊天输入，"/label 文字" 添加标注，"/unlabel" 删除最近的标注
+                Event::KeyDown {
+                    keycode: Some(Keycode::Return | Keycode::KpEnter),
+                    ..
+                } => {
+                    chat_input.open();
+                    text_input.start();
+                }
                 Event::KeyDown {
                     keycode: Some(Keycode::R),
                     ..
@@ -161,6 +341,97 @@ fn render_loop(
                 } => {
                     add_circle_requested = true;
                 }
+                Event::KeyDown {
+                    keycode: Some(Keycode::L),
+                    ..
+                } => {
+                    send_message(&writer, &ClientMessage::ListRooms);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::U),
+                    ..
+                } => {
+                    send_message(&writer, &ClientMessage::ListUsers);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } => {
+                    show_users = !show_users;
+                }
+                // G 显示/隐藏能量和动量曲线
+                Event::KeyDown {
+                    keycode: Some(Keycode::G),
+                    ..
+                } => {
+                    show_graphs = !show_graphs;
+                    {
+                        let mut status = status.lock().unwrap();
+                        status.subscriptions.retain(|t| *t != Topic::Diagnostics);
+                        if show_graphs {
+                            status.subscriptions.push(Topic::Diagnostics);
+                        } else {
+                            status.diagnostics.clear();
+                        }
+                    }
+                    let msg = ClientMessage::Subscribe {
+                        topic: Topic::Diagnostics,
+                        enabled: show_graphs,
+                    };
+                    send_message(&writer, &msg);
+                }
+                // 模拟控制（仅教师）：P 暂停/继续，. 单步（Shift 为 10 步），- / = 减慢或加快
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    ..
+                } => {
+                    send_message(&writer, &ClientMessage::SetPaused { paused: !paused });
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Period),
+                    keymod,
+                    ..
+                } => {
+                    let steps = if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) { 10 } else { 1 };
+                    send_message(&writer, &ClientMessage::StepSimulation { steps });
+                }
+                Event::KeyDown {
+                    keycode: Some(key @ (Keycode::Minus | Keycode::Equals)),
+                    ..
+                } => {
+                    let factor = if key == Keycode::Minus { 0.5 } else { 2.0 };
+                    let time_scale = (time_scale * factor).clamp(0.0625, 8.0);
+                    send_message(&writer, &ClientMessage::SetTimeScale { time_scale });
+                }
+                // [ 把世界回退两秒（仅教师）
+                Event::KeyDown {
+                    keycode: Some(Keycode::LeftBracket),
+                    ..
+                } => {
+                    let tick = tick.saturating_sub((REWIND_SECS * tick_rate).round() as u64);
+                    send_message(&writer, &ClientMessage::Rewind { tick });
+                }
+                // M 把选中的物体移到鼠标处
+                Event::KeyDown {
+                    keycode: Some(Keycode::M),
+                    ..
+                } => {
+                    move_requested = true;
+                }
+                // Delete 删除鼠标下的物体，Shift+Delete 清除自己的物体，Ctrl+Delete 重置世界
+                Event::KeyDown {
+                    keycode: Some(Keycode::Delete | Keycode::Backspace),
+                    keymod,
+                    ..
+                } => {
+                    if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) {
+                        send_message(&writer, &ClientMessage::ResetWorld);
+                    } else if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        send_message(&writer, &ClientMessage::RemoveOwnedBodies);
+                    } else {
+                        delete_requested = true;
+                    }
+                }
                 Event::MouseButtonDown {
                     mouse_btn: MouseButton::Left,
                     x,
@@ -168,48 +439,67 @@ fn render_loop(
                     ..
                 } => {
                     let mouse_pos = Vec2::new(x as f32, y as f32);
-                    let ws = world_state.lock().unwrap();
-                    for body in &ws.bodies {
-                        let delta = mouse_pos - body.position;
-                        let is_clicked = match body.shape {
-                            Shape::Circle { radius } => delta.length() <= radius,
-                            Shape::Rectangle { width, height } => {
-                                // 简化的矩形点击检测（不考虑旋转）
-                                delta.x.abs() <= width / 2.0 && delta.y.abs() <= height / 2.0
-                            }
-                        };
-                        if is_clicked {
-                            dragging = true;
-                            drag_body = Some(body.id);
-                            drag_start = mouse_pos;
-                            break;
+                    if inspector.contains(&bodies, mouse_pos) {
+                        if let Some(msg) = inspector.click(&bodies, mouse_pos) {
+                            send_message(&writer, &msg);
                         }
+                        continue;
+                    }
+                    // 点击物体即选中，同时可以拖拽施加冲量；点击空白处取消选择
+                    let clicked = body_at(&bodies, mouse_pos);
+                    inspector.select(clicked);
+                    // 观众不能推动物体，也就不做本地预测
+                    if spectator {
+                        continue;
+                    }
+                    if let Some(body_id) = clicked {
+                        dragging = true;
+                        drag_body = Some(body_id);
+                        drag_start = mouse_pos;
                     }
                 }
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Right,
+                    x,
+                    y,
+                    ..
+                } => {
+                    select_start = Some(Vec2::new(x as f32, y as f32));
+                }
                 Event::MouseButtonUp {
-                    mouse_btn: MouseButton::Left,
+                    mouse_btn: MouseButton::Right,
                     x,
                     y,
                     ..
                 } => {
-                    if dragging {
-                        if let Some(body_id) = drag_body {
-                            let mouse_pos = Vec2::new(x as f32, y as f32);
-                            let impulse = (mouse_pos - drag_start) * 5.0;
-                            let msg = ClientMessage::ApplyImpulse {
-                                body_id,
-                                impulse,
-                            };
-                            let json = serde_json::to_string(&msg).unwrap();
-                            let msg_str = format!("{}\n", json);
-                            if let Ok(mut w) = writer.lock() {
-                                let _ = w.write_all(msg_str.as_bytes());
-                                let _ = w.flush();
-                            }
+                    if let Some(start) = select_start.take() {
+                        let msg = ClientMessage::RemoveBodiesInRect {
+                            min: start,
+                            max: Vec2::new(x as f32, y as f32),
+                        };
+                        send_message(&writer, &msg);
+                    }
+                }
+                Event::MouseButtonUp {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } if dragging => {
+                    if let Some(body_id) = drag_body {
+                        let mouse_pos = Vec2::new(x as f32, y as f32);
+                        let impulse = (mouse_pos - drag_start) * 5.0;
+                        let msg = ClientMessage::ApplyImpulse {
+                            body_id,
+                            impulse,
+                        };
+                        send_message(&writer, &msg);
+                        if let Some(server_now) = world_state.lock().unwrap().server_now() {
+                            predictor.apply_impulse(&bodies, body_id, impulse, server_now);
                         }
-                        dragging = false;
-                        drag_body = None;
                     }
+                    dragging = false;
+                    drag_body = None;
                 }
                 _ => {}
             }
@@ -217,80 +507,85 @@ fn render_loop(
 
         // 处理添加矩形的请求（在事件循环外获取鼠标状态）
         if add_rectangle_requested {
-            let mouse_state = event_pump.mouse_state();
-            let mouse_pos = Vec2::new(mouse_state.x() as f32, mouse_state.y() as f32);
+            let mouse_pos = mouse_position(&event_pump, &canvas);
             let msg = ClientMessage::AddRectangle {
                 position: mouse_pos,
                 width: 60.0,
                 height: 40.0,
                 mass: 1.0,
             };
-            let json = serde_json::to_string(&msg).unwrap();
-            let msg_str = format!("{}\n", json);
-            println!("发送添加矩形请求: {}", msg_str);
-            if let Ok(mut w) = writer.lock() {
-                let _ = w.write_all(msg_str.as_bytes());
-                let _ = w.flush();
-            }
+            println!("发送添加矩形请求: {:?}", msg);
+            send_message(&writer, &msg);
             add_rectangle_requested = false;
         }
         if add_circle_requested {
-            let mouse_state = event_pump.mouse_state();
-            let mouse_pos = Vec2::new(mouse_state.x() as f32, mouse_state.y() as f32);
+            let mouse_pos = mouse_position(&event_pump, &canvas);
             let msg = ClientMessage::AddCircle {
                 position: mouse_pos,
                 radius: 30.0,
                 mass: 1.0,
             };
-            let json = serde_json::to_string(&msg).unwrap();
-            let msg_str = format!("{}\n", json);
-            println!("发送添加圆请求: {}", msg_str);
-            if let Ok(mut w) = writer.lock() {
-                let _ = w.write_all(msg_str.as_bytes());
-                let _ = w.flush();
-            }
+            println!("发送添加圆请求: {:?}", msg);
+            send_message(&writer, &msg);
             add_circle_requested = false;
         }
 
+        if delete_requested {
+            let mouse_pos = mouse_position(&event_pump, &canvas);
+            if let Some(body_id) = body_at(&bodies, mouse_pos) {
+                send_message(&writer, &ClientMessage::RemoveBody { body_id });
+            }
+            delete_requested = false;
+        }
+        if move_requested {
+            let mouse_pos = mouse_position(&event_pump, &canvas);
+            if let Some(msg) = inspector.move_to(mouse_pos) {
+                send_message(&writer, &msg);
+            }
+            move_requested = false;
+        }
+
         // 绘制背景贴图
         canvas.copy(&background_texture, None, None).unwrap();
 
-        let bodies = {
-            let ws = world_state.lock().unwrap();
-            ws.bodies.clone()
+        let render_time = {
+            let buffer = world_state.lock().unwrap();
+            bodies = buffer.sample();
+            buffer.render_time()
         };
+        predictor.apply(&mut bodies, render_time, if paused { 0.0 } else { time_scale });
+        inspector.sync(&bodies);
 
-        // 更新轨迹点
+        // 更新轨迹点，已删除物体的轨迹一并丢弃
+        trails.retain(|id, _| bodies.iter().any(|b| b.id == *id));
         for body in &bodies {
-            let trail = trails.entry(body.id).or_insert(Vec::new());
+            let trail = trails.entry(body.id).or_default();
             trail.push(body.position);
-            if trail.len() > 30 {
+            if trail.len() > TRAIL_LEN {
                 trail.remove(0);
             }
         }
 
-        // 绘制拖尾
-        for body in &bodies {
-            if let Some(trail) = trails.get(&body.id) {
-                for w in trail.windows(2) {
-                    let p1 = w[0];
-                    let p2 = w[1];
-                    // 越靠后的点越透明
-                    let alpha = ((trail.len() as f32 - w[0..1].len() as f32) / trail.len() as f32 * 180.0 + 40.0) as u8;
-                    canvas.set_draw_color(sdl2::pixels::Color::RGBA(255, 255, 255, alpha));
-                    canvas.draw_line((p1.x as i32, p1.y as i32), (p2.x as i32, p2.y as i32)).ok();
-                }
-            }
+        draw_trails(&mut canvas, &trails, &bodies);
+        if let Some(state) = world_state.lock().unwrap().latest() {
+            draw_environment(&mut canvas, &state.force_fields, &state.joints, &bodies);
         }
 
         for body in &bodies {
             draw_body(&mut canvas, body);
         }
 
+        let contacts = std::mem::take(&mut status.lock().unwrap().contacts);
+        impacts.add(&contacts);
+        impacts.draw(&mut canvas);
+
+        if let Some(state) = world_state.lock().unwrap().latest() {
+            chat::draw_labels(&mut canvas, &state.labels, &bodies);
+        }
+
         // 绘制拖拽线（在事件循环外获取鼠标状态）
         if dragging {
-            let mouse_state = event_pump.mouse_state();
-            let mouse_pos = Vec2::new(mouse_state.x() as f32, mouse_state.y() as f32);
+            let mouse_pos = mouse_position(&event_pump, &canvas);
             
             canvas.set_draw_color(Color::RGB(255, 255, 100));
             canvas.draw_line(
@@ -299,6 +594,88 @@ fn render_loop(
             ).unwrap();
         }
 
+        // 低频上报自己的光标和拖拽：变化时发送，不变时也定期重发以防丢包
+        if !spectator && last_presence_sent.elapsed() >= PRESENCE_INTERVAL {
+            let cursor = mouse_position(&event_pump, &canvas);
+            let presence = (cursor, dragging.then_some(drag_start));
+            let changed = last_presence.is_none_or(|(c, d)| {
+                (c - cursor).length() > 0.5 || d.is_some() != presence.1.is_some()
+            });
+            if changed || last_presence_sent.elapsed() >= PRESENCE_REFRESH {
+                let msg = ClientMessage::UpdatePresence {
+                    cursor,
+                    drag_start: presence.1,
+                };
+                send_unreliable(&writer, &msg);
+                last_presence = Some(presence);
+                last_presence_sent = Instant::now();
+            }
+        }
+
+        {
+            let status = status.lock().unwrap();
+            presence::draw_cursors(&mut canvas, &status.presence, &status.users, status.client_id);
+            if show_users {
+                presence::draw_user_list(&mut canvas, &status.users, status.client_id);
+            }
+            let height = WORLD_HEIGHT as i32;
+            chat::draw_log(&mut canvas, &status.chat, &status.users, height);
+            chat_input.draw(&mut canvas, height);
+            if show_graphs {
+                graphs::draw(&mut canvas, &status.diagnostics);
+            }
+        }
+
+        inspector.draw(&mut canvas, &bodies);
+
+        // 暂停或变速时在左上角提示，例如 "PAUSED ×0.25"
+        if paused || time_scale != 1.0 {
+            let mut indicator = if paused { "PAUSED".to_string() } else { String::new() };
+            if time_scale != 1.0 {
+                indicator = format!("{} ×{}", indicator, time_scale).trim().to_string();
+            }
+            draw_text(&mut canvas, &indicator, 12, 12, 3, Color::RGB(255, 220, 0));
+        }
+
+        // 绘制右键删除区域
+        if let Some(start) = select_start {
+            let mouse_pos = mouse_position(&event_pump, &canvas);
+            let (x, y) = (mouse_pos.x as i32, mouse_pos.y as i32);
+            let rect = sdl2::rect::Rect::new(
+                (start.x as i32).min(x),
+                (start.y as i32).min(y),
+                (start.x as i32 - x).unsigned_abs(),
+                (start.y as i32 - y).unsigned_abs(),
+            );
+            canvas.set_draw_color(Color::RGB(255, 80, 80));
+            canvas.draw_rect(rect).ok();
+        }
+
+        let title = {
+            let status = status.lock().unwrap();
+            let state = match (status.connected, status.rtt_ms) {
+                (false, _) => "连接断开，正在重连...".to_string(),
+                (true, Some(rtt)) => format!("RTT {:.0}ms", rtt),
+                (true, None) => "RTT --".to_string(),
+            };
+            let role = match (status.role, status.spectator) {
+                (Role::Teacher, _) => " | 教师",
+                (_, true) => " | 观众",
+                _ => "",
+            };
+            format!(
+                "简单物理沙盒 - 按R添加矩形 | 房间 {}{} | {} 人在线 | {}",
+                status.room,
+                role,
+                status.users.len(),
+                state
+            )
+        };
+        if title != window_title {
+            let _ = canvas.window_mut().set_title(&title);
+            window_title = title;
+        }
+
         canvas.present();
         
         let elapsed = frame_start.elapsed();
@@ -308,6 +685,102 @@ fn render_loop(
     }
 }
 
+// 创建窗口和画布，以世界坐标绘制，窗口大小不同时由 SDL 等比缩放
+fn open_canvas(video_subsystem: &sdl2::VideoSubsystem, config: &ClientConfig, title: &str) -> sdl2::render::Canvas<sdl2::video::Window> {
+    let (window_width, window_height) = config.window_size;
+    let mut window_builder = video_subsystem.window(title, window_width, window_height);
+    window_builder.position_centered().resizable();
+    if config.fullscreen {
+        window_builder.fullscreen_desktop();
+    }
+    let window = window_builder.build().unwrap();
+
+    let mut canvas = window.into_canvas()
+        .accelerated()
+        .present_vsync()
+        .build()
+        .unwrap();
+    canvas.set_logical_size(WORLD_WIDTH, WORLD_HEIGHT).unwrap();
+    canvas
+}
+
+// 绘制拖尾
+fn draw_trails(canvas: &mut sdl2::render::Canvas<sdl2::video::Window>, trails: &Trails, bodies: &[RigidBody]) {
+    for body in bodies {
+        if let Some(trail) = trails.get(&body.id) {
+            for w in trail.windows(2) {
+                let p1 = w[0];
+                let p2 = w[1];
+                // 越靠后的点越透明
+                let alpha = ((trail.len() as f32 - w[0..1].len() as f32) / trail.len() as f32 * 180.0 + 40.0) as u8;
+                canvas.set_draw_color(sdl2::pixels::Color::RGBA(255, 255, 255, alpha));
+                canvas.draw_line((p1.x as i32, p1.y as i32), (p2.x as i32, p2.y as i32)).ok();
+            }
+        }
+    }
+}
+
+// 绘制力场区域和关节。关节端点取插值后的物体位置，与物体一起移动
+fn draw_environment(
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+    force_fields: &[ForceField],
+    joints: &[Joint],
+    bodies: &[RigidBody],
+) {
+    canvas.set_draw_color(Color::RGBA(100, 180, 255, 120));
+    for field in force_fields {
+        let rect = sdl2::rect::Rect::new(
+            field.min.x as i32,
+            field.min.y as i32,
+            (field.max.x - field.min.x).max(1.0) as u32,
+            (field.max.y - field.min.y).max(1.0) as u32,
+        );
+        canvas.draw_rect(rect).ok();
+        // 从区域中心画出加速度方向
+        let center = (field.min + field.max) * 0.5;
+        let tip = center + field.acceleration.normalize() * 30.0;
+        canvas.draw_line((center.x as i32, center.y as i32), (tip.x as i32, tip.y as i32)).ok();
+    }
+    canvas.set_draw_color(Color::RGB(200, 200, 200));
+    for joint in joints {
+        let a = bodies.iter().find(|b| b.id == joint.body_a);
+        let b = bodies.iter().find(|b| b.id == joint.body_b);
+        if let (Some(a), Some(b)) = (a, b) {
+            canvas
+                .draw_line((a.position.x as i32, a.position.y as i32), (b.position.x as i32, b.position.y as i32))
+                .ok();
+        }
+    }
+}
+
+// 鼠标在世界坐标中的位置。鼠标事件已由 SDL 换算，直接查询的鼠标状态仍是窗口坐标
+fn mouse_position(event_pump: &sdl2::EventPump, canvas: &sdl2::render::Canvas<sdl2::video::Window>) -> Vec2 {
+    let mouse_state = event_pump.mouse_state();
+    let (scale_x, scale_y) = canvas.scale();
+    let viewport = canvas.viewport();
+    Vec2::new(
+        mouse_state.x() as f32 / scale_x - viewport.x() as f32,
+        mouse_state.y() as f32 / scale_y - viewport.y() as f32,
+    )
+}
+
+// 返回位于该点的物体 id
+fn body_at(bodies: &[RigidBody], pos: Vec2) -> Option<u32> {
+    bodies
+        .iter()
+        .find(|body| {
+            let delta = pos - body.position;
+            match body.shape {
+                Shape::Circle { radius } => delta.length() <= radius,
+                Shape::Rectangle { width, height } => {
+                    // 简化的矩形点击检测（不考虑旋转）
+                    delta.x.abs() <= width / 2.0 && delta.y.abs() <= height / 2.0
+                }
+            }
+        })
+        .map(|body| body.id)
+}
+
 fn draw_body(canvas: &mut sdl2::render::Canvas<sdl2::video::Window>, body: &RigidBody) {
     let highlight = body.collision_frames > 0;
     match body.shape {
@@ -0,0 +1,405 @@
+use common::physics::{
+    ChatMessage, ClientMessage, ContactEvent, Diagnostics, PresenceInfo, Role, RoomInfo, ServerMessage, Topic, UserInfo,
+};
+use common::transport::{Delivery, MessageSender, TransportKind};
+use crate::graphs::GRAPH_SAMPLES;
+use crate::interpolation::SnapshotBuffer;
+use std::collections::VecDeque;
+use crate::recording::StateRecorder;
+use crate::transport::{self, Connection};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// 当前连接的发送端，断线重连期间为 None
+pub type Writer = Arc<Mutex<Option<Box<dyn MessageSender>>>>;
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+// 本地保留的聊天条数
+const CHAT_LOG_LEN: usize = 50;
+// 连接后默认订阅的附加数据：碰撞效果需要接触事件
+const DEFAULT_SUBSCRIPTIONS: &[Topic] = &[Topic::Contacts];
+// 渲染线程来不及取走时最多积压的接触事件
+const MAX_PENDING_CONTACTS: usize = 256;
+
+pub struct ConnectOptions {
+    pub addr: String,
+    pub transport: TransportKind,
+    pub packet_loss: f32,
+    pub name: String,
+    pub token: Option<String>,
+    pub spectator: bool,
+    pub color: Option<[u8; 3]>,
+}
+
+// 连接状态，供渲染线程显示
+pub struct NetStatus {
+    pub connected: bool,
+    pub client_id: Option<usize>,
+    pub role: Role,
+    pub spectator: bool,
+    pub room: String,
+    // 所在房间的模拟频率，旧服务器不发送时为 0
+    pub tick_rate: f32,
+    pub users: Vec<UserInfo>,
+    pub presence: Vec<PresenceInfo>,
+    pub chat: Vec<ChatMessage>,
+    // 已订阅的附加数据，重连后重新订阅
+    pub subscriptions: Vec<Topic>,
+    pub diagnostics: VecDeque<Diagnostics>,
+    // 收到但尚未被渲染线程取走的接触事件
+    pub contacts: Vec<ContactEvent>,
+    pub rtt_ms: Option<f32>,
+    epoch: Instant,
+    last_received: Instant,
+}
+
+impl NetStatus {
+    pub fn new() -> Self {
+        Self {
+            connected: true,
+            client_id: None,
+            role: Role::Student,
+            spectator: false,
+            room: String::new(),
+            tick_rate: 0.0,
+            users: Vec::new(),
+            presence: Vec::new(),
+            chat: Vec::new(),
+            subscriptions: DEFAULT_SUBSCRIPTIONS.to_vec(),
+            diagnostics: VecDeque::new(),
+            contacts: Vec::new(),
+            rtt_ms: None,
+            epoch: Instant::now(),
+            last_received: Instant::now(),
+        }
+    }
+}
+
+// 客户端命令都走可靠通道
+pub fn send_message(writer: &Writer, msg: &ClientMessage) {
+    let json = serde_json::to_string(msg).unwrap();
+    if let Some(w) = writer.lock().unwrap().as_mut() {
+        let _ = w.send(&json, Delivery::Reliable);
+    }
+}
+
+// 高频且可丢失的消息（如光标位置）走不可靠通道
+pub fn send_unreliable(writer: &Writer, msg: &ClientMessage) {
+    let json = serde_json::to_string(msg).unwrap();
+    if let Some(w) = writer.lock().unwrap().as_mut() {
+        let _ = w.send(&json, Delivery::Unreliable);
+    }
+}
+
+fn send_direct(connection: &mut Connection, msg: &ClientMessage) -> io::Result<()> {
+    connection
+        .sender
+        .send(&serde_json::to_string(msg).unwrap(), Delivery::Reliable)
+}
+
+// 握手：报上名字和口令，回到之前所在的房间并恢复订阅
+fn handshake(connection: &mut Connection, options: &ConnectOptions, room: Option<&str>, subscriptions: &[Topic]) -> io::Result<()> {
+    let hello = ClientMessage::Hello {
+        name: options.name.clone(),
+        token: options.token.clone(),
+        spectator: options.spectator,
+        color: options.color,
+    };
+    send_direct(connection, &hello)?;
+    if let Some(room) = room {
+        send_direct(connection, &ClientMessage::JoinRoom { name: room.to_string() })?;
+    }
+    for &topic in subscriptions {
+        send_direct(connection, &ClientMessage::Subscribe { topic, enabled: true })?;
+    }
+    Ok(())
+}
+
+pub fn connect(options: &ConnectOptions) -> io::Result<Connection> {
+    let mut connection = transport::connect(options.transport, &options.addr, options.packet_loss)?;
+    handshake(&mut connection, options, None, DEFAULT_SUBSCRIPTIONS)?;
+    Ok(connection)
+}
+
+// 列出服务器上的房间，让用户选择加入或新建；已指定房间名时不再询问。
+// 返回等待房间列表期间收到的其他消息（欢迎、加入房间、用户列表等），由 network_loop 先行处理
+pub fn choose_room(connection: &mut Connection, room: Option<&str>) -> io::Result<Vec<String>> {
+    use std::io::Write;
+
+    send_direct(connection, &ClientMessage::ListRooms)?;
+    let mut pending = Vec::new();
+    let rooms = loop {
+        let Some(line) = connection.receiver.recv()? else {
+            return Err(io::Error::new(io::ErrorKind::ConnectionAborted, "服务器断开连接"));
+        };
+        match serde_json::from_str::<ServerMessage>(&line) {
+            Ok(ServerMessage::RoomList { rooms }) => break rooms,
+            _ => pending.push(line),
+        }
+    };
+
+    let name = match room {
+        Some(room) => room.trim().to_string(),
+        None => {
+            print_rooms(&rooms);
+            print!("请输入要加入或新建的房间名 (默认留在大厅): ");
+            io::stdout().flush()?;
+            let mut name = String::new();
+            io::stdin().read_line(&mut name)?;
+            name.trim().to_string()
+        }
+    };
+    if name.is_empty() {
+        return Ok(pending);
+    }
+
+    let msg = if rooms.iter().any(|room| room.name == name) {
+        ClientMessage::JoinRoom { name }
+    } else {
+        ClientMessage::CreateRoom { name, config: None }
+    };
+    send_direct(connection, &msg)?;
+    Ok(pending)
+}
+
+fn print_rooms(rooms: &[RoomInfo]) {
+    println!("当前房间:");
+    for room in rooms {
+        println!("  {} （{} 人，{} 个物体）", room.name, room.clients, room.bodies);
+    }
+}
+
+fn print_users(users: &[UserInfo]) {
+    println!("房间内用户:");
+    for user in users {
+        let mut tags = Vec::new();
+        if user.role == Role::Teacher {
+            tags.push("教师");
+        }
+        if user.spectator {
+            tags.push("观众");
+        }
+        let tags = if tags.is_empty() { String::new() } else { format!("（{}）", tags.join("，")) };
+        println!("  [{}] {}{}", user.client_id, user.name, tags);
+    }
+}
+
+// 接收服务器消息；连接断开后按指数退避自动重连，重新握手并回到原房间。
+// pending 是选择房间时已经收到的消息；recorder 不为 None 时把收到的快照写入录像
+pub fn network_loop(
+    options: ConnectOptions,
+    mut connection: Connection,
+    pending: Vec<String>,
+    world_state: Arc<Mutex<SnapshotBuffer>>,
+    writer: Writer,
+    status: Arc<Mutex<NetStatus>>,
+    mut recorder: Option<StateRecorder>,
+) {
+    for line in &pending {
+        handle_server_message(line, &world_state, &status, &mut recorder);
+    }
+    loop {
+        let mut receiver = connection.receiver;
+        *writer.lock().unwrap() = Some(connection.sender);
+        {
+            let mut status = status.lock().unwrap();
+            status.connected = true;
+            status.last_received = Instant::now();
+        }
+
+        loop {
+            match receiver.recv() {
+                Ok(None) => {
+                    println!("服务器断开连接");
+                    break;
+                }
+                Ok(Some(line)) => {
+                    status.lock().unwrap().last_received = Instant::now();
+                    handle_server_message(&line, &world_state, &status, &mut recorder);
+                }
+                Err(_) => {
+                    println!("网络读取错误");
+                    break;
+                }
+            }
+        }
+
+        if let Some(mut sender) = writer.lock().unwrap().take() {
+            sender.close();
+        }
+        let (room, subscriptions) = {
+            let mut status = status.lock().unwrap();
+            status.connected = false;
+            status.rtt_ms = None;
+            status.presence.clear();
+            (status.room.clone(), status.subscriptions.clone())
+        };
+
+        let mut backoff = INITIAL_BACKOFF;
+        connection = loop {
+            println!("{:.1} 秒后尝试重连 {} ...", backoff.as_secs_f32(), options.addr);
+            thread::sleep(backoff);
+            match transport::connect(options.transport, &options.addr, options.packet_loss) {
+                Ok(mut c) => {
+                    let room = (!room.is_empty()).then_some(room.as_str());
+                    if handshake(&mut c, &options, room, &subscriptions).is_ok() {
+                        println!("重连成功");
+                        break c;
+                    }
+                }
+                Err(e) => println!("重连失败: {}", e),
+            }
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        };
+        world_state.lock().unwrap().clear();
+    }
+}
+
+fn handle_server_message(
+    line: &str,
+    world_state: &Arc<Mutex<SnapshotBuffer>>,
+    status: &Arc<Mutex<NetStatus>>,
+    recorder: &mut Option<StateRecorder>,
+) {
+    match serde_json::from_str::<ServerMessage>(line) {
+        Ok(ServerMessage::WorldState(state)) => {
+            println!("收到新世界状态，物体数量: {}", state.bodies.len());
+            for b in &state.bodies {
+                println!("ID: {}, 位置: {:?}, 形状: {:?}", b.id, b.position, b.shape);
+            }
+            // 只录制被接受的快照，乱序到达的不写入
+            let snapshot = recorder.is_some().then(|| state.clone());
+            if world_state.lock().unwrap().push(state) {
+                if let (Some(r), Some(snapshot)) = (recorder.as_mut(), snapshot) {
+                    if let Err(e) = r.record(&snapshot) {
+                        println!("写入录像失败，停止录制: {}", e);
+                        *recorder = None;
+                    }
+                }
+            }
+        }
+        Ok(ServerMessage::Welcome { client_id, role, spectator }) => {
+            println!(
+                "服务器分配的客户端 ID: {}，角色: {:?}{}",
+                client_id,
+                role,
+                if spectator { "（观众）" } else { "" }
+            );
+            let mut status = status.lock().unwrap();
+            status.client_id = Some(client_id);
+            status.role = role;
+            status.spectator = spectator;
+        }
+        Ok(ServerMessage::UserList { users }) => {
+            print_users(&users);
+            let mut status = status.lock().unwrap();
+            // 已离开的用户不再显示光标
+            status.presence.retain(|p| users.iter().any(|u| u.client_id == p.client_id));
+            status.users = users;
+        }
+        Ok(ServerMessage::Diagnostics(diagnostics)) => {
+            let mut status = status.lock().unwrap();
+            // 走不可靠通道，丢弃乱序到达的旧样本
+            if status
+                .diagnostics
+                .back()
+                .is_none_or(|last| diagnostics.server_time > last.server_time)
+            {
+                status.diagnostics.push_back(diagnostics);
+                if status.diagnostics.len() > GRAPH_SAMPLES {
+                    status.diagnostics.pop_front();
+                }
+            }
+        }
+        Ok(ServerMessage::Contacts { events, .. }) => {
+            let mut status = status.lock().unwrap();
+            status.contacts.extend(events);
+            let excess = status.contacts.len().saturating_sub(MAX_PENDING_CONTACTS);
+            status.contacts.drain(..excess);
+        }
+        Ok(ServerMessage::Presence { users }) => {
+            status.lock().unwrap().presence = users;
+        }
+        Ok(ServerMessage::Chat(message)) => {
+            println!("[聊天] {}: {}", message.name, message.text);
+            let mut status = status.lock().unwrap();
+            status.chat.push(message);
+            let excess = status.chat.len().saturating_sub(CHAT_LOG_LEN);
+            status.chat.drain(..excess);
+        }
+        Ok(ServerMessage::ChatHistory { messages }) => {
+            status.lock().unwrap().chat = messages;
+        }
+        Ok(ServerMessage::PermissionsChanged { permissions }) => {
+            println!(
+                "房间权限已修改: 推动 {:?}，修改 {:?}，删除 {:?}",
+                permissions.push, permissions.edit, permissions.delete
+            );
+        }
+        Ok(ServerMessage::Pong { nonce }) => {
+            let mut status = status.lock().unwrap();
+            let now = status.epoch.elapsed().as_millis() as u64;
+            let sample = now.saturating_sub(nonce) as f32;
+            status.rtt_ms = Some(match status.rtt_ms {
+                Some(rtt) => rtt * 0.8 + sample * 0.2,
+                None => sample,
+            });
+        }
+        Ok(ServerMessage::JoinedRoom { name, tick_rate }) => {
+            println!("已进入房间: {}", name);
+            {
+                let mut status = status.lock().unwrap();
+                status.room = name;
+                status.tick_rate = tick_rate;
+                status.presence.clear();
+                status.diagnostics.clear();
+            }
+            world_state.lock().unwrap().clear();
+        }
+        Ok(ServerMessage::RoomList { rooms }) => {
+            print_rooms(&rooms);
+        }
+        Ok(ServerMessage::SceneList { scenes }) => {
+            println!("服务器上的场景: {}", if scenes.is_empty() { "（无）".to_string() } else { scenes.join("、") });
+        }
+        Ok(ServerMessage::SceneSaved { name }) => {
+            println!("场景已保存: {}", name);
+        }
+        Ok(ServerMessage::Error { message }) => {
+            println!("服务器错误: {}", message);
+        }
+        Err(e) => {
+            println!("收到无法解析的服务器消息: {}", line);
+            println!("解析错误: {:?}", e);
+        }
+    }
+}
+
+// 定时发送心跳；超时未收到服务器任何消息时关闭连接，交给 network_loop 重连
+pub fn heartbeat_loop(interval: Duration, server_timeout: Duration, writer: Writer, status: Arc<Mutex<NetStatus>>) {
+    loop {
+        thread::sleep(interval);
+        let (nonce, timed_out) = {
+            let status = status.lock().unwrap();
+            (
+                status.epoch.elapsed().as_millis() as u64,
+                status.connected && status.last_received.elapsed() > server_timeout,
+            )
+        };
+        let mut writer = writer.lock().unwrap();
+        let Some(sender) = writer.as_mut() else {
+            continue;
+        };
+        if timed_out {
+            println!("服务器心跳超时，准备重连");
+            sender.close();
+            continue;
+        }
+        // 心跳丢了就丢了，不需要重发
+        let ping = serde_json::to_string(&ClientMessage::Ping { nonce }).unwrap();
+        let _ = sender.send(&ping, Delivery::Unreliable);
+    }
+}
@@ -0,0 +1,207 @@
+use common::physics::{Vec2, WorldState};
+use crate::chat;
+use crate::config::ClientConfig;
+use crate::font::draw_text;
+use crate::inspector::Inspector;
+use crate::{Trails, TRAIL_LEN, WORLD_HEIGHT, WORLD_WIDTH};
+use sdl2::event::Event;
+use sdl2::image::LoadTexture;
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// 底部的时间轴
+const BAR_MARGIN: i32 = 20;
+const BAR_Y: i32 = WORLD_HEIGHT as i32 - 24;
+const BAR_HEIGHT: u32 = 8;
+// 时间轴附近这个范围内的点击都算拖动时间轴
+const BAR_HIT: i32 = 14;
+const MIN_SPEED: f64 = 0.125;
+const MAX_SPEED: f64 = 8.0;
+
+// 回看快照录像：空格暂停/继续，左右方向键逐帧，- / = 减慢或加快，Home / End 跳到开头或结尾，
+// 拖动底部时间轴跳到任意位置，点击物体查看属性
+pub fn run(config: &ClientConfig, frames: Vec<WorldState>) {
+    let sdl_context = sdl2::init().unwrap();
+    let _image_context = sdl2::image::init(sdl2::image::InitFlag::PNG | sdl2::image::InitFlag::JPG).unwrap();
+    let video_subsystem = sdl_context.video().unwrap();
+    let mut canvas = crate::open_canvas(&video_subsystem, config, "简单物理沙盒 - 录像回看");
+
+    let texture_creator = canvas.texture_creator();
+    let background_path = config.assets.join("background.png");
+    let background_texture = texture_creator
+        .load_texture(&background_path)
+        .unwrap_or_else(|e| panic!("无法加载背景图片 {}: {}", background_path.display(), e));
+
+    let mut event_pump = sdl_context.event_pump().unwrap();
+    let frame_duration = Duration::from_nanos(1_000_000_000 / 60);
+
+    // 播放位置是相对第一帧的服务器时间（秒）
+    let start = frames[0].server_time;
+    let duration = frames[frames.len() - 1].server_time - start;
+    let mut position = 0.0;
+    let mut playing = true;
+    let mut speed: f64 = 1.0;
+    let mut scrubbing = false;
+    let mut inspector = Inspector::new();
+    let mut last_frame = Instant::now();
+
+    'running: loop {
+        let frame_start = Instant::now();
+        let index = frame_at(&frames, start + position);
+
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => break 'running,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Space),
+                    ..
+                } => {
+                    // 播完后再按空格从头播放
+                    if !playing && position >= duration {
+                        position = 0.0;
+                    }
+                    playing = !playing;
+                }
+                Event::KeyDown {
+                    keycode: Some(key @ (Keycode::Left | Keycode::Right)),
+                    ..
+                } => {
+                    playing = false;
+                    let target = if key == Keycode::Left {
+                        index.saturating_sub(1)
+                    } else {
+                        (index + 1).min(frames.len() - 1)
+                    };
+                    position = frames[target].server_time - start;
+                }
+                Event::KeyDown {
+                    keycode: Some(key @ (Keycode::Minus | Keycode::Equals)),
+                    ..
+                } => {
+                    let factor = if key == Keycode::Minus { 0.5 } else { 2.0 };
+                    speed = (speed * factor).clamp(MIN_SPEED, MAX_SPEED);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Home),
+                    ..
+                } => position = 0.0,
+                Event::KeyDown {
+                    keycode: Some(Keycode::End),
+                    ..
+                } => position = duration,
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } => {
+                    if (y - BAR_Y).abs() <= BAR_HIT {
+                        scrubbing = true;
+                        position = bar_position(x, duration);
+                    } else {
+                        let pos = Vec2::new(x as f32, y as f32);
+                        if !inspector.contains(&frames[index].bodies, pos) {
+                            inspector.select(crate::body_at(&frames[index].bodies, pos));
+                        }
+                    }
+                }
+                Event::MouseMotion { x, .. } if scrubbing => position = bar_position(x, duration),
+                Event::MouseButtonUp {
+                    mouse_btn: MouseButton::Left,
+                    ..
+                } => scrubbing = false,
+                _ => {}
+            }
+        }
+
+        let elapsed = last_frame.elapsed().as_secs_f64();
+        last_frame = Instant::now();
+        if playing && !scrubbing {
+            position += elapsed * speed;
+            if position >= duration {
+                position = duration;
+                playing = false;
+            }
+        }
+
+        let index = frame_at(&frames, start + position);
+        let state = &frames[index];
+        inspector.sync(&state.bodies);
+
+        canvas.copy(&background_texture, None, None).unwrap();
+        crate::draw_trails(&mut canvas, &trails_at(&frames, index), &state.bodies);
+        for body in &state.bodies {
+            crate::draw_body(&mut canvas, body);
+        }
+        chat::draw_labels(&mut canvas, &state.labels, &state.bodies);
+        inspector.draw(&mut canvas, &state.bodies);
+
+        // 左上角：播放状态、时间、速度和帧号，例如 "PLAY 12.3/60.0s x0.5 #740 TICK 1203"
+        let mut info = format!(
+            "{} {:.1}/{:.1}s x{} #{} TICK {}",
+            if playing { "PLAY" } else { "PAUSE" },
+            position,
+            duration,
+            speed,
+            index + 1,
+            state.tick
+        );
+        if state.paused {
+            info.push_str(" (PAUSED)");
+        }
+        draw_text(&mut canvas, &info, 12, 12, 2, Color::RGB(255, 220, 0));
+        draw_timeline(&mut canvas, position, duration);
+
+        canvas.present();
+
+        let elapsed = frame_start.elapsed();
+        if elapsed < frame_duration {
+            thread::sleep(frame_duration - elapsed);
+        }
+    }
+}
+
+// 服务器时间不晚于 time 的最后一帧。留一点余量，逐帧跳转时不会因为舍入落到前一帧
+fn frame_at(frames: &[WorldState], time: f64) -> usize {
+    frames.partition_point(|f| f.server_time <= time + 1e-6).saturating_sub(1)
+}
+
+// 拖尾由前面若干帧的位置组成，跳转后也能立即显示正确的轨迹
+fn trails_at(frames: &[WorldState], index: usize) -> Trails {
+    let mut trails = Trails::new();
+    for frame in &frames[(index + 1).saturating_sub(TRAIL_LEN)..=index] {
+        for body in &frame.bodies {
+            trails.entry(body.id).or_default().push(body.position);
+        }
+    }
+    trails
+}
+
+fn bar_width() -> i32 {
+    WORLD_WIDTH as i32 - 2 * BAR_MARGIN
+}
+
+fn bar_position(x: i32, duration: f64) -> f64 {
+    let t = (x - BAR_MARGIN) as f64 / bar_width() as f64;
+    t.clamp(0.0, 1.0) * duration
+}
+
+fn draw_timeline(canvas: &mut sdl2::render::Canvas<sdl2::video::Window>, position: f64, duration: f64) {
+    let progress = if duration > 0.0 { position / duration } else { 1.0 };
+    let filled = (bar_width() as f64 * progress) as i32;
+    canvas.set_draw_color(Color::RGBA(60, 60, 70, 220));
+    canvas.fill_rect(Rect::new(BAR_MARGIN, BAR_Y, bar_width() as u32, BAR_HEIGHT)).ok();
+    canvas.set_draw_color(Color::RGB(255, 220, 0));
+    canvas.fill_rect(Rect::new(BAR_MARGIN, BAR_Y, filled.max(1) as u32, BAR_HEIGHT)).ok();
+    // 拖动手柄
+    canvas.set_draw_color(Color::RGB(255, 255, 255));
+    canvas.fill_rect(Rect::new(BAR_MARGIN + filled - 3, BAR_Y - 5, 6, BAR_HEIGHT + 10)).ok();
+}
@@ -0,0 +1,165 @@
+use common::physics::{RigidBody, Vec2, WorldState};
+use std::collections::HashMap;
+use std::time::Instant;
+
+// 收到房间的模拟频率之前使用的固定步长
+const DEFAULT_FIXED_DT: f32 = 1.0 / 60.0;
+// 还没有测得往返时间时假定的值（秒）
+const DEFAULT_RTT: f64 = 0.1;
+// 交还时机在往返时间和一个周期之外再留出的余量（秒），覆盖网络抖动
+const HANDOVER_JITTER: f64 = 0.05;
+// 误差修正的衰减速率（每秒），越大收敛越快
+const CORRECTION_RATE: f32 = 10.0;
+// 单帧最多补算的步数，避免窗口卡顿后一次算太多
+const MAX_STEPS_PER_FRAME: u32 = 8;
+
+struct Correction {
+    position: Vec2,
+    angle: f32,
+}
+
+// 本地预测：对刚施加冲量的物体立即在本地运行同样的物理步，
+// 等服务器的权威状态在插值画面中追上后再平滑地交还给服务器状态
+pub struct Predictor {
+    world: WorldState,
+    // 物体 id -> 交还时刻（服务器时间）
+    predicted: HashMap<u32, f64>,
+    corrections: HashMap<u32, Correction>,
+    last_update: Instant,
+    accumulator: f32,
+    // 与房间模拟频率相同的固定步长
+    fixed_dt: f32,
+    // 最近测得的往返时间（秒）
+    rtt: Option<f64>,
+}
+
+impl Predictor {
+    pub fn new() -> Self {
+        Self {
+            world: WorldState::default(),
+            predicted: HashMap::new(),
+            corrections: HashMap::new(),
+            last_update: Instant::now(),
+            accumulator: 0.0,
+            fixed_dt: DEFAULT_FIXED_DT,
+            rtt: None,
+        }
+    }
+
+    pub fn set_rtt(&mut self, rtt_ms: Option<f32>) {
+        self.rtt = rtt_ms.map(|ms| ms as f64 / 1000.0);
+    }
+
+    // 命令发出后，服务器执行它的快照最晚何时出现在插值画面中：
+    // 一个往返（命令送达加快照返回）、等待下一周期执行，再加上抖动余量
+    fn handover_margin(&self) -> f64 {
+        self.rtt.unwrap_or(DEFAULT_RTT) + self.fixed_dt as f64 + HANDOVER_JITTER
+    }
+
+    // 房间的模拟频率，加入房间前为 0
+    pub fn set_tick_rate(&mut self, tick_rate: f32) {
+        if tick_rate > 0.0 {
+            self.fixed_dt = 1.0 / tick_rate;
+        }
+    }
+
+    // 使用服务器下发的世界参数、关节和力场，保证本地与服务器的物理一致
+    pub fn set_world(&mut self, state: &WorldState) {
+        self.world.params = state.params;
+        self.world.joints.clone_from(&state.joints);
+        self.world.force_fields.clone_from(&state.force_fields);
+    }
+
+    // 本地施加冲量；server_now 为按本地时钟估计的服务器当前时间
+    pub fn apply_impulse(&mut self, rendered: &[RigidBody], body_id: u32, impulse: Vec2, server_now: f64) {
+        if self.predicted.is_empty() {
+            self.world.bodies = rendered.to_vec();
+            self.accumulator = 0.0;
+        }
+        let Some(body) = self.world.bodies.iter_mut().find(|b| b.id == body_id) else {
+            return;
+        };
+        body.velocity = body.velocity + impulse * body.inverse_mass();
+        self.predicted.insert(body_id, server_now + self.handover_margin());
+        self.corrections.remove(&body_id);
+    }
+
+    // 用预测结果覆盖插值得到的物体，并对刚交还的物体叠加逐渐衰减的误差
+    // time_scale 跟随服务器的时间倍率，暂停时为 0
+    pub fn apply(&mut self, bodies: &mut [RigidBody], render_time: Option<f64>, time_scale: f32) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_update).as_secs_f32();
+        self.last_update = now;
+
+        if !self.predicted.is_empty() {
+            self.sync_unpredicted(bodies);
+            self.accumulator += elapsed * time_scale;
+            let mut steps = 0;
+            while self.accumulator >= self.fixed_dt && steps < MAX_STEPS_PER_FRAME {
+                self.world.step(self.fixed_dt);
+                self.accumulator -= self.fixed_dt;
+                steps += 1;
+            }
+            if steps == MAX_STEPS_PER_FRAME {
+                self.accumulator = 0.0;
+            }
+
+            // 插值画面已包含服务器处理该命令后的状态：记录误差后交还
+            if let Some(render_time) = render_time {
+                let due: Vec<u32> = self
+                    .predicted
+                    .iter()
+                    .filter(|&(_, &handover)| render_time >= handover)
+                    .map(|(&id, _)| id)
+                    .collect();
+                for id in due {
+                    self.predicted.remove(&id);
+                    let local = self.world.bodies.iter().find(|b| b.id == id);
+                    let server = bodies.iter().find(|b| b.id == id);
+                    if let (Some(local), Some(server)) = (local, server) {
+                        self.corrections.insert(id, Correction {
+                            position: local.position - server.position,
+                            angle: local.angle - server.angle,
+                        });
+                    }
+                }
+            }
+        }
+
+        let decay = (-CORRECTION_RATE * elapsed).exp();
+        self.corrections.retain(|_, c| {
+            c.position = c.position * decay;
+            c.angle *= decay;
+            c.position.length() > 0.1 || c.angle.abs() > 0.001
+        });
+
+        for body in bodies.iter_mut() {
+            if self.predicted.contains_key(&body.id) {
+                if let Some(local) = self.world.bodies.iter().find(|b| b.id == body.id) {
+                    *body = local.clone();
+                }
+            } else if let Some(correction) = self.corrections.get(&body.id) {
+                body.position = body.position + correction.position;
+                body.angle += correction.angle;
+            }
+        }
+    }
+
+    // 未被预测的物体始终跟随服务器状态，使本地碰撞尽量贴近服务器
+    fn sync_unpredicted(&mut self, bodies: &[RigidBody]) {
+        let predicted = &self.predicted;
+        let mut world_bodies: Vec<RigidBody> = bodies
+            .iter()
+            .filter(|b| !predicted.contains_key(&b.id))
+            .cloned()
+            .collect();
+        world_bodies.extend(
+            self.world
+                .bodies
+                .drain(..)
+                .filter(|b| predicted.contains_key(&b.id)),
+        );
+        world_bodies.sort_by_key(|b| b.id);
+        self.world.bodies = world_bodies;
+    }
+}
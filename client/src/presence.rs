@@ -0,0 +1,75 @@
+use common::physics::{PresenceInfo, Role, UserInfo};
+use crate::font::draw_text;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+const LABEL_SCALE: u32 = 2;
+
+// 点阵字体只有 ASCII，名字里的其他字符略去，全部略去时显示编号
+fn display_name(user: &UserInfo) -> String {
+    let name: String = user.name.chars().filter(|c| c.is_ascii_graphic() || *c == ' ').collect();
+    let name = name.trim();
+    if name.is_empty() {
+        format!("USER {}", user.client_id)
+    } else {
+        name.to_string()
+    }
+}
+
+fn user_color(user: Option<&UserInfo>) -> Color {
+    let [r, g, b] = user.map_or([200, 200, 200], |u| u.color);
+    Color::RGB(r, g, b)
+}
+
+// 绘制其他用户的光标、名字和拖拽线
+pub fn draw_cursors(canvas: &mut Canvas<Window>, presence: &[PresenceInfo], users: &[UserInfo], own_id: Option<usize>) {
+    for p in presence.iter().filter(|p| Some(p.client_id) != own_id) {
+        let user = users.iter().find(|u| u.client_id == p.client_id);
+        let color = user_color(user);
+        let (x, y) = (p.cursor.x as i32, p.cursor.y as i32);
+
+        if let Some(start) = p.drag_start {
+            canvas.set_draw_color(color);
+            canvas.draw_line((start.x as i32, start.y as i32), (x, y)).ok();
+        }
+
+        // 十字光标
+        canvas.set_draw_color(color);
+        canvas.draw_line((x - 6, y), (x + 6, y)).ok();
+        canvas.draw_line((x, y - 6), (x, y + 6)).ok();
+        if let Some(user) = user {
+            draw_text(canvas, &display_name(user), x + 8, y + 8, LABEL_SCALE, color);
+        }
+    }
+}
+
+// 在线用户列表，Tab 键切换显示
+pub fn draw_user_list(canvas: &mut Canvas<Window>, users: &[UserInfo], own_id: Option<usize>) {
+    let row_height = 20;
+    let height = (users.len() as i32 + 1) * row_height + 8;
+    let panel = Rect::new(10, 50, 300, height as u32);
+    canvas.set_draw_color(Color::RGBA(20, 20, 30, 220));
+    canvas.fill_rect(panel).ok();
+    canvas.set_draw_color(Color::RGB(150, 150, 170));
+    canvas.draw_rect(panel).ok();
+    draw_text(canvas, &format!("USERS ({})", users.len()), 18, 56, LABEL_SCALE, Color::RGB(255, 255, 255));
+
+    for (i, user) in users.iter().enumerate() {
+        let y = 56 + (i as i32 + 1) * row_height;
+        canvas.set_draw_color(user_color(Some(user)));
+        canvas.fill_rect(Rect::new(18, y, 14, 14)).ok();
+        let mut label = format!("#{} {}", user.client_id, display_name(user));
+        if user.role == Role::Teacher {
+            label.push_str(" [T]");
+        }
+        if user.spectator {
+            label.push_str(" [S]");
+        }
+        if Some(user.client_id) == own_id {
+            label.push_str(" (ME)");
+        }
+        draw_text(canvas, &label, 40, y, LABEL_SCALE, Color::RGB(230, 230, 230));
+    }
+}
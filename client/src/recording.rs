@@ -0,0 +1,339 @@
+use common::physics::{BodyType, Label, LabelAnchor, Material, RigidBody, Shape, Vec2, WorldState};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// 快照录像文件：文件头之后是一帧帧的快照，每帧前面是 4 字节的长度。
+// 数值一律小端序；只保存回看需要的字段，比 JSON 小得多
+const MAGIC: &[u8; 6] = b"PSREC\x01";
+
+// 把收到的快照写入录像文件
+pub struct StateRecorder {
+    file: BufWriter<File>,
+}
+
+impl StateRecorder {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        Ok(Self { file })
+    }
+
+    pub fn record(&mut self, state: &WorldState) -> io::Result<()> {
+        let mut frame = Encoder::default();
+        frame.encode_state(state);
+        self.file.write_all(&(frame.0.len() as u32).to_le_bytes())?;
+        self.file.write_all(&frame.0)?;
+        // 客户端可能直接被关掉，每帧都写到文件里
+        self.file.flush()
+    }
+}
+
+// 读入整个录像。最后一帧不完整（录制时程序被强制关闭）时忽略该帧
+pub fn load(path: &Path) -> Result<Vec<WorldState>, String> {
+    let data = std::fs::read(path).map_err(|e| format!("无法读取录像 {}: {}", path.display(), e))?;
+    let Some(mut rest) = data.strip_prefix(MAGIC) else {
+        return Err(format!("{} 不是快照录像文件", path.display()));
+    };
+    let mut frames = Vec::new();
+    while rest.len() >= 4 {
+        let len = u32::from_le_bytes(rest[..4].try_into().unwrap()) as usize;
+        let Some(payload) = rest.get(4..4 + len) else {
+            break;
+        };
+        let state = Decoder(payload)
+            .decode_state()
+            .ok_or_else(|| format!("录像 {} 第 {} 帧已损坏", path.display(), frames.len() + 1))?;
+        frames.push(state);
+        rest = &rest[4 + len..];
+    }
+    if frames.is_empty() {
+        return Err(format!("录像 {} 中没有快照", path.display()));
+    }
+    Ok(frames)
+}
+
+#[derive(Default)]
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn u8(&mut self, v: u8) {
+        self.0.push(v);
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn u64(&mut self, v: u64) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn f32(&mut self, v: f32) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn f64(&mut self, v: f64) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn vec2(&mut self, v: Vec2) {
+        self.f32(v.x);
+        self.f32(v.y);
+    }
+
+    fn encode_state(&mut self, state: &WorldState) {
+        self.f64(state.server_time);
+        self.u64(state.tick);
+        self.u8(state.paused as u8);
+        self.f32(state.time_scale);
+        self.u32(state.bodies.len() as u32);
+        for body in &state.bodies {
+            self.encode_body(body);
+        }
+        self.u32(state.labels.len() as u32);
+        for label in &state.labels {
+            self.u32(label.id);
+            match label.anchor {
+                LabelAnchor::Body { body_id, offset } => {
+                    self.u8(0);
+                    self.u32(body_id);
+                    self.vec2(offset);
+                }
+                LabelAnchor::Point { position } => {
+                    self.u8(1);
+                    self.vec2(position);
+                }
+            }
+            self.u32(label.text.len() as u32);
+            self.0.extend_from_slice(label.text.as_bytes());
+        }
+    }
+
+    fn encode_body(&mut self, body: &RigidBody) {
+        self.u32(body.id);
+        match body.shape {
+            Shape::Circle { radius } => {
+                self.u8(0);
+                self.f32(radius);
+                self.f32(0.0);
+            }
+            Shape::Rectangle { width, height } => {
+                self.u8(1);
+                self.f32(width);
+                self.f32(height);
+            }
+        }
+        self.vec2(body.position);
+        self.vec2(body.velocity);
+        self.f32(body.angle);
+        self.f32(body.angular_velocity);
+        self.f32(body.mass);
+        self.f32(body.material.restitution);
+        self.f32(body.material.friction);
+        self.u8(match body.body_type {
+            BodyType::Dynamic => 0,
+            BodyType::Kinematic => 1,
+            BodyType::Static => 2,
+        });
+        self.u8(body.collision_frames);
+    }
+}
+
+// 读到末尾或遇到无效数据时返回 None
+struct Decoder<'a>(&'a [u8]);
+
+impl Decoder<'_> {
+    fn bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (head, rest) = self.0.split_first_chunk::<N>()?;
+        self.0 = rest;
+        Some(*head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes::<1>().map(|b| b[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.bytes().map(u32::from_le_bytes)
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.bytes().map(u64::from_le_bytes)
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        self.bytes().map(f32::from_le_bytes)
+    }
+
+    fn f64(&mut self) -> Option<f64> {
+        self.bytes().map(f64::from_le_bytes)
+    }
+
+    fn vec2(&mut self) -> Option<Vec2> {
+        Some(Vec2::new(self.f32()?, self.f32()?))
+    }
+
+    fn decode_state(&mut self) -> Option<WorldState> {
+        let mut state = WorldState {
+            server_time: self.f64()?,
+            tick: self.u64()?,
+            paused: self.u8()? != 0,
+            time_scale: self.f32()?,
+            ..Default::default()
+        };
+        for _ in 0..self.u32()? {
+            state.bodies.push(self.decode_body()?);
+        }
+        for _ in 0..self.u32()? {
+            let id = self.u32()?;
+            let anchor = match self.u8()? {
+                0 => LabelAnchor::Body {
+                    body_id: self.u32()?,
+                    offset: self.vec2()?,
+                },
+                1 => LabelAnchor::Point { position: self.vec2()? },
+                _ => return None,
+            };
+            let len = self.u32()? as usize;
+            let text = self.0.get(..len)?;
+            self.0 = &self.0[len..];
+            state.labels.push(Label {
+                id,
+                text: String::from_utf8_lossy(text).into_owned(),
+                anchor,
+            });
+        }
+        Some(state)
+    }
+
+    fn decode_body(&mut self) -> Option<RigidBody> {
+        let id = self.u32()?;
+        let tag = self.u8()?;
+        let (a, b) = (self.f32()?, self.f32()?);
+        let shape = match tag {
+            0 => Shape::Circle { radius: a },
+            1 => Shape::Rectangle { width: a, height: b },
+            _ => return None,
+        };
+        let position = self.vec2()?;
+        let velocity = self.vec2()?;
+        let angle = self.f32()?;
+        let angular_velocity = self.f32()?;
+        let mass = self.f32()?;
+        let material = Material {
+            restitution: self.f32()?,
+            friction: self.f32()?,
+        };
+        let body_type = match self.u8()? {
+            0 => BodyType::Dynamic,
+            1 => BodyType::Kinematic,
+            2 => BodyType::Static,
+            _ => return None,
+        };
+        let mut body = RigidBody {
+            id,
+            position,
+            velocity,
+            shape,
+            mass,
+            angle,
+            angular_velocity,
+            collision_frames: self.u8()?,
+            owner: None,
+            material,
+            body_type,
+            inertia: 0.0,
+        };
+        body.update_inertia();
+        Some(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_state(tick: u64) -> WorldState {
+        let mut ball = RigidBody::new_circle(1, Vec2::new(200.0, 300.5), 30.0, 2.0);
+        ball.velocity = Vec2::new(-12.25, 40.0);
+        ball.angular_velocity = 1.5;
+        ball.collision_frames = 3;
+        let mut plank = RigidBody::new_rectangle(7, Vec2::new(600.0, 700.0), 300.0, 20.0, 10.0);
+        plank.angle = 0.25;
+        plank.body_type = BodyType::Static;
+        plank.material = Material {
+            restitution: 0.1,
+            friction: 0.9,
+        };
+        WorldState {
+            server_time: 12.345678901,
+            tick,
+            paused: true,
+            time_scale: 0.5,
+            bodies: vec![ball, plank],
+            labels: vec![
+                Label {
+                    id: 1,
+                    text: "小球 A".to_string(),
+                    anchor: LabelAnchor::Body {
+                        body_id: 1,
+                        offset: Vec2::new(0.0, -40.0),
+                    },
+                },
+                Label {
+                    id: 2,
+                    text: "地面".to_string(),
+                    anchor: LabelAnchor::Point {
+                        position: Vec2::new(600.0, 780.0),
+                    },
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn encode(state: &WorldState) -> Vec<u8> {
+        let mut encoder = Encoder::default();
+        encoder.encode_state(state);
+        encoder.0
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        let state = sample_state(42);
+        let decoded = Decoder(&encode(&state)).decode_state().unwrap();
+        assert_eq!(
+            serde_json::to_value(&decoded).unwrap(),
+            serde_json::to_value(&state).unwrap()
+        );
+    }
+
+    #[test]
+    fn truncated_frame_is_rejected() {
+        let data = encode(&sample_state(42));
+        for len in [0, 1, data.len() / 2, data.len() - 1] {
+            assert!(Decoder(&data[..len]).decode_state().is_none(), "截断到 {} 字节", len);
+        }
+    }
+
+    #[test]
+    fn load_ignores_incomplete_last_frame() {
+        let path = std::env::temp_dir().join(format!("psrec-test-{}.rec", std::process::id()));
+        let mut recorder = StateRecorder::create(&path).unwrap();
+        recorder.record(&sample_state(1)).unwrap();
+        recorder.record(&sample_state(2)).unwrap();
+        drop(recorder);
+        // 模拟录制时被强制关闭：最后一帧只写了一半
+        let mut data = std::fs::read(&path).unwrap();
+        let partial = encode(&sample_state(3));
+        data.extend_from_slice(&(partial.len() as u32).to_le_bytes());
+        data.extend_from_slice(&partial[..partial.len() / 2]);
+        std::fs::write(&path, data).unwrap();
+
+        let frames = load(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        let ticks: Vec<u64> = frames.iter().map(|f| f.tick).collect();
+        assert_eq!(ticks, vec![1, 2]);
+    }
+}
@@ -0,0 +1,206 @@
+use common::lock::LockExt;
+use common::transport::{
+    decode_packet, encode_packet, Delivery, LossySocket, MessageReceiver, MessageSender, ReliableState, TransportKind,
+    HANDSHAKE, HEADER_LEN, MAINTENANCE_INTERVAL, MAX_DATAGRAM, PACKET_DISCONNECT, PACKET_KEEPALIVE, PEER_TIMEOUT,
+};
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct Connection {
+    pub sender: Box<dyn MessageSender>,
+    pub receiver: Box<dyn MessageReceiver>,
+}
+
+pub fn connect(kind: TransportKind, addr: &str, packet_loss: f32) -> io::Result<Connection> {
+    match kind {
+        TransportKind::Tcp => {
+            let stream = TcpStream::connect(addr)?;
+            let _ = stream.set_nodelay(true);
+            let reader = BufReader::new(stream.try_clone()?);
+            Ok(Connection {
+                sender: Box::new(TcpSender { stream }),
+                receiver: Box::new(TcpReceiver { reader }),
+            })
+        }
+        TransportKind::Udp => connect_udp(addr, packet_loss),
+    }
+}
+
+// ---------------- TCP ----------------
+
+struct TcpSender {
+    stream: TcpStream,
+}
+
+impl MessageSender for TcpSender {
+    fn send(&mut self, message: &str, _delivery: Delivery) -> io::Result<()> {
+        self.stream.write_all(message.as_bytes())?;
+        self.stream.write_all(b"\n")?;
+        self.stream.flush()
+    }
+
+    fn close(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+struct TcpReceiver {
+    reader: BufReader<TcpStream>,
+}
+
+impl MessageReceiver for TcpReceiver {
+    fn recv(&mut self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            if !line.trim().is_empty() {
+                return Ok(Some(line.trim().to_string()));
+            }
+        }
+    }
+}
+
+// ---------------- UDP ----------------
+
+// UDP 客户端：发送端与接收端共享同一个套接字和可靠性状态
+struct UdpShared {
+    socket: LossySocket,
+    server: SocketAddr,
+    state: Mutex<ReliableState>,
+    closed: AtomicBool,
+}
+
+fn connect_udp(addr: &str, packet_loss: f32) -> io::Result<Connection> {
+    let server = addr
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "无法解析服务器地址"))?;
+    let bind_addr = if server.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" };
+    let socket = UdpSocket::bind(bind_addr)?;
+    let recv_socket = socket.try_clone()?;
+    recv_socket.set_read_timeout(Some(Duration::from_secs(1)))?;
+
+    let shared = Arc::new(UdpShared {
+        socket: LossySocket::new(socket, packet_loss),
+        server,
+        state: Mutex::new(ReliableState::new()),
+        closed: AtomicBool::new(false),
+    });
+    // 先发一个带握手内容的保活包，让服务器登记这个对端。之后的保活包也带着它，第一个包丢失时服务器仍能登记
+    shared.socket.send_to(&encode_packet(PACKET_KEEPALIVE, 0, HANDSHAKE), server)?;
+
+    let weak = Arc::downgrade(&shared);
+    thread::spawn(move || {
+        udp_maintenance_loop(weak);
+    });
+
+    Ok(Connection {
+        sender: Box::new(UdpClientSender { shared: shared.clone() }),
+        receiver: Box::new(UdpClientReceiver {
+            socket: recv_socket,
+            shared,
+            queue: VecDeque::new(),
+        }),
+    })
+}
+
+// 定时重发未确认的可靠消息并发送保活包，连接释放后自动退出
+fn udp_maintenance_loop(shared: Weak<UdpShared>) {
+    let mut last_keepalive = Instant::now();
+    loop {
+        thread::sleep(MAINTENANCE_INTERVAL);
+        let Some(shared) = shared.upgrade() else {
+            break;
+        };
+        let now = Instant::now();
+        let resends = shared.state.locked().due_resends(now);
+        for packet in resends {
+            let _ = shared.socket.send_to(&packet, shared.server);
+        }
+        if now.duration_since(last_keepalive) >= KEEPALIVE_INTERVAL {
+            last_keepalive = now;
+            let _ = shared.socket.send_to(&encode_packet(PACKET_KEEPALIVE, 0, HANDSHAKE), shared.server);
+        }
+    }
+}
+
+struct UdpClientSender {
+    shared: Arc<UdpShared>,
+}
+
+impl MessageSender for UdpClientSender {
+    fn send(&mut self, message: &str, delivery: Delivery) -> io::Result<()> {
+        let packets = self.shared.state.locked().wrap(message, delivery)?;
+        for packet in &packets {
+            self.shared.socket.send_to(packet, self.shared.server)?;
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) {
+        if !self.shared.closed.swap(true, Ordering::SeqCst) {
+            let _ = self
+                .shared
+                .socket
+                .send_to(&encode_packet(PACKET_DISCONNECT, 0, &[]), self.shared.server);
+        }
+    }
+}
+
+impl Drop for UdpClientSender {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+struct UdpClientReceiver {
+    socket: UdpSocket,
+    shared: Arc<UdpShared>,
+    queue: VecDeque<String>,
+}
+
+impl MessageReceiver for UdpClientReceiver {
+    fn recv(&mut self) -> io::Result<Option<String>> {
+        let mut buf = vec![0u8; MAX_DATAGRAM + HEADER_LEN];
+        loop {
+            if let Some(message) = self.queue.pop_front() {
+                return Ok(Some(message));
+            }
+            if self.shared.closed.load(Ordering::SeqCst) {
+                return Ok(None);
+            }
+            let len = match self.socket.recv_from(&mut buf) {
+                Ok((len, from)) if from == self.shared.server => len,
+                Ok(_) => continue,
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                    if self.shared.state.locked().last_heard().elapsed() > PEER_TIMEOUT {
+                        return Ok(None);
+                    }
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let Some((kind, seq, payload)) = decode_packet(&buf[..len]) else {
+                continue;
+            };
+            if kind == PACKET_DISCONNECT {
+                return Ok(None);
+            }
+            let (delivered, ack) = self.shared.state.locked().receive(kind, seq, payload);
+            if let Some(ack) = ack {
+                let _ = self.shared.socket.send_to(&ack, self.shared.server);
+            }
+            self.queue.extend(delivered);
+        }
+    }
+}
@@ -0,0 +1,6 @@
+// 客户端和服务器共用的部分：协议消息、物理模拟和 UDP 可靠传输。
+// 客户端的本地预测与服务器运行同一份物理步，不会因为两份拷贝各自修改而不一致；
+// 两端的 UDP 分片、确认和重发也是同一份实现
+pub mod lock;
+pub mod physics;
+pub mod transport;
@@ -0,0 +1,13 @@
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+// 加锁时忽略中毒标记：一个线程持锁时 panic 不应拖垮所有使用这把锁的线程。
+// 锁内的数据在每次修改完成后才会被其他线程看到，中毒后继续使用不会读到半途的状态
+pub trait LockExt<T> {
+    fn locked(&self) -> MutexGuard<'_, T>;
+}
+
+impl<T> LockExt<T> for Mutex<T> {
+    fn locked(&self) -> MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
@@ -0,0 +1,1023 @@
+use serde::{Deserialize, Serialize};
+
+// 世界边界，物体在此范围内运动，客户端按此大小绘制
+pub const WORLD_WIDTH: f32 = 1200.0;
+pub const WORLD_HEIGHT: f32 = 800.0;
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Vec2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+#[allow(dead_code)]
+impl Vec2 {
+    pub fn new(x: f32, y: f32) -> Self {
+        Self { x, y }
+    }
+    
+    pub fn zero() -> Self {
+        Self { x: 0.0, y: 0.0 }
+    }
+    
+    pub fn length(&self) -> f32 {
+        (self.x * self.x + self.y * self.y).sqrt()
+    }
+
+    pub fn normalize(&self) -> Vec2 {
+        let len = self.length();
+        if len > 0.0 {
+            Vec2::new(self.x / len, self.y / len)
+        } else {
+            Vec2::zero()
+        }
+    }
+
+    pub fn dot(&self, other: Vec2) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+
+    pub fn rotate(&self, angle: f32) -> Vec2 {
+        let cos = angle.cos();
+        let sin = angle.sin();
+        Vec2::new(
+            self.x * cos - self.y * sin,
+            self.x * sin + self.y * cos
+        )
+    }
+}
+
+impl std::ops::Add for Vec2 {
+    type Output = Self;
+    
+    fn add(self, other: Self) -> Self {
+        Self {
+            x: self.x + other.x,
+            y: self.y + other.y,
+        }
+    }
+}
+
+impl std::ops::Sub for Vec2 {
+    type Output = Self;
+    
+    fn sub(self, other: Self) -> Self {
+        Self {
+            x: self.x - other.x,
+            y: self.y - other.y,
+        }
+    }
+}
+
+impl std::ops::Mul<f32> for Vec2 {
+    type Output = Self;
+    
+    fn mul(self, scalar: f32) -> Self {
+        Self {
+            x: self.x * scalar,
+            y: self.y * scalar,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Shape {
+    Circle { radius: f32 },
+    Rectangle { width: f32, height: f32 },
+}
+
+// 材质：弹性系数与摩擦系数
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Material {
+    pub restitution: f32,
+    pub friction: f32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            restitution: 0.8,
+            friction: 0.0,
+        }
+    }
+}
+
+// 动态物体受重力和碰撞影响；运动学物体按自身速度运动但不受力；静态物体固定不动
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum BodyType {
+    #[default]
+    Dynamic,
+    Kinematic,
+    Static,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RigidBody {
+    pub id: u32,
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub shape: Shape,
+    pub mass: f32,
+    pub angle: f32,        // 角度（弧度）
+    pub angular_velocity: f32, // 角速度
+    pub collision_frames: u8, // 碰撞特效帧数
+    #[serde(default)]
+    pub owner: Option<usize>, // 创建者的客户端 ID，None 表示场景自带
+    #[serde(default)]
+    pub material: Material,
+    #[serde(default)]
+    pub body_type: BodyType,
+    #[serde(default)]
+    pub inertia: f32, // 转动惯量，由质量和形状推导
+}
+
+// 修改已有物体的属性，未给出的字段保持不变
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BodyProperties {
+    pub position: Option<Vec2>,
+    pub angle: Option<f32>,
+    pub velocity: Option<Vec2>,
+    pub angular_velocity: Option<f32>,
+    pub mass: Option<f32>,
+    pub shape: Option<Shape>,
+    pub material: Option<Material>,
+    pub body_type: Option<BodyType>,
+}
+
+#[allow(dead_code)]
+impl RigidBody {
+    pub fn new_circle(id: u32, position: Vec2, radius: f32, mass: f32) -> Self {
+        Self {
+            id,
+            position,
+            velocity: Vec2::zero(),
+            shape: Shape::Circle { radius },
+            mass,
+            angle: 0.0,
+            angular_velocity: 0.0,
+            collision_frames: 0,
+            owner: None,
+            material: Material::default(),
+            body_type: BodyType::Dynamic,
+            inertia: 0.0,
+        }
+        .with_inertia()
+    }
+
+    pub fn new_rectangle(id: u32, position: Vec2, width: f32, height: f32, mass: f32) -> Self {
+        Self {
+            id,
+            position,
+            velocity: Vec2::zero(),
+            shape: Shape::Rectangle { width, height },
+            mass,
+            angle: 0.0,
+            angular_velocity: 0.0,
+            collision_frames: 0,
+            owner: None,
+            material: Material::default(),
+            body_type: BodyType::Dynamic,
+            inertia: 0.0,
+        }
+        .with_inertia()
+    }
+
+    fn with_inertia(mut self) -> Self {
+        self.update_inertia();
+        self
+    }
+
+    // 质量或形状变化后重新计算转动惯量
+    pub fn update_inertia(&mut self) {
+        self.inertia = match self.shape {
+            Shape::Circle { radius } => 0.5 * self.mass * radius * radius,
+            Shape::Rectangle { width, height } => self.mass * (width * width + height * height) / 12.0,
+        };
+    }
+
+    // 只有动态物体会被冲量推动，其余视为质量无穷大
+    pub fn inverse_mass(&self) -> f32 {
+        match self.body_type {
+            BodyType::Dynamic => 1.0 / self.mass,
+            BodyType::Kinematic | BodyType::Static => 0.0,
+        }
+    }
+
+    // 平动动能与转动动能之和
+    pub fn kinetic_energy(&self) -> f32 {
+        let speed = self.velocity.length();
+        0.5 * self.mass * speed * speed + 0.5 * self.inertia * self.angular_velocity * self.angular_velocity
+    }
+
+    // 重力势能，以世界底边为零点（y 轴向下）
+    pub fn potential_energy(&self, gravity: f32) -> f32 {
+        self.mass * gravity * (WORLD_HEIGHT - self.position.y)
+    }
+
+    pub fn momentum(&self) -> Vec2 {
+        self.velocity * self.mass
+    }
+
+    // 绕世界原点的角动量：质心运动的 r × p 加上自转 Iω
+    pub fn angular_momentum(&self) -> f32 {
+        let p = self.momentum();
+        self.position.x * p.y - self.position.y * p.x + self.inertia * self.angular_velocity
+    }
+
+    pub fn apply_properties(&mut self, properties: &BodyProperties) {
+        if let Some(position) = properties.position {
+            self.position = position;
+        }
+        if let Some(angle) = properties.angle {
+            self.angle = angle;
+        }
+        if let Some(velocity) = properties.velocity {
+            self.velocity = velocity;
+        }
+        if let Some(angular_velocity) = properties.angular_velocity {
+            self.angular_velocity = angular_velocity;
+        }
+        if let Some(mass) = properties.mass {
+            self.mass = mass;
+        }
+        if let Some(shape) = properties.shape {
+            self.shape = shape;
+        }
+        if let Some(material) = properties.material {
+            self.material = material;
+        }
+        if let Some(body_type) = properties.body_type {
+            self.body_type = body_type;
+        }
+        if self.body_type == BodyType::Static {
+            self.velocity = Vec2::zero();
+            self.angular_velocity = 0.0;
+        }
+        self.update_inertia();
+    }
+
+    pub fn radius(&self) -> f32 {
+        match self.shape {
+            Shape::Circle { radius } => radius,
+            Shape::Rectangle { width, height } => (width * width + height * height).sqrt() / 2.0,
+        }
+    }
+
+    pub fn width(&self) -> f32 {
+        match self.shape {
+            Shape::Circle { radius } => radius * 2.0,
+            Shape::Rectangle { width, .. } => width,
+        }
+    }
+
+    pub fn height(&self) -> f32 {
+        match self.shape {
+            Shape::Circle { radius } => radius * 2.0,
+            Shape::Rectangle { height, .. } => height,
+        }
+    }
+
+    // 获取矩形的四个角点（考虑旋转）
+    pub fn get_corners(&self) -> Option<[Vec2; 4]> {
+        match self.shape {
+            Shape::Rectangle { width, height } => {
+                let half_width = width / 2.0;
+                let half_height = height / 2.0;
+                
+                // 矩形的四个角（未旋转）
+                let corners = [
+                    Vec2::new(-half_width, -half_height),
+                    Vec2::new(half_width, -half_height),
+                    Vec2::new(half_width, half_height),
+                    Vec2::new(-half_width, half_height),
+                ];
+                
+                // 应用旋转
+                let rotated_corners = [
+                    corners[0].rotate(self.angle) + self.position,
+                    corners[1].rotate(self.angle) + self.position,
+                    corners[2].rotate(self.angle) + self.position,
+                    corners[3].rotate(self.angle) + self.position,
+                ];
+                
+                Some(rotated_corners)
+            }
+            Shape::Circle { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorldState {
+    pub bodies: Vec<RigidBody>,
+    #[serde(default)]
+    pub tick: u64, // 模拟步数
+    #[serde(default)]
+    pub server_time: f64, // 服务器发出快照时的时间（秒，自服务器启动起）
+    #[serde(default)]
+    pub paused: bool,
+    #[serde(default = "default_time_scale")]
+    pub time_scale: f32, // 模拟时间与真实时间之比，小于 1 为慢放
+    #[serde(default)]
+    pub pending_steps: u32, // 暂停时还需单步执行的步数
+    #[serde(default)]
+    pub rewinds: u32, // 世界被回退的次数，客户端据此丢弃回退前的插值和拖尾
+    #[serde(default)]
+    pub labels: Vec<Label>, // 教师添加的文字标注
+    #[serde(default)]
+    pub next_id: u32, // 下一个新物体的 ID，只增不减，删除的物体的 ID 不会再分配给新物体
+    #[serde(default)]
+    pub joints: Vec<Joint>,
+    #[serde(default)]
+    pub force_fields: Vec<ForceField>,
+    #[serde(default)]
+    pub params: WorldParams,
+}
+
+// 世界参数，由服务器配置决定，随快照下发以便客户端预测使用相同的参数
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorldParams {
+    pub gravity: f32,         // 竖直向下的重力加速度
+    pub linear_damping: f32,  // 每步保留的速度比例
+    pub angular_damping: f32, // 每步保留的角速度比例
+}
+
+impl Default for WorldParams {
+    fn default() -> Self {
+        Self {
+            gravity: 98.0,
+            linear_damping: 0.995,
+            angular_damping: 0.99,
+        }
+    }
+}
+
+// 标注固定在物体上（随物体移动）或世界中的某一点
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum LabelAnchor {
+    Body { body_id: u32, offset: Vec2 },
+    Point { position: Vec2 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Label {
+    pub id: u32,
+    pub text: String,
+    pub anchor: LabelAnchor,
+}
+
+// 两个物体之间的距离约束，像一根没有质量的轻杆保持两者中心的距离不变。
+// 一端连在静态物体上就是单摆
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Joint {
+    pub body_a: u32,
+    pub body_b: u32,
+    pub length: f32,
+}
+
+// 矩形区域内的恒定加速度，叠加在重力上，例如风或水流。只作用于中心在区域内的动态物体
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ForceField {
+    pub min: Vec2,
+    pub max: Vec2,
+    pub acceleration: Vec2,
+}
+
+impl ForceField {
+    pub fn contains(&self, point: Vec2) -> bool {
+        (self.min.x..=self.max.x).contains(&point.x) && (self.min.y..=self.max.y).contains(&point.y)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub client_id: usize,
+    pub name: String,
+    pub text: String,
+    pub server_time: f64,
+}
+
+// 客户端可以订阅的附加数据，默认不发送
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Topic {
+    // 每个周期的能量和动量
+    Diagnostics,
+    // 物体之间的接触事件
+    Contacts,
+}
+
+// 两个物体在一步中的接触。法线从 body_b 指向 body_a；冲量只算法向分量，物体分离时为 0；
+// 相对速度是碰撞处理前两者速度差的大小
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Contact {
+    pub body_a: u32,
+    pub body_b: u32,
+    pub point: Vec2,
+    pub normal: Vec2,
+    pub normal_impulse: f32,
+    pub relative_speed: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContactPhase {
+    Begin,
+    Persist,
+    // 上一周期接触、本周期已分开，数据沿用最后一次接触，冲量为 0
+    End,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContactEvent {
+    pub phase: ContactPhase,
+    #[serde(flatten)]
+    pub contact: Contact,
+}
+
+// 单个动态物体的能量和动量。角动量绕世界原点（左上角）计算，包括自转部分
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BodyDiagnostics {
+    pub id: u32,
+    pub kinetic: f32,
+    pub potential: f32,
+    pub momentum: Vec2,
+    pub angular_momentum: f32,
+}
+
+// 整个世界的能量和动量，只统计动态物体
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Diagnostics {
+    pub tick: u64,
+    pub server_time: f64,
+    pub kinetic: f32,
+    pub potential: f32,
+    pub total: f32,
+    pub momentum: Vec2,
+    pub angular_momentum: f32,
+    pub bodies: Vec<BodyDiagnostics>,
+}
+
+fn default_time_scale() -> f32 {
+    1.0
+}
+
+impl Default for WorldState {
+    fn default() -> Self {
+        Self {
+            bodies: Vec::new(),
+            tick: 0,
+            server_time: 0.0,
+            paused: false,
+            time_scale: default_time_scale(),
+            pending_steps: 0,
+            rewinds: 0,
+            labels: Vec::new(),
+            next_id: 0,
+            joints: Vec::new(),
+            force_fields: Vec::new(),
+            params: WorldParams::default(),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl WorldState {
+    // 统计动态物体的能量和动量，静止和运动学物体视为质量无穷大，不参与守恒
+    pub fn diagnostics(&self) -> Diagnostics {
+        let bodies: Vec<BodyDiagnostics> = self
+            .bodies
+            .iter()
+            .filter(|b| b.body_type == BodyType::Dynamic)
+            .map(|b| BodyDiagnostics {
+                id: b.id,
+                kinetic: b.kinetic_energy(),
+                potential: b.potential_energy(self.params.gravity),
+                momentum: b.momentum(),
+                angular_momentum: b.angular_momentum(),
+            })
+            .collect();
+        let kinetic = bodies.iter().map(|b| b.kinetic).sum();
+        let potential = bodies.iter().map(|b| b.potential).sum();
+        Diagnostics {
+            tick: self.tick,
+            server_time: self.server_time,
+            kinetic,
+            potential,
+            total: kinetic + potential,
+            momentum: bodies.iter().fold(Vec2::zero(), |sum, b| sum + b.momentum),
+            angular_momentum: bodies.iter().map(|b| b.angular_momentum).sum(),
+            bodies,
+        }
+    }
+
+    // 标注所在位置，固定的物体已被删除时返回 None
+    pub fn label_position(&self, label: &Label) -> Option<Vec2> {
+        match label.anchor {
+            LabelAnchor::Body { body_id, offset } => self
+                .bodies
+                .iter()
+                .find(|b| b.id == body_id)
+                .map(|b| b.position + offset),
+            LabelAnchor::Point { position } => Some(position),
+        }
+    }
+
+    // 为新物体分配 ID。next_id 比已有物体的 ID 小时（例如手写的场景）从最大 ID 之后开始
+    pub fn allocate_body_id(&mut self) -> u32 {
+        let after_max = self.bodies.iter().map(|b| b.id).max().map_or(1, |id| id + 1);
+        let id = self.next_id.max(after_max);
+        self.next_id = id + 1;
+        id
+    }
+
+    // 删除固定在已不存在物体上的标注
+    pub fn prune_labels(&mut self) {
+        let labels = std::mem::take(&mut self.labels);
+        self.labels = labels
+            .into_iter()
+            .filter(|label| self.label_position(label).is_some())
+            .collect();
+    }
+
+    // 删除连着已不存在物体的关节
+    pub fn prune_joints(&mut self) {
+        let bodies = &self.bodies;
+        self.joints
+            .retain(|joint| [joint.body_a, joint.body_b].iter().all(|id| bodies.iter().any(|b| b.id == *id)));
+    }
+
+    // 推进一个固定时间步：积分、边界碰撞、物体间碰撞、关节约束，返回这一步中物体之间的接触
+    pub fn step(&mut self, dt: f32) -> Vec<Contact> {
+        let mut contacts = Vec::new();
+        for body in &mut self.bodies {
+            if body.body_type == BodyType::Static {
+                if body.collision_frames > 0 {
+                    body.collision_frames -= 1;
+                }
+                continue;
+            }
+            let restitution = body.material.restitution;
+            // 重力和力场
+            if body.body_type == BodyType::Dynamic {
+                body.velocity.y += self.params.gravity * dt;
+                for field in self.force_fields.iter().filter(|f| f.contains(body.position)) {
+                    body.velocity = body.velocity + field.acceleration * dt;
+                }
+            }
+            // 更新位置
+            body.position = body.position + body.velocity * dt;
+            // 更新角度
+            body.angle += body.angular_velocity * dt;
+            // 边界碰撞检测 - 根据形状类型
+            match body.shape {
+                Shape::Circle { radius } => {
+                    if body.position.x - radius < 0.0 {
+                        body.position.x = radius;
+                        body.velocity.x = -body.velocity.x * restitution;
+                    } else if body.position.x + radius > WORLD_WIDTH {
+                        body.position.x = WORLD_WIDTH - radius;
+                        body.velocity.x = -body.velocity.x * restitution;
+                    }
+                    if body.position.y - radius < 0.0 {
+                        body.position.y = radius;
+                        body.velocity.y = -body.velocity.y * restitution;
+                    } else if body.position.y + radius > WORLD_HEIGHT {
+                        body.position.y = WORLD_HEIGHT - radius;
+                        body.velocity.y = -body.velocity.y * restitution;
+                    }
+                }
+                Shape::Rectangle { width, height } => {
+                    let half_width = width / 2.0;
+                    let half_height = height / 2.0;
+                    if body.position.x - half_width < 0.0 {
+                        body.position.x = half_width;
+                        body.velocity.x = -body.velocity.x * restitution;
+                        body.angular_velocity += body.velocity.y * 0.01;
+                    } else if body.position.x + half_width > WORLD_WIDTH {
+                        body.position.x = WORLD_WIDTH - half_width;
+                        body.velocity.x = -body.velocity.x * restitution;
+                        body.angular_velocity += body.velocity.y * 0.01;
+                    }
+                    if body.position.y - half_height < 0.0 {
+                        body.position.y = half_height;
+                        body.velocity.y = -body.velocity.y * restitution;
+                        body.angular_velocity += body.velocity.x * 0.01;
+                    } else if body.position.y + half_height > WORLD_HEIGHT {
+                        body.position.y = WORLD_HEIGHT - half_height;
+                        body.velocity.y = -body.velocity.y * restitution;
+                        body.angular_velocity += body.velocity.x * 0.01;
+                    }
+                }
+            }
+            // 阻尼
+            if body.material.friction > 0.0 && body.body_type == BodyType::Dynamic && touches_floor(body) {
+                // 贴地时摩擦力使水平速度衰减
+                body.velocity.x *= (1.0 - body.material.friction * dt * 10.0).max(0.0);
+            }
+            body.velocity = body.velocity * self.params.linear_damping;
+            body.angular_velocity *= self.params.angular_damping; // 角速度阻尼
+            // 碰撞特效帧数递减
+            if body.collision_frames > 0 {
+                body.collision_frames -= 1;
+            }
+        }
+        
+        // 简化的碰撞检测
+        let body_count = self.bodies.len();
+        for i in 0..body_count {
+            for j in i + 1..body_count {
+                let pos_i = self.bodies[i].position;
+                let pos_j = self.bodies[j].position;
+                let vel_i = self.bodies[i].velocity;
+                let vel_j = self.bodies[j].velocity;
+                let inv_mass_i = self.bodies[i].inverse_mass();
+                let inv_mass_j = self.bodies[j].inverse_mass();
+                if inv_mass_i == 0.0 && inv_mass_j == 0.0 {
+                    continue;
+                }
+                let material_i = self.bodies[i].material;
+                let material_j = self.bodies[j].material;
+                let shape_i = self.bodies[i].shape;
+                let shape_j = self.bodies[j].shape;
+                let (min_i, max_i) = get_bounding_box_from_data(pos_i, shape_i);
+                let (min_j, max_j) = get_bounding_box_from_data(pos_j, shape_j);
+                if max_i.x >= min_j.x && min_i.x <= max_j.x &&
+                   max_i.y >= min_j.y && min_i.y <= max_j.y {
+                    let normal = (pos_i - pos_j).normalize();
+                    let overlap = calculate_overlap_from_data(pos_i, shape_i, pos_j, shape_j);
+                    if overlap > 0.0 {
+                        // 设置碰撞特效帧数
+                        self.bodies[i].collision_frames = 10;
+                        self.bodies[j].collision_frames = 10;
+                        // 按质量倒数分配位置修正，静态物体不动
+                        let inv_mass_sum = inv_mass_i + inv_mass_j;
+                        self.bodies[i].position = self.bodies[i].position + normal * (overlap * inv_mass_i / inv_mass_sum);
+                        self.bodies[j].position = self.bodies[j].position - normal * (overlap * inv_mass_j / inv_mass_sum);
+                        let relative_velocity = vel_i - vel_j;
+                        let velocity_along_normal = relative_velocity.x * normal.x + relative_velocity.y * normal.y;
+                        // 接触点取两个表面之间的中点
+                        let surface_i = pos_i - normal * support_extent(shape_i, normal);
+                        let surface_j = pos_j + normal * support_extent(shape_j, normal);
+                        let mut contact = Contact {
+                            body_a: self.bodies[i].id,
+                            body_b: self.bodies[j].id,
+                            point: (surface_i + surface_j) * 0.5,
+                            normal,
+                            normal_impulse: 0.0,
+                            relative_speed: relative_velocity.length(),
+                        };
+                        if velocity_along_normal > 0.0 {
+                            contacts.push(contact);
+                            continue;
+                        }
+                        let restitution = material_i.restitution.min(material_j.restitution);
+                        let mut impulse_magnitude = -(1.0 + restitution) * velocity_along_normal;
+                        impulse_magnitude /= inv_mass_sum;
+                        contact.normal_impulse = impulse_magnitude;
+                        contacts.push(contact);
+                        let mut impulse = normal * impulse_magnitude;
+                        // 库仑摩擦：切向冲量不超过法向冲量乘以摩擦系数
+                        let friction = (material_i.friction * material_j.friction).sqrt();
+                        if friction > 0.0 {
+                            let tangent = relative_velocity - normal * velocity_along_normal;
+                            let tangent_speed = tangent.length();
+                            if tangent_speed > 0.0 {
+                                let tangent_magnitude = (tangent_speed / inv_mass_sum).min(friction * impulse_magnitude);
+                                impulse = impulse - tangent * (tangent_magnitude / tangent_speed);
+                            }
+                        }
+                        self.bodies[i].velocity = self.bodies[i].velocity + impulse * inv_mass_i;
+                        self.bodies[j].velocity = self.bodies[j].velocity - impulse * inv_mass_j;
+                        // 真实角冲量计算（仅对矩形，近似碰撞点在边缘）
+                        for (idx, impulse_sign) in [(i, 1.0), (j, -1.0)] {
+                            let body = &mut self.bodies[idx];
+                            if body.body_type != BodyType::Dynamic {
+                                continue;
+                            }
+                            if let Shape::Rectangle { width, height } = body.shape {
+                                let r = (pos_j - pos_i).normalize() * (width.min(height) / 2.0);
+                                let tau = r.x * impulse.y - r.y * impulse.x;
+                                if body.inertia > 0.0 {
+                                    body.angular_velocity += impulse_sign * tau / body.inertia;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        self.solve_joints();
+        contacts
+    }
+
+    // 沿关节方向按质量倒数修正两端的位置，并消去沿该方向的相对速度
+    fn solve_joints(&mut self) {
+        for joint in &self.joints {
+            let Some(a) = self.bodies.iter().position(|b| b.id == joint.body_a) else {
+                continue;
+            };
+            let Some(b) = self.bodies.iter().position(|b| b.id == joint.body_b) else {
+                continue;
+            };
+            let inv_mass_a = self.bodies[a].inverse_mass();
+            let inv_mass_b = self.bodies[b].inverse_mass();
+            let inv_mass_sum = inv_mass_a + inv_mass_b;
+            let delta = self.bodies[b].position - self.bodies[a].position;
+            let distance = delta.length();
+            if inv_mass_sum == 0.0 || distance == 0.0 {
+                continue;
+            }
+            let direction = delta * (1.0 / distance);
+            let error = distance - joint.length;
+            self.bodies[a].position = self.bodies[a].position + direction * (error * inv_mass_a / inv_mass_sum);
+            self.bodies[b].position = self.bodies[b].position - direction * (error * inv_mass_b / inv_mass_sum);
+            let relative_speed = (self.bodies[b].velocity - self.bodies[a].velocity).dot(direction);
+            let impulse = direction * (relative_speed / inv_mass_sum);
+            self.bodies[a].velocity = self.bodies[a].velocity + impulse * inv_mass_a;
+            self.bodies[b].velocity = self.bodies[b].velocity - impulse * inv_mass_b;
+        }
+    }
+}
+
+fn touches_floor(body: &RigidBody) -> bool {
+    let (_, max) = get_bounding_box_from_data(body.position, body.shape);
+    max.y >= WORLD_HEIGHT - 0.5
+}
+
+fn get_bounding_box_from_data(position: Vec2, shape: Shape) -> (Vec2, Vec2) {
+    match shape {
+        Shape::Circle { radius } => {
+            let min = Vec2::new(position.x - radius, position.y - radius);
+            let max = Vec2::new(position.x + radius, position.y + radius);
+            (min, max)
+        }
+        Shape::Rectangle { width, height } => {
+            // 简化的包围盒（不考虑旋转）
+            let half_width = width / 2.0;
+            let half_height = height / 2.0;
+            let min = Vec2::new(position.x - half_width, position.y - half_height);
+            let max = Vec2::new(position.x + half_width, position.y + half_height);
+            (min, max)
+        }
+    }
+}
+
+// 形状沿 direction 方向从中心到边界的距离（矩形按包围盒计算）
+fn support_extent(shape: Shape, direction: Vec2) -> f32 {
+    match shape {
+        Shape::Circle { radius } => radius,
+        Shape::Rectangle { width, height } => direction.x.abs() * width / 2.0 + direction.y.abs() * height / 2.0,
+    }
+}
+
+fn calculate_overlap_from_data(pos_i: Vec2, shape_i: Shape, pos_j: Vec2, shape_j: Shape) -> f32 {
+    let (min_i, max_i) = get_bounding_box_from_data(pos_i, shape_i);
+    let (min_j, max_j) = get_bounding_box_from_data(pos_j, shape_j);
+    
+    let overlap_x = (max_i.x - min_j.x).min(max_j.x - min_i.x);
+    let overlap_y = (max_i.y - min_j.y).min(max_j.y - min_i.y);
+    
+    if overlap_x < 0.0 || overlap_y < 0.0 {
+        0.0
+    } else {
+        overlap_x.min(overlap_y)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ClientMessage {
+    Hello {
+        name: String,
+        #[serde(default)]
+        token: Option<String>,
+        #[serde(default)]
+        spectator: bool, // 观众只接收状态，不能发送命令
+        #[serde(default)]
+        color: Option<[u8; 3]>, // 光标颜色，未指定时由服务器分配
+    },
+    Ping {
+        nonce: u64,
+    },
+    ApplyImpulse {
+        body_id: u32,
+        impulse: Vec2,
+    },
+    AddRectangle {
+        position: Vec2,
+        width: f32,
+        height: f32,
+        mass: f32,
+    },
+    AddCircle {
+        position: Vec2,
+        radius: f32,
+        mass: f32,
+    },
+    ListRooms,
+    ListUsers,
+    Chat {
+        text: String,
+    },
+    AddLabel {
+        text: String,
+        anchor: LabelAnchor,
+    },
+    RemoveLabel {
+        label_id: u32,
+    },
+    // 低频上报的光标位置和正在进行的拖拽（拖拽起点）
+    UpdatePresence {
+        cursor: Vec2,
+        #[serde(default)]
+        drag_start: Option<Vec2>,
+    },
+    CreateRoom {
+        name: String,
+        #[serde(default)]
+        config: Option<RoomConfig>,
+    },
+    JoinRoom {
+        name: String,
+    },
+    SetPermissions {
+        permissions: Permissions,
+    },
+    SetPaused {
+        paused: bool,
+    },
+    StepSimulation {
+        steps: u32,
+    },
+    SetTimeScale {
+        time_scale: f32,
+    },
+    RemoveBody {
+        body_id: u32,
+    },
+    RemoveOwnedBodies,
+    SetBodyProperties {
+        body_id: u32,
+        properties: BodyProperties,
+    },
+    RemoveBodiesInRect {
+        min: Vec2,
+        max: Vec2,
+    },
+    ResetWorld,
+    // 把世界回退到历史记录中的某一周期并从那里继续（仅教师）
+    Rewind {
+        tick: u64,
+    },
+    // 服务器场景目录中的场景（仅教师）
+    SaveScene {
+        name: String,
+    },
+    LoadScene {
+        name: String,
+    },
+    ListScenes,
+    // 开始或停止接收某类附加数据
+    Subscribe {
+        topic: Topic,
+        enabled: bool,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RoomConfig {
+    pub tick_rate: f32,         // 模拟频率（Hz）
+    pub idle_timeout_secs: u64, // 房间无人后自动关闭的等待时间（秒）
+    pub spectator_rate: f32,    // 发给观众的快照频率（Hz）
+    pub permissions: Permissions,
+    // 确定性模式：命令排队到下一周期开始时按客户端 ID 顺序执行，相同场景和命令记录得到相同结果
+    pub deterministic: bool,
+    // 保留最近多少秒的世界状态用于回退，0 表示不保留
+    pub history_secs: f32,
+}
+
+impl Default for RoomConfig {
+    fn default() -> Self {
+        Self {
+            tick_rate: 60.0,
+            idle_timeout_secs: 60,
+            spectator_rate: 20.0,
+            permissions: Permissions::default(),
+            deterministic: false,
+            history_secs: 10.0,
+        }
+    }
+}
+
+// 握手时根据口令分配的角色，教师不受物体权限限制
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Role {
+    #[default]
+    Student,
+    Teacher,
+}
+
+// 谁可以对物体执行某类操作。OwnerOnly 时场景自带（无主）的物体对所有人开放
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Access {
+    Everyone,
+    OwnerOnly,
+    TeacherOnly,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Permissions {
+    pub push: Access,   // 施加冲量
+    pub edit: Access,   // 修改属性
+    pub delete: Access, // 删除
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        Self {
+            push: Access::OwnerOnly,
+            edit: Access::OwnerOnly,
+            delete: Access::OwnerOnly,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomInfo {
+    pub name: String,
+    pub clients: usize,
+    pub bodies: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserInfo {
+    pub client_id: usize,
+    pub name: String,
+    pub role: Role,
+    pub spectator: bool,
+    #[serde(default)]
+    pub color: [u8; 3],
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PresenceInfo {
+    pub client_id: usize,
+    pub cursor: Vec2,
+    pub drag_start: Option<Vec2>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ServerMessage {
+    Welcome {
+        client_id: usize,
+        role: Role,
+        #[serde(default)]
+        spectator: bool,
+    },
+    Pong {
+        nonce: u64,
+    },
+    WorldState(WorldState),
+    RoomList {
+        rooms: Vec<RoomInfo>,
+    },
+    JoinedRoom {
+        name: String,
+        #[serde(default)]
+        tick_rate: f32,
+    },
+    // 当前房间的在线用户，成员变化时推送
+    UserList {
+        users: Vec<UserInfo>,
+    },
+    Chat(ChatMessage),
+    SceneList {
+        scenes: Vec<String>,
+    },
+    SceneSaved {
+        name: String,
+    },
+    // 加入房间时补发最近的聊天记录
+    ChatHistory {
+        messages: Vec<ChatMessage>,
+    },
+    // 其他用户的光标与拖拽，定期推送
+    Presence {
+        users: Vec<PresenceInfo>,
+    },
+    PermissionsChanged {
+        permissions: Permissions,
+    },
+    // 订阅了 Diagnostics 时随快照推送
+    Diagnostics(Diagnostics),
+    // 订阅了 Contacts 时，有接触事件的周期推送
+    Contacts {
+        tick: u64,
+        events: Vec<ContactEvent>,
+    },
+    Error {
+        message: String,
+    },
+}
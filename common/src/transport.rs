@@ -0,0 +1,460 @@
+use crate::lock::LockExt;
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// 数据包类型（UDP 包头第一个字节）
+pub const PACKET_RELIABLE: u8 = 0;
+pub const PACKET_UNRELIABLE: u8 = 1;
+pub const PACKET_ACK: u8 = 2;
+pub const PACKET_DISCONNECT: u8 = 3;
+pub const PACKET_KEEPALIVE: u8 = 4;
+// 可靠消息中不是最后一片的包
+pub const PACKET_RELIABLE_PART: u8 = 5;
+// 不可靠消息的一片
+pub const PACKET_UNRELIABLE_PART: u8 = 6;
+pub const HEADER_LEN: usize = 5;
+pub const MAX_DATAGRAM: usize = 65_000;
+// 每个包的负载上限，加上 IP/UDP 包头仍小于常见的 1500 字节 MTU
+const MAX_PAYLOAD: usize = 1200;
+// 一条消息最多的分片数，也是接收端缓存乱序可靠包的窗口大小
+const MAX_FRAGMENTS: usize = 1024;
+const MAX_MESSAGE: usize = MAX_PAYLOAD * MAX_FRAGMENTS;
+const REORDER_WINDOW: u32 = MAX_FRAGMENTS as u32;
+
+const RESEND_INTERVAL: Duration = Duration::from_millis(200);
+pub const MAINTENANCE_INTERVAL: Duration = Duration::from_millis(50);
+pub const PEER_TIMEOUT: Duration = Duration::from_secs(10);
+
+// 客户端保活包的内容。服务器只为带着它的保活包登记新对端，其他来历不明的数据包一律忽略
+pub const HANDSHAKE: &[u8] = b"SANDBOX/1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportKind {
+    Tcp,
+    Udp,
+}
+
+impl TransportKind {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "tcp" => Some(TransportKind::Tcp),
+            "udp" => Some(TransportKind::Udp),
+            _ => None,
+        }
+    }
+}
+
+// 消息投递方式：TCP 下两者相同，UDP 下快照走不可靠通道，命令走可靠通道
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    Reliable,
+    Unreliable,
+}
+
+pub trait MessageSender: Send {
+    fn send(&mut self, message: &str, delivery: Delivery) -> io::Result<()>;
+    // 主动关闭连接，使对应的接收端尽快返回 Ok(None)
+    fn close(&mut self);
+}
+
+pub trait MessageReceiver: Send {
+    // 返回 Ok(None) 表示对端已断开
+    fn recv(&mut self) -> io::Result<Option<String>>;
+}
+
+// 模拟丢包的 UDP 套接字，便于在本机测试
+pub struct LossySocket {
+    socket: UdpSocket,
+    loss: f32,
+    rng: Mutex<u64>,
+}
+
+impl LossySocket {
+    pub fn new(socket: UdpSocket, loss: f32) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0x9E37_79B9_7F4A_7C15);
+        Self {
+            socket,
+            loss: loss.clamp(0.0, 1.0),
+            rng: Mutex::new(seed | 1),
+        }
+    }
+
+    fn should_drop(&self) -> bool {
+        if self.loss <= 0.0 {
+            return false;
+        }
+        let mut state = self.rng.locked();
+        // xorshift64
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        ((*state >> 40) as f32 / (1u64 << 24) as f32) < self.loss
+    }
+
+    pub fn send_to(&self, packet: &[u8], addr: SocketAddr) -> io::Result<()> {
+        if packet.len() > MAX_DATAGRAM {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "数据包过大"));
+        }
+        if self.should_drop() {
+            return Ok(());
+        }
+        self.socket.send_to(packet, addr).map(|_| ())
+    }
+}
+
+pub fn encode_packet(kind: u8, seq: u32, payload: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(HEADER_LEN + payload.len());
+    packet.push(kind);
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend_from_slice(payload);
+    packet
+}
+
+pub fn decode_packet(data: &[u8]) -> Option<(u8, u32, &[u8])> {
+    if data.len() < HEADER_LEN {
+        return None;
+    }
+    let seq = u32::from_be_bytes([data[1], data[2], data[3], data[4]]);
+    Some((data[0], seq, &data[HEADER_LEN..]))
+}
+
+struct PendingPacket {
+    packet: Vec<u8>,
+    last_sent: Instant,
+}
+
+// 正在拼装的分片不可靠消息，只保留最新的一条
+struct Assembly {
+    seq: u32,
+    parts: Vec<Option<Vec<u8>>>,
+    received: usize,
+}
+
+// 每个 UDP 对端的可靠性状态：可靠消息按序号确认、重发、按序交付；不可靠消息丢弃过期序号。
+// 超过 MAX_PAYLOAD 的消息拆成多个包，避免 IP 分片（丢一片就丢整条）
+pub struct ReliableState {
+    next_reliable_seq: u32,
+    next_unreliable_seq: u32,
+    pending: BTreeMap<u32, PendingPacket>,
+    next_expected: u32,
+    // 提前到达的可靠包：是否为消息的最后一片，以及内容
+    out_of_order: BTreeMap<u32, (bool, Vec<u8>)>,
+    // 已按序收到、尚未收到最后一片的可靠消息
+    partial: Vec<u8>,
+    // 当前可靠消息超过 MAX_MESSAGE，丢弃到它的最后一片为止
+    oversized: bool,
+    assembly: Option<Assembly>,
+    last_unreliable: Option<u32>,
+    last_heard: Instant,
+}
+
+impl Default for ReliableState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReliableState {
+    pub fn new() -> Self {
+        Self {
+            next_reliable_seq: 0,
+            next_unreliable_seq: 0,
+            pending: BTreeMap::new(),
+            next_expected: 0,
+            out_of_order: BTreeMap::new(),
+            partial: Vec::new(),
+            oversized: false,
+            assembly: None,
+            last_unreliable: None,
+            last_heard: Instant::now(),
+        }
+    }
+
+    // 把消息编码成一个或多个数据包
+    pub fn wrap(&mut self, message: &str, delivery: Delivery) -> io::Result<Vec<Vec<u8>>> {
+        let bytes = message.as_bytes();
+        if bytes.len() > MAX_MESSAGE {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "消息过大"));
+        }
+        let chunks: Vec<&[u8]> = if bytes.is_empty() {
+            vec![bytes]
+        } else {
+            bytes.chunks(MAX_PAYLOAD).collect()
+        };
+        let mut packets = Vec::with_capacity(chunks.len());
+        match delivery {
+            Delivery::Reliable => {
+                // 每一片占一个可靠序号，按序交付时依次拼接，最后一片用 PACKET_RELIABLE
+                let now = Instant::now();
+                for (i, chunk) in chunks.iter().enumerate() {
+                    let seq = self.next_reliable_seq;
+                    self.next_reliable_seq = self.next_reliable_seq.wrapping_add(1);
+                    let kind = if i + 1 == chunks.len() { PACKET_RELIABLE } else { PACKET_RELIABLE_PART };
+                    let packet = encode_packet(kind, seq, chunk);
+                    self.pending.insert(seq, PendingPacket {
+                        packet: packet.clone(),
+                        last_sent: now,
+                    });
+                    packets.push(packet);
+                }
+            }
+            Delivery::Unreliable => {
+                let seq = self.next_unreliable_seq;
+                self.next_unreliable_seq = self.next_unreliable_seq.wrapping_add(1);
+                if chunks.len() == 1 {
+                    packets.push(encode_packet(PACKET_UNRELIABLE, seq, bytes));
+                } else {
+                    // 各片共用消息的序号，负载前 4 字节是片号和总片数
+                    for (i, chunk) in chunks.iter().enumerate() {
+                        let mut payload = Vec::with_capacity(4 + chunk.len());
+                        payload.extend_from_slice(&(i as u16).to_be_bytes());
+                        payload.extend_from_slice(&(chunks.len() as u16).to_be_bytes());
+                        payload.extend_from_slice(chunk);
+                        packets.push(encode_packet(PACKET_UNRELIABLE_PART, seq, &payload));
+                    }
+                }
+            }
+        }
+        Ok(packets)
+    }
+
+    // 处理收到的数据包，返回可交付的消息和需要回复的确认包
+    pub fn receive(&mut self, kind: u8, seq: u32, payload: &[u8]) -> (Vec<String>, Option<Vec<u8>>) {
+        self.last_heard = Instant::now();
+        let mut delivered = Vec::new();
+        match kind {
+            PACKET_RELIABLE | PACKET_RELIABLE_PART => {
+                // 超出窗口的包不缓存也不确认，对端稍后会重发
+                if seq >= self.next_expected.saturating_add(REORDER_WINDOW) {
+                    return (delivered, None);
+                }
+                let ack = encode_packet(PACKET_ACK, seq, &[]);
+                if seq >= self.next_expected {
+                    self.out_of_order.insert(seq, (kind == PACKET_RELIABLE, payload.to_vec()));
+                    while let Some((last, bytes)) = self.out_of_order.remove(&self.next_expected) {
+                        self.next_expected = self.next_expected.wrapping_add(1);
+                        self.push_reliable(last, &bytes, &mut delivered);
+                    }
+                }
+                return (delivered, Some(ack));
+            }
+            PACKET_UNRELIABLE => {
+                let fresh = self.is_fresh(seq);
+                if fresh {
+                    self.last_unreliable = Some(seq);
+                    if let Ok(text) = std::str::from_utf8(payload) {
+                        delivered.push(text.to_string());
+                    }
+                }
+            }
+            PACKET_UNRELIABLE_PART => {
+                if let Some(text) = self.assemble(seq, payload) {
+                    delivered.push(text);
+                }
+            }
+            PACKET_ACK => {
+                self.pending.remove(&seq);
+            }
+            PACKET_KEEPALIVE => {}
+            _ => {}
+        }
+        (delivered, None)
+    }
+
+    fn push_reliable(&mut self, last: bool, bytes: &[u8], delivered: &mut Vec<String>) {
+        if !self.oversized {
+            self.partial.extend_from_slice(bytes);
+            if self.partial.len() > MAX_MESSAGE {
+                self.partial.clear();
+                self.oversized = true;
+            }
+        }
+        if last {
+            let message = std::mem::take(&mut self.partial);
+            if !std::mem::take(&mut self.oversized) {
+                if let Ok(text) = String::from_utf8(message) {
+                    delivered.push(text);
+                }
+            }
+        }
+    }
+
+    fn is_fresh(&self, seq: u32) -> bool {
+        self.last_unreliable.is_none_or(|last| seq > last)
+    }
+
+    // 收齐所有分片后返回整条消息。更新的消息开始到达时放弃旧的
+    fn assemble(&mut self, seq: u32, payload: &[u8]) -> Option<String> {
+        if !self.is_fresh(seq) || payload.len() < 4 {
+            return None;
+        }
+        let index = u16::from_be_bytes([payload[0], payload[1]]) as usize;
+        let count = u16::from_be_bytes([payload[2], payload[3]]) as usize;
+        if count == 0 || count > MAX_FRAGMENTS || index >= count {
+            return None;
+        }
+        match &self.assembly {
+            Some(assembly) if assembly.seq > seq => return None,
+            Some(assembly) if assembly.seq == seq && assembly.parts.len() == count => {}
+            Some(assembly) if assembly.seq == seq => return None,
+            _ => {
+                self.assembly = Some(Assembly {
+                    seq,
+                    parts: vec![None; count],
+                    received: 0,
+                });
+            }
+        }
+        let assembly = self.assembly.as_mut().unwrap();
+        if assembly.parts[index].is_none() {
+            assembly.parts[index] = Some(payload[4..].to_vec());
+            assembly.received += 1;
+        }
+        if assembly.received < count {
+            return None;
+        }
+        let assembly = self.assembly.take().unwrap();
+        self.last_unreliable = Some(seq);
+        String::from_utf8(assembly.parts.into_iter().flatten().flatten().collect()).ok()
+    }
+
+    // 最近一次收到对端数据包的时间，用于判断对端超时
+    pub fn last_heard(&self) -> Instant {
+        self.last_heard
+    }
+
+    pub fn due_resends(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        let mut resends = Vec::new();
+        for pending in self.pending.values_mut() {
+            if now.duration_since(pending.last_sent) >= RESEND_INTERVAL {
+                pending.last_sent = now;
+                resends.push(pending.packet.clone());
+            }
+        }
+        resends
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 把数据包交给接收端，确认包交回发送端
+    fn deliver(sender: &mut ReliableState, receiver: &mut ReliableState, packet: &[u8]) -> Vec<String> {
+        let (kind, seq, payload) = decode_packet(packet).unwrap();
+        let (delivered, ack) = receiver.receive(kind, seq, payload);
+        if let Some(ack) = ack {
+            let (kind, seq, payload) = decode_packet(&ack).unwrap();
+            sender.receive(kind, seq, payload);
+        }
+        delivered
+    }
+
+    // 足够长、需要拆成多个包的消息，内容可以区分
+    fn long_message(tag: char, len: usize) -> String {
+        (0..len).map(|i| if i % 97 == 0 { tag } else { 'x' }).collect()
+    }
+
+    #[test]
+    fn reliable_in_order_and_deduplicated() {
+        let (mut sender, mut receiver) = (ReliableState::new(), ReliableState::new());
+        let packets: Vec<Vec<u8>> = ["a", "b", "c"]
+            .iter()
+            .flat_map(|m| sender.wrap(m, Delivery::Reliable).unwrap())
+            .collect();
+        assert!(deliver(&mut sender, &mut receiver, &packets[2]).is_empty());
+        assert!(deliver(&mut sender, &mut receiver, &packets[1]).is_empty());
+        assert_eq!(deliver(&mut sender, &mut receiver, &packets[0]), vec!["a", "b", "c"]);
+        // 重复的包仍然确认，但不再交付
+        for packet in &packets {
+            assert!(deliver(&mut sender, &mut receiver, packet).is_empty());
+        }
+        assert!(sender.pending.is_empty());
+    }
+
+    #[test]
+    fn reliable_reassembled_after_packet_loss() {
+        let (mut sender, mut receiver) = (ReliableState::new(), ReliableState::new());
+        let messages = [long_message('a', 5000), "short".to_string(), long_message('b', MAX_PAYLOAD * 3)];
+        let mut packets = Vec::new();
+        for message in &messages {
+            packets.extend(sender.wrap(message, Delivery::Reliable).unwrap());
+        }
+        assert!(packets.len() > messages.len());
+        assert!(packets.iter().all(|p| p.len() <= HEADER_LEN + MAX_PAYLOAD));
+
+        // 每三个包丢一个，其余倒序到达
+        let mut delivered = Vec::new();
+        for packet in packets.iter().enumerate().filter(|(i, _)| i % 3 != 1).map(|(_, p)| p).rev() {
+            delivered.extend(deliver(&mut sender, &mut receiver, packet));
+        }
+        // 重发未确认的包直到全部到达
+        let mut now = Instant::now();
+        while !sender.pending.is_empty() {
+            now += RESEND_INTERVAL;
+            for packet in sender.due_resends(now) {
+                delivered.extend(deliver(&mut sender, &mut receiver, &packet));
+            }
+        }
+        assert_eq!(delivered, messages);
+    }
+
+    #[test]
+    fn reliable_packets_beyond_window_are_dropped() {
+        let mut receiver = ReliableState::new();
+        let (delivered, ack) = receiver.receive(PACKET_RELIABLE, REORDER_WINDOW, b"far");
+        assert!(delivered.is_empty());
+        assert!(ack.is_none());
+        assert!(receiver.out_of_order.is_empty());
+        let (_, ack) = receiver.receive(PACKET_RELIABLE, REORDER_WINDOW - 1, b"near");
+        assert!(ack.is_some());
+        assert_eq!(receiver.out_of_order.len(), 1);
+    }
+
+    #[test]
+    fn unreliable_fragments_reassembled_out_of_order() {
+        let (mut sender, mut receiver) = (ReliableState::new(), ReliableState::new());
+        let message = long_message('s', MAX_PAYLOAD * 4 + 10);
+        let mut packets = sender.wrap(&message, Delivery::Unreliable).unwrap();
+        assert_eq!(packets.len(), 5);
+        packets.reverse();
+        let mut delivered = Vec::new();
+        for packet in &packets {
+            delivered.extend(deliver(&mut sender, &mut receiver, packet));
+        }
+        assert_eq!(delivered, vec![message]);
+        // 重复到达的旧消息不再交付
+        for packet in &packets {
+            assert!(deliver(&mut sender, &mut receiver, packet).is_empty());
+        }
+    }
+
+    #[test]
+    fn unreliable_incomplete_message_replaced_by_newer() {
+        let (mut sender, mut receiver) = (ReliableState::new(), ReliableState::new());
+        let old = sender.wrap(&long_message('o', MAX_PAYLOAD * 2), Delivery::Unreliable).unwrap();
+        let newer = long_message('n', MAX_PAYLOAD * 2);
+        let new = sender.wrap(&newer, Delivery::Unreliable).unwrap();
+        assert_eq!((old.len(), new.len()), (2, 2));
+        // 旧消息丢了一片，新消息开始到达后旧消息的剩余分片被忽略
+        assert!(deliver(&mut sender, &mut receiver, &old[0]).is_empty());
+        assert!(deliver(&mut sender, &mut receiver, &new[0]).is_empty());
+        assert!(deliver(&mut sender, &mut receiver, &old[1]).is_empty());
+        assert_eq!(deliver(&mut sender, &mut receiver, &new[1]), vec![newer]);
+    }
+
+    #[test]
+    fn oversized_message_rejected() {
+        let mut sender = ReliableState::new();
+        let message = "x".repeat(MAX_MESSAGE + 1);
+        assert!(sender.wrap(&message, Delivery::Unreliable).is_err());
+        assert!(sender.wrap(&message, Delivery::Reliable).is_err());
+    }
+}
+
@@ -0,0 +1,118 @@
+use common::lock::LockExt;
+use common::physics::{Permissions, RoomConfig};
+use crate::room::{Room, Rooms, LOBBY};
+use crate::scene::{self, SceneFile};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const AUTOSAVE_VERSION: u32 = 1;
+
+// 自动存档：所有房间的配置、权限、模拟控制状态和场景
+#[derive(Serialize, Deserialize)]
+struct Autosave {
+    version: u32,
+    rooms: Vec<SavedRoom>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedRoom {
+    name: String,
+    config: RoomConfig,
+    permissions: Permissions,
+    paused: bool,
+    time_scale: f32,
+    // 按场景文件格式保存，恢复时走同样的迁移和校验
+    scene: serde_json::Value,
+}
+
+impl SavedRoom {
+    fn from_room(room: &Room) -> Self {
+        let world = room.world.locked();
+        Self {
+            name: room.name.clone(),
+            config: room.config.clone(),
+            permissions: room.permissions.locked().clone(),
+            paused: world.paused,
+            time_scale: world.time_scale,
+            scene: serde_json::to_value(SceneFile::from_world(&world)).unwrap(),
+        }
+    }
+}
+
+// 保存所有房间，返回保存的房间数
+pub fn save(rooms: &Rooms, path: &Path) -> Result<usize, String> {
+    let mut saved: Vec<SavedRoom> = rooms.all().iter().map(|room| SavedRoom::from_room(room)).collect();
+    saved.sort_by(|a, b| a.name.cmp(&b.name));
+    let count = saved.len();
+    let autosave = Autosave {
+        version: AUTOSAVE_VERSION,
+        rooms: saved,
+    };
+    scene::write_atomic(path, &serde_json::to_string(&autosave).unwrap())
+        .map_err(|e| format!("无法写入自动存档 {}: {}", path.display(), e))?;
+    Ok(count)
+}
+
+// 从存档恢复：大厅就地恢复，其他房间重新创建。返回新建的房间，由调用方启动模拟线程
+pub fn restore(rooms: &Rooms, path: &Path) -> Result<Vec<Arc<Room>>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("无法读取自动存档 {}: {}", path.display(), e))?;
+    let autosave: Autosave =
+        serde_json::from_str(&text).map_err(|e| format!("自动存档 {} 格式错误: {}", path.display(), e))?;
+    if autosave.version != AUTOSAVE_VERSION {
+        return Err(format!("不支持的自动存档版本 {}", autosave.version));
+    }
+
+    let mut created = Vec::new();
+    for saved in autosave.rooms {
+        let scene = match SceneFile::parse(&saved.scene.to_string(), &rooms.limits) {
+            Ok(scene) => scene,
+            Err(e) => {
+                warn!("跳过房间 {} 的存档: {}", saved.name, e);
+                continue;
+            }
+        };
+        let room = if saved.name == LOBBY {
+            rooms.get(LOBBY)
+        } else {
+            match rooms.create(&saved.name, saved.config, false, None) {
+                Ok(room) => {
+                    created.push(room.clone());
+                    Some(room)
+                }
+                Err(e) => {
+                    warn!("无法恢复房间 {}: {}", saved.name, e);
+                    None
+                }
+            }
+        };
+        let Some(room) = room else {
+            continue;
+        };
+        *room.permissions.locked() = saved.permissions;
+        let mut world = room.world.locked();
+        let restored = scene.into_world();
+        world.bodies = restored.bodies;
+        world.labels = restored.labels;
+        world.params = restored.params;
+        world.paused = saved.paused;
+        if saved.time_scale.is_finite() && saved.time_scale > 0.0 {
+            world.time_scale = saved.time_scale;
+        }
+        world.pending_steps = 0;
+    }
+    Ok(created)
+}
+
+// 每隔 interval 保存一次
+pub fn spawn(rooms: Arc<Rooms>, path: PathBuf, interval: Duration) {
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        match save(&rooms, &path) {
+            Ok(count) => debug!("已自动保存 {} 个房间到 {}", count, path.display()),
+            Err(e) => warn!("{}", e),
+        }
+    });
+}
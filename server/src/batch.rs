@@ -0,0 +1,371 @@
+use common::physics::{BodyType, ContactEvent, ContactPhase, WorldState};
+use crate::commands;
+use crate::contacts::ContactTracker;
+use crate::config::ServerConfig;
+use crate::scene;
+use crate::validation::Limits;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+// 一次扫描最多运行的次数
+const MAX_RUNS: usize = 1000;
+
+// 扫描时逐次修改的参数。世界参数直接修改，物体参数作用于所有动态物体
+#[derive(Debug, Clone, Copy)]
+pub enum SweepParam {
+    Gravity,
+    LinearDamping,
+    AngularDamping,
+    Restitution,
+    Friction,
+    Mass,
+}
+
+impl SweepParam {
+    const ALL: [SweepParam; 6] = [
+        SweepParam::Gravity,
+        SweepParam::LinearDamping,
+        SweepParam::AngularDamping,
+        SweepParam::Restitution,
+        SweepParam::Friction,
+        SweepParam::Mass,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SweepParam::Gravity => "gravity",
+            SweepParam::LinearDamping => "linear_damping",
+            SweepParam::AngularDamping => "angular_damping",
+            SweepParam::Restitution => "restitution",
+            SweepParam::Friction => "friction",
+            SweepParam::Mass => "mass",
+        }
+    }
+
+    fn apply(self, world: &mut WorldState, value: f32, limits: &Limits) -> Result<(), String> {
+        match self {
+            SweepParam::Gravity => world.params.gravity = value,
+            SweepParam::LinearDamping => world.params.linear_damping = value,
+            SweepParam::AngularDamping => world.params.angular_damping = value,
+            SweepParam::Restitution | SweepParam::Friction | SweepParam::Mass => {
+                for body in world.bodies.iter_mut().filter(|b| b.body_type == BodyType::Dynamic) {
+                    match self {
+                        SweepParam::Restitution => body.material.restitution = value,
+                        SweepParam::Friction => body.material.friction = value,
+                        _ => {
+                            body.mass = value;
+                            body.update_inertia();
+                        }
+                    }
+                    limits.check_body(body)?;
+                }
+            }
+        }
+        scene::check_world(&world.params)
+    }
+}
+
+// 参数扫描：依次取的值
+#[derive(Debug, Clone)]
+pub struct Sweep {
+    pub param: SweepParam,
+    pub values: Vec<f32>,
+}
+
+impl Sweep {
+    // 格式为 参数=起始:结束:步长（包含结束值），或 参数=取值
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let usage = || format!("扫描参数格式应为 参数=起始:结束:步长，例如 restitution=0.1:0.9:0.1: {}", spec);
+        let (name, range) = spec.split_once('=').ok_or_else(usage)?;
+        let param = SweepParam::ALL
+            .into_iter()
+            .find(|p| p.name() == name.trim())
+            .ok_or_else(|| {
+                let names: Vec<&str> = SweepParam::ALL.iter().map(|p| p.name()).collect();
+                format!("未知的扫描参数 {}，可选: {}", name.trim(), names.join(" / "))
+            })?;
+        // 用 f64 计算取值，避免 0.1 的步长累积出 0.70000005 这样的误差
+        let numbers: Vec<f64> = range
+            .split(':')
+            .map(|s| s.trim().parse().ok().filter(|v: &f64| v.is_finite()))
+            .collect::<Option<_>>()
+            .ok_or_else(usage)?;
+        let values = match numbers[..] {
+            [value] => vec![value as f32],
+            [start, end, step] => {
+                if step <= 0.0 || end < start {
+                    return Err("扫描的步长必须大于 0，结束值不能小于起始值".to_string());
+                }
+                let count = ((end - start) / step + 1e-6).floor() as usize + 1;
+                if count > MAX_RUNS {
+                    return Err(format!("一次扫描最多运行 {} 次", MAX_RUNS));
+                }
+                (0..count).map(|i| (start + i as f64 * step) as f32).collect()
+            }
+            _ => return Err(usage()),
+        };
+        Ok(Self { param, values })
+    }
+}
+
+// 在场景上依次运行每个扫描取值（不扫描时只运行一次），写出轨迹和能量
+pub fn run(config: &ServerConfig, scene: &WorldState, limits: &Limits) -> Result<(), String> {
+    let steps = config.batch.unwrap_or(0);
+    let fixed_dt = 1.0 / config.tick_rate;
+    let mut trajectory = config.trajectory.as_deref().map(Table::create).transpose()?;
+    let mut energy = config.energy.as_deref().map(Table::create).transpose()?;
+    let mut contacts = config.contacts.as_deref().map(Table::create).transpose()?;
+    let runs: Vec<Option<f32>> = match &config.sweep {
+        Some(sweep) => sweep.values.iter().copied().map(Some).collect(),
+        None => vec![None],
+    };
+
+    for (run, value) in runs.into_iter().enumerate() {
+        let mut world = scene.clone();
+        // 场景可能是暂停时保存的，批量模拟总是运行
+        world.paused = false;
+        world.pending_steps = 0;
+        let mut prefix = vec![("run", Cell::Int(run as u64))];
+        if let (Some(sweep), Some(value)) = (&config.sweep, value) {
+            sweep
+                .param
+                .apply(&mut world, value, limits)
+                .map_err(|e| format!("{} = {} 无效: {}", sweep.param.name(), value, e))?;
+            prefix.push((sweep.param.name(), Cell::Float(value)));
+        }
+
+        let started = Instant::now();
+        let start_tick = world.tick;
+        let mut tracker = ContactTracker::default();
+        loop {
+            let step = world.tick - start_tick;
+            let time = step as f32 * fixed_dt;
+            if let Some(table) = &mut trajectory {
+                write_trajectory(table, &prefix, step, time, &world)?;
+            }
+            if let Some(table) = &mut energy {
+                write_energy(table, &prefix, step, time, &world)?;
+            }
+            if step >= steps {
+                break;
+            }
+            // 与服务器的模拟循环使用同一个周期函数，结果与在线运行一致
+            let events = tracker.update(commands::step_tick(&mut world, fixed_dt));
+            if let Some(table) = &mut contacts {
+                write_contacts(table, &prefix, step + 1, time + fixed_dt, &events)?;
+            }
+        }
+
+        let label = match (&config.sweep, value) {
+            (Some(sweep), Some(value)) => format!("（{} = {}）", sweep.param.name(), value),
+            _ => String::new(),
+        };
+        info!(
+            "第 {} 次运行{}完成：{} 步，用时 {:.2} 秒",
+            run + 1,
+            label,
+            steps,
+            started.elapsed().as_secs_f32()
+        );
+    }
+
+    for mut table in [trajectory, energy, contacts].into_iter().flatten() {
+        table
+            .file
+            .flush()
+            .map_err(|e| format!("无法写入输出文件 {}: {}", table.path.display(), e))?;
+        info!("结果已写入 {}", table.path.display());
+    }
+    Ok(())
+}
+
+fn write_trajectory(table: &mut Table, prefix: &[(&str, Cell)], step: u64, time: f32, world: &WorldState) -> Result<(), String> {
+    for body in &world.bodies {
+        let mut row = prefix.to_vec();
+        row.extend([
+            ("step", Cell::Int(step)),
+            ("time", Cell::Float(time)),
+            ("body", Cell::Int(body.id as u64)),
+            ("x", Cell::Float(body.position.x)),
+            ("y", Cell::Float(body.position.y)),
+            ("vx", Cell::Float(body.velocity.x)),
+            ("vy", Cell::Float(body.velocity.y)),
+            ("angle", Cell::Float(body.angle)),
+            ("angular_velocity", Cell::Float(body.angular_velocity)),
+        ]);
+        table.row(&row)?;
+    }
+    Ok(())
+}
+
+fn write_energy(table: &mut Table, prefix: &[(&str, Cell)], step: u64, time: f32, world: &WorldState) -> Result<(), String> {
+    let diagnostics = world.diagnostics();
+    let mut row = prefix.to_vec();
+    row.extend([
+        ("step", Cell::Int(step)),
+        ("time", Cell::Float(time)),
+        ("kinetic", Cell::Float(diagnostics.kinetic)),
+        ("potential", Cell::Float(diagnostics.potential)),
+        ("total", Cell::Float(diagnostics.total)),
+        ("momentum_x", Cell::Float(diagnostics.momentum.x)),
+        ("momentum_y", Cell::Float(diagnostics.momentum.y)),
+        ("angular_momentum", Cell::Float(diagnostics.angular_momentum)),
+    ]);
+    table.row(&row)
+}
+
+fn write_contacts(table: &mut Table, prefix: &[(&str, Cell)], step: u64, time: f32, events: &[ContactEvent]) -> Result<(), String> {
+    for event in events {
+        let contact = &event.contact;
+        let phase = match event.phase {
+            ContactPhase::Begin => "Begin",
+            ContactPhase::Persist => "Persist",
+            ContactPhase::End => "End",
+        };
+        let mut row = prefix.to_vec();
+        row.extend([
+            ("step", Cell::Int(step)),
+            ("time", Cell::Float(time)),
+            ("phase", Cell::Text(phase)),
+            ("body_a", Cell::Int(contact.body_a as u64)),
+            ("body_b", Cell::Int(contact.body_b as u64)),
+            ("point_x", Cell::Float(contact.point.x)),
+            ("point_y", Cell::Float(contact.point.y)),
+            ("normal_x", Cell::Float(contact.normal.x)),
+            ("normal_y", Cell::Float(contact.normal.y)),
+            ("normal_impulse", Cell::Float(contact.normal_impulse)),
+            ("relative_speed", Cell::Float(contact.relative_speed)),
+        ]);
+        table.row(&row)?;
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy)]
+enum Cell {
+    Int(u64),
+    Float(f32),
+    // 只用于固定的英文单词，不需要转义
+    Text(&'static str),
+}
+
+impl Cell {
+    // 非有限的数值在 CSV 中留空，在 JSON 中写 null
+    fn write(self, out: &mut impl Write, format: Format) -> io::Result<()> {
+        match (self, format) {
+            (Cell::Int(v), _) => write!(out, "{}", v),
+            (Cell::Float(v), _) if v.is_finite() => write!(out, "{}", v),
+            (Cell::Float(_), Format::Csv) => Ok(()),
+            (Cell::Float(_), Format::JsonLines) => write!(out, "null"),
+            (Cell::Text(v), Format::Csv) => write!(out, "{}", v),
+            (Cell::Text(v), Format::JsonLines) => write!(out, "\"{}\"", v),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+    Csv,
+    JsonLines,
+}
+
+// 输出文件，格式由扩展名决定。CSV 的表头取自第一行的列名
+struct Table {
+    path: PathBuf,
+    file: BufWriter<File>,
+    format: Format,
+    header_written: bool,
+}
+
+impl Table {
+    fn create(path: &Path) -> Result<Self, String> {
+        let format = match path.extension().and_then(|e| e.to_str()) {
+            Some("csv") => Format::Csv,
+            Some("jsonl") => Format::JsonLines,
+            _ => return Err(format!("输出文件 {} 的扩展名必须是 .csv 或 .jsonl", path.display())),
+        };
+        let file = File::create(path).map_err(|e| format!("无法创建输出文件 {}: {}", path.display(), e))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: BufWriter::new(file),
+            format,
+            header_written: false,
+        })
+    }
+
+    fn row(&mut self, cells: &[(&str, Cell)]) -> Result<(), String> {
+        self.write_row(cells)
+            .map_err(|e| format!("无法写入输出文件 {}: {}", self.path.display(), e))
+    }
+
+    fn write_row(&mut self, cells: &[(&str, Cell)]) -> io::Result<()> {
+        let out = &mut self.file;
+        match self.format {
+            Format::Csv => {
+                if !self.header_written {
+                    let names: Vec<&str> = cells.iter().map(|(name, _)| *name).collect();
+                    writeln!(out, "{}", names.join(","))?;
+                    self.header_written = true;
+                }
+                for (i, (_, cell)) in cells.iter().enumerate() {
+                    if i > 0 {
+                        write!(out, ",")?;
+                    }
+                    cell.write(out, self.format)?;
+                }
+            }
+            Format::JsonLines => {
+                write!(out, "{{")?;
+                for (i, (name, cell)) in cells.iter().enumerate() {
+                    if i > 0 {
+                        write!(out, ",")?;
+                    }
+                    write!(out, "\"{}\":", name)?;
+                    cell.write(out, self.format)?;
+                }
+                write!(out, "}}")?;
+            }
+        }
+        writeln!(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_range_includes_end() {
+        let sweep = Sweep::parse("restitution=0.1:0.9:0.1").unwrap();
+        assert!(matches!(sweep.param, SweepParam::Restitution));
+        assert_eq!(sweep.values.len(), 9);
+        assert_eq!(sweep.values[0], 0.1);
+        assert_eq!(sweep.values[6], 0.7);
+        assert_eq!(sweep.values[8], 0.9);
+    }
+
+    #[test]
+    fn parse_single_value() {
+        let sweep = Sweep::parse(" gravity = 50 ").unwrap();
+        assert!(matches!(sweep.param, SweepParam::Gravity));
+        assert_eq!(sweep.values, vec![50.0]);
+    }
+
+    #[test]
+    fn parse_rejects_bad_specs() {
+        for spec in [
+            "restitution",
+            "bounce=0.1:0.9:0.1",
+            "mass=1:2",
+            "mass=1:x:1",
+            "mass=2:1:0.5",
+            "mass=1:2:0",
+            "mass=0:10000:1",
+            "mass=NaN",
+        ] {
+            assert!(Sweep::parse(spec).is_err(), "{} 应当无效", spec);
+        }
+    }
+}
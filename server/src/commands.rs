@@ -0,0 +1,461 @@
+use common::physics::{BodyProperties, Contact, Label, LabelAnchor, Permissions, RigidBody, Role, Shape, Vec2, WorldState};
+use crate::contacts;
+use crate::permissions::{self, Action};
+use crate::scene::SceneFile;
+use crate::validation::Limits;
+use serde::{Deserialize, Serialize};
+
+// 修改世界的命令。命令自带执行所需的全部数据，
+// 同一场景按同样的顺序执行同样的命令总能得到相同的世界
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum WorldCommand {
+    ApplyImpulse {
+        body_id: u32,
+        impulse: Vec2,
+    },
+    AddBody {
+        position: Vec2,
+        shape: Shape,
+        mass: f32,
+    },
+    SetBodyProperties {
+        body_id: u32,
+        properties: BodyProperties,
+    },
+    RemoveBody {
+        body_id: u32,
+    },
+    // 批量删除由 RemoveOwnedBodies / RemoveBodiesInRect 在授权时展开为具体的物体
+    RemoveOwnedBodies,
+    RemoveBodiesInRect {
+        min: Vec2,
+        max: Vec2,
+    },
+    RemoveBodies {
+        body_ids: Vec<u32>,
+    },
+    AddLabel {
+        text: String,
+        anchor: LabelAnchor,
+    },
+    RemoveLabel {
+        label_id: u32,
+    },
+    // 重置和加载场景都替换为命令中携带的场景
+    LoadScene {
+        scene: SceneFile,
+    },
+    SetPaused {
+        paused: bool,
+    },
+    Step {
+        steps: u32,
+    },
+    SetTimeScale {
+        time_scale: f32,
+    },
+    // 由房间按历史记录展开为 RestoreState
+    Rewind {
+        tick: u64,
+    },
+    RestoreState {
+        state: Box<WorldState>,
+    },
+}
+
+impl WorldCommand {
+    // 高频的推动和属性修改只在调试级别记录
+    pub fn is_frequent(&self) -> bool {
+        matches!(self, WorldCommand::ApplyImpulse { .. } | WorldCommand::SetBodyProperties { .. })
+    }
+
+    // 执行后整个世界被替换
+    pub fn replaces_world(&self) -> bool {
+        matches!(self, WorldCommand::LoadScene { .. } | WorldCommand::RestoreState { .. })
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            WorldCommand::ApplyImpulse { body_id, impulse } => format!("对物体 {} 施加冲量 {:?}", body_id, impulse),
+            WorldCommand::AddBody { position, shape, .. } => format!("在 {:?} 添加 {:?}", position, shape),
+            WorldCommand::SetBodyProperties { body_id, properties } => {
+                format!("修改物体 {} 属性: {:?}", body_id, properties)
+            }
+            WorldCommand::RemoveBody { body_id } => format!("删除物体 {}", body_id),
+            WorldCommand::RemoveOwnedBodies => "删除自己的物体".to_string(),
+            WorldCommand::RemoveBodiesInRect { .. } => "删除区域内的物体".to_string(),
+            WorldCommand::RemoveBodies { body_ids } => format!("删除了 {} 个物体", body_ids.len()),
+            WorldCommand::AddLabel { text, .. } => format!("添加标注: {}", text),
+            WorldCommand::RemoveLabel { label_id } => format!("删除标注 {}", label_id),
+            WorldCommand::LoadScene { scene } => format!("加载了包含 {} 个物体的场景", scene.bodies.len()),
+            WorldCommand::SetPaused { paused } => (if *paused { "暂停模拟" } else { "继续模拟" }).to_string(),
+            WorldCommand::Step { steps } => format!("单步执行 {} 步", steps),
+            WorldCommand::SetTimeScale { time_scale } => format!("把时间倍率改为 {}", time_scale),
+            WorldCommand::Rewind { tick } => format!("回退到第 {} 周期", tick),
+            WorldCommand::RestoreState { state } => format!("回退到第 {} 周期", state.tick),
+        }
+    }
+}
+
+// 发出命令的客户端
+#[derive(Debug, Clone, Copy)]
+pub struct Issuer {
+    pub client_id: usize,
+    pub role: Role,
+}
+
+// 按执行时的世界检查权限和容量，返回实际要执行的命令
+pub fn authorize(
+    world: &WorldState,
+    permissions: &Permissions,
+    limits: &Limits,
+    issuer: Issuer,
+    command: WorldCommand,
+) -> Result<WorldCommand, String> {
+    let check = |action: Action, body: &RigidBody| {
+        if permissions::is_allowed(permissions, action, issuer.role, issuer.client_id, body) {
+            Ok(())
+        } else {
+            Err(format!("没有权限{}物体 {}", action.describe(), body.id))
+        }
+    };
+    let find = |body_id: u32| {
+        world
+            .bodies
+            .iter()
+            .find(|b| b.id == body_id)
+            .ok_or_else(|| format!("物体 {} 不存在", body_id))
+    };
+    match command {
+        // 推动已删除的物体时静默忽略
+        WorldCommand::ApplyImpulse { body_id, .. } => {
+            if let Ok(body) = find(body_id) {
+                check(Action::Push, body)?;
+            }
+        }
+        WorldCommand::SetBodyProperties { body_id, .. } => check(Action::Edit, find(body_id)?)?,
+        WorldCommand::RemoveBody { body_id } => check(Action::Delete, find(body_id)?)?,
+        WorldCommand::AddBody { .. } => limits.check_capacity(&world.bodies, issuer.client_id)?,
+        WorldCommand::RemoveOwnedBodies => {
+            // 删除权限只对教师开放时，学生也不能删除自己的物体
+            let mut body_ids = Vec::new();
+            for body in world.bodies.iter().filter(|b| b.owner == Some(issuer.client_id)) {
+                check(Action::Delete, body)?;
+                body_ids.push(body.id);
+            }
+            return Ok(WorldCommand::RemoveBodies { body_ids });
+        }
+        WorldCommand::RemoveBodiesInRect { min, max } => {
+            // 只删除区域内有权限删除的物体，其余保留
+            let (min_x, max_x) = (min.x.min(max.x), min.x.max(max.x));
+            let (min_y, max_y) = (min.y.min(max.y), min.y.max(max.y));
+            let body_ids = world
+                .bodies
+                .iter()
+                .filter(|b| (min_x..=max_x).contains(&b.position.x) && (min_y..=max_y).contains(&b.position.y))
+                .filter(|b| check(Action::Delete, b).is_ok())
+                .map(|b| b.id)
+                .collect();
+            return Ok(WorldCommand::RemoveBodies { body_ids });
+        }
+        WorldCommand::RemoveBodies { ref body_ids } => {
+            for body in world.bodies.iter().filter(|b| body_ids.contains(&b.id)) {
+                check(Action::Delete, body)?;
+            }
+        }
+        WorldCommand::AddLabel { ref anchor, .. } => {
+            if let LabelAnchor::Body { body_id, .. } = anchor {
+                find(*body_id).map_err(|_| "标注固定的物体不存在".to_string())?;
+            }
+        }
+        WorldCommand::RemoveLabel { .. }
+        | WorldCommand::LoadScene { .. }
+        | WorldCommand::SetPaused { .. }
+        | WorldCommand::Step { .. }
+        | WorldCommand::SetTimeScale { .. }
+        | WorldCommand::Rewind { .. }
+        | WorldCommand::RestoreState { .. } => {}
+    }
+    Ok(command)
+}
+
+// 执行已授权的命令。不检查权限，回放时直接使用；物体 ID 由 next_id 分配，标注 ID 取当前最大值加一
+pub fn execute(world: &mut WorldState, client_id: usize, command: &WorldCommand) {
+    match command {
+        WorldCommand::ApplyImpulse { body_id, impulse } => {
+            if let Some(body) = world.bodies.iter_mut().find(|b| b.id == *body_id) {
+                body.velocity = body.velocity + *impulse * body.inverse_mass();
+            }
+        }
+        WorldCommand::AddBody { position, shape, mass } => {
+            let new_id = world.allocate_body_id();
+            let mut body = match *shape {
+                Shape::Circle { radius } => RigidBody::new_circle(new_id, *position, radius, *mass),
+                Shape::Rectangle { width, height } => {
+                    RigidBody::new_rectangle(new_id, *position, width, height, *mass)
+                }
+            };
+            body.owner = Some(client_id);
+            world.bodies.push(body);
+        }
+        WorldCommand::SetBodyProperties { body_id, properties } => {
+            if let Some(body) = world.bodies.iter_mut().find(|b| b.id == *body_id) {
+                body.apply_properties(properties);
+            }
+        }
+        WorldCommand::RemoveBody { body_id } => world.bodies.retain(|b| b.id != *body_id),
+        WorldCommand::RemoveOwnedBodies => world.bodies.retain(|b| b.owner != Some(client_id)),
+        // authorize 已按权限展开为 RemoveBodies
+        WorldCommand::RemoveBodiesInRect { .. } => {}
+        WorldCommand::RemoveBodies { body_ids } => world.bodies.retain(|b| !body_ids.contains(&b.id)),
+        WorldCommand::AddLabel { text, anchor } => {
+            let label_id = world.labels.iter().map(|l| l.id).max().unwrap_or(0) + 1;
+            world.labels.push(Label {
+                id: label_id,
+                text: text.trim().to_string(),
+                anchor: *anchor,
+            });
+        }
+        WorldCommand::RemoveLabel { label_id } => world.labels.retain(|l| l.id != *label_id),
+        WorldCommand::LoadScene { scene } => {
+            let loaded = scene.clone().into_world();
+            world.bodies = loaded.bodies;
+            world.joints = loaded.joints;
+            world.force_fields = loaded.force_fields;
+            world.labels = loaded.labels;
+            world.params = loaded.params;
+            // 之前的物体 ID 可能仍被客户端引用，不能分配给之后的新物体
+            world.next_id = world.next_id.max(loaded.next_id);
+        }
+        WorldCommand::SetPaused { paused } => {
+            world.paused = *paused;
+            if !paused {
+                world.pending_steps = 0;
+            }
+        }
+        WorldCommand::Step { steps } => {
+            // 运行中收到单步命令时先暂停
+            world.paused = true;
+            world.pending_steps = world.pending_steps.saturating_add(*steps);
+        }
+        WorldCommand::SetTimeScale { time_scale } => world.time_scale = *time_scale,
+        WorldCommand::Rewind { .. } => {}
+        WorldCommand::RestoreState { state } => {
+            let rewinds = world.rewinds + 1;
+            let next_id = world.next_id;
+            *world = (**state).clone();
+            world.rewinds = rewinds;
+            world.next_id = world.next_id.max(next_id);
+        }
+    }
+}
+
+// 推进一个模拟周期，返回本周期物体之间的接触。只依赖世界本身和固定步长，与墙上时钟无关
+pub fn step_tick(world: &mut WorldState, fixed_dt: f32) -> Vec<Contact> {
+    let mut contacts = Vec::new();
+    if !world.paused {
+        // 按时间倍率缩放步长；快进时拆成多个子步，避免步长过大穿透
+        let substeps = world.time_scale.ceil().max(1.0);
+        let dt = fixed_dt * world.time_scale / substeps;
+        for _ in 0..substeps as u32 {
+            contacts.extend(world.step(dt));
+        }
+        world.tick += 1;
+    } else if world.pending_steps > 0 {
+        // 暂停时每个周期执行一个待执行的单步，方便观察
+        world.pending_steps -= 1;
+        contacts = world.step(fixed_dt);
+        world.tick += 1;
+    }
+
+    // 物体被删除后其上的标注和关节一并移除
+    world.prune_labels();
+    world.prune_joints();
+    contacts::merge(contacts)
+}
+
+// 世界状态的校验和（FNV-1a），用于比较两次运行是否逐位一致。服务器时间是墙上时钟，不参与计算
+pub fn checksum(world: &WorldState) -> u64 {
+    let world = WorldState {
+        server_time: 0.0,
+        ..world.clone()
+    };
+    fnv1a(&serde_json::to_vec(&world).unwrap())
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::physics::{Access, BodyType, ForceField, Joint};
+    use crate::room::initial_scene;
+
+    const FIXED_DT: f32 = 1.0 / 60.0;
+    const CHECKSUM_OF_FIXED_WORLD: u64 = 0x6a1f_a28f_1d05_27f0;
+
+    // 按周期执行的命令序列，模拟几个客户端在不同周期的操作
+    fn script() -> Vec<(u64, usize, WorldCommand)> {
+        vec![
+            (5, 1, WorldCommand::ApplyImpulse { body_id: 1, impulse: Vec2::new(300.0, -200.0) }),
+            (
+                12,
+                2,
+                WorldCommand::AddBody {
+                    position: Vec2::new(600.0, 200.0),
+                    shape: Shape::Rectangle { width: 40.0, height: 20.0 },
+                    mass: 1.5,
+                },
+            ),
+            (12, 3, WorldCommand::ApplyImpulse { body_id: 2, impulse: Vec2::new(-150.0, 0.0) }),
+            (40, 1, WorldCommand::SetTimeScale { time_scale: 2.0 }),
+            (90, 2, WorldCommand::ApplyImpulse { body_id: 3, impulse: Vec2::new(0.0, -500.0) }),
+        ]
+    }
+
+    fn run(ticks: u64) -> WorldState {
+        let mut world = initial_scene();
+        let script = script();
+        for _ in 0..ticks {
+            let tick = world.tick;
+            for (_, client_id, command) in script.iter().filter(|(t, _, _)| *t == tick) {
+                execute(&mut world, *client_id, command);
+            }
+            step_tick(&mut world, FIXED_DT);
+        }
+        world
+    }
+
+    fn run_without_commands(ticks: u64) -> WorldState {
+        let mut world = initial_scene();
+        for _ in 0..ticks {
+            step_tick(&mut world, FIXED_DT);
+        }
+        world
+    }
+
+    #[test]
+    fn fnv1a_matches_reference_values() {
+        // FNV-1a 64 位的公开测试向量
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn checksum_of_fixed_world() {
+        // 快照格式或字段顺序变化会改变校验和，旧的录像和日志中的校验和随之失效，改动时需要确认
+        let world: WorldState = serde_json::from_str(r#"{"bodies":[],"tick":7}"#).unwrap();
+        assert_eq!(checksum(&world), CHECKSUM_OF_FIXED_WORLD);
+    }
+
+    #[test]
+    fn removed_body_ids_are_not_reused() {
+        let mut world = initial_scene();
+        let add = WorldCommand::AddBody {
+            position: Vec2::new(600.0, 200.0),
+            shape: Shape::Circle { radius: 10.0 },
+            mass: 1.0,
+        };
+        execute(&mut world, 1, &add);
+        let first = world.bodies.last().unwrap().id;
+        execute(&mut world, 1, &WorldCommand::RemoveBody { body_id: first });
+        execute(&mut world, 1, &add);
+        let second = world.bodies.last().unwrap().id;
+        assert!(second > first);
+
+        // 回退到添加之前，之后的新物体仍使用新的 ID
+        let before = initial_scene();
+        execute(&mut world, 1, &WorldCommand::RestoreState { state: Box::new(before) });
+        execute(&mut world, 1, &add);
+        assert!(world.bodies.last().unwrap().id > second);
+    }
+
+    #[test]
+    fn removing_own_bodies_needs_delete_permission() {
+        let mut world = initial_scene();
+        let add = WorldCommand::AddBody {
+            position: Vec2::new(600.0, 200.0),
+            shape: Shape::Circle { radius: 10.0 },
+            mass: 1.0,
+        };
+        execute(&mut world, 1, &add);
+        let student = Issuer {
+            client_id: 1,
+            role: Role::Student,
+        };
+        let limits = Limits::default();
+        let teacher_only = Permissions {
+            delete: Access::TeacherOnly,
+            ..Default::default()
+        };
+        assert!(authorize(&world, &teacher_only, &limits, student, WorldCommand::RemoveOwnedBodies).is_err());
+        let allowed = authorize(&world, &Permissions::default(), &limits, student, WorldCommand::RemoveOwnedBodies);
+        assert!(matches!(allowed, Ok(WorldCommand::RemoveBodies { body_ids }) if body_ids.len() == 1));
+    }
+
+    #[test]
+    fn joints_keep_length_and_go_with_their_bodies() {
+        let mut world = WorldState {
+            bodies: vec![
+                RigidBody::new_circle(1, Vec2::new(600.0, 100.0), 5.0, 1.0),
+                RigidBody::new_circle(2, Vec2::new(700.0, 100.0), 10.0, 1.0),
+            ],
+            joints: vec![Joint {
+                body_a: 1,
+                body_b: 2,
+                length: 100.0,
+            }],
+            force_fields: vec![ForceField {
+                min: Vec2::new(650.0, 0.0),
+                max: Vec2::new(1200.0, 800.0),
+                acceleration: Vec2::new(200.0, 0.0),
+            }],
+            ..Default::default()
+        };
+        world.bodies[0].body_type = BodyType::Static;
+        for _ in 0..120 {
+            step_tick(&mut world, FIXED_DT);
+        }
+        let distance = (world.bodies[1].position - world.bodies[0].position).length();
+        assert!((distance - 100.0).abs() < 0.01, "关节长度变为 {}", distance);
+        // 力场把摆锤推向右侧
+        assert!(world.bodies[1].position.x > 600.0);
+
+        execute(&mut world, 1, &WorldCommand::RemoveBody { body_id: 2 });
+        step_tick(&mut world, FIXED_DT);
+        assert!(world.joints.is_empty());
+    }
+
+    #[test]
+    fn checksum_ignores_server_time() {
+        let world = initial_scene();
+        let later = WorldState {
+            server_time: 123.5,
+            ..world.clone()
+        };
+        assert_eq!(checksum(&world), checksum(&later));
+    }
+
+    #[test]
+    fn checksum_changes_with_world() {
+        let world = initial_scene();
+        let mut moved = world.clone();
+        moved.bodies[0].position.x += 0.001;
+        assert_ne!(checksum(&world), checksum(&moved));
+    }
+
+    #[test]
+    fn same_scene_and_commands_give_same_checksum() {
+        let first = run(180);
+        let second = run(180);
+        assert_eq!(first.tick, second.tick);
+        assert_eq!(checksum(&first), checksum(&second));
+        // 命令确实改变了结果，而不是两次都什么也没做
+        assert_ne!(checksum(&first), checksum(&run_without_commands(180)));
+    }
+}
@@ -0,0 +1,302 @@
+use common::physics::{WorldParams, WorldState};
+use crate::batch::Sweep;
+use crate::log::LogLevel;
+use crate::room::{initial_scene, MAX_HISTORY_SECS};
+use crate::scene;
+use crate::validation::Limits;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+const USAGE: &str = "用法: server [选项]
+  --config <文件>        从 JSON 配置文件读取参数，命令行参数优先于文件
+  --bind <地址>          监听地址，默认 0.0.0.0
+  --port <端口>          客户端端口，默认 8080
+  --ws-port <端口>       浏览器查看器端口，0 表示关闭，默认 8081
+  --tick-rate <Hz>       模拟频率，默认 60
+  --max-clients <数量>   同时在线的客户端上限，0 表示不限，默认 0
+  --scene <文件>         初始场景文件，默认内置的四个物体
+  --scenes-dir <目录>    客户端保存和加载场景的目录，默认 scenes
+  --export-scene <文件>  把初始场景以当前格式写入文件后退出，可用于升级旧版本的场景文件。
+                         运行中房间的当前状态可在服务器终端输入 export <房间> <文件> 导出
+  --autosave <文件>      定期把所有房间保存到该文件，退出时（quit、Ctrl+C 或 SIGTERM）也会保存
+  --autosave-interval <秒>  自动保存间隔，默认 30
+  --restore              启动时从自动存档恢复
+  --deterministic        确定性模式：命令在周期开始时按固定顺序执行，相同场景和命令得到相同结果
+  --record <目录>        把每个房间的初始世界和执行的命令记录到该目录，用于回放
+  --replay <文件>        在大厅中按实际速度回放记录，客户端可以连接观看，播完后恢复正常
+  --replay-fast          与 --replay 一起使用：不启动服务，尽快重新模拟并核对校验和后退出
+  --batch <步数>         不启动服务，在场景上尽快模拟若干步，把结果写入 --trajectory / --energy
+  --trajectory <文件>    与 --batch 一起使用：每步每个物体的位置和速度，扩展名为 .csv 或 .jsonl
+  --energy <文件>        与 --batch 一起使用：每步的动能、势能、动量和角动量，扩展名为 .csv 或 .jsonl
+  --contacts <文件>      与 --batch 一起使用：物体之间接触的开始、持续和结束事件，扩展名为 .csv 或 .jsonl
+  --sweep <参数=起始:结束:步长>  与 --batch 一起使用：逐次修改参数重复模拟，参数可以是
+                         gravity / linear_damping / angular_damping / restitution / friction / mass
+  --history-secs <秒>    保留最近多少秒的世界供教师回退，0 表示关闭，默认 10
+  --log-level <级别>     error / warn / info / debug，默认 info
+  --gravity <数值>       重力加速度，默认 98。以下三个参数指定时只覆盖场景文件中的对应值
+  --linear-damping <数值>  每步保留的速度比例，默认 0.995
+  --angular-damping <数值> 每步保留的角速度比例，默认 0.99
+  -h, --help             显示本帮助";
+
+// 服务器启动参数。配置文件示例：
+// { "port": 9000, "tick_rate": 120, "scene": "ramp.json", "world": { "gravity": 50 } }
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: String,
+    pub port: u16,
+    pub ws_port: u16,
+    pub tick_rate: f32,
+    pub max_clients: usize,
+    pub scene: Option<PathBuf>,
+    pub scenes_dir: PathBuf,
+    pub autosave: Option<PathBuf>,
+    pub autosave_interval: u64,
+    pub restore: bool,
+    pub deterministic: bool,
+    pub record: Option<PathBuf>,
+    pub history_secs: f32,
+    pub log_level: LogLevel,
+    // 只覆盖给出的世界参数，其余使用场景文件中的值
+    pub world: WorldOverrides,
+    #[serde(skip)]
+    pub export_scene: Option<PathBuf>,
+    #[serde(skip)]
+    pub replay: Option<PathBuf>,
+    #[serde(skip)]
+    pub replay_fast: bool,
+    #[serde(skip)]
+    pub batch: Option<u64>,
+    #[serde(skip)]
+    pub trajectory: Option<PathBuf>,
+    #[serde(skip)]
+    pub energy: Option<PathBuf>,
+    #[serde(skip)]
+    pub contacts: Option<PathBuf>,
+    #[serde(skip)]
+    pub sweep: Option<Sweep>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: "0.0.0.0".to_string(),
+            port: 8080,
+            // 兼容原来的 SANDBOX_WS_PORT 环境变量
+            ws_port: std::env::var("SANDBOX_WS_PORT")
+                .ok()
+                .and_then(|s| s.trim().parse().ok())
+                .unwrap_or(8081),
+            tick_rate: 60.0,
+            max_clients: 0,
+            scene: None,
+            scenes_dir: PathBuf::from("scenes"),
+            autosave: None,
+            autosave_interval: 30,
+            restore: false,
+            deterministic: false,
+            record: None,
+            history_secs: 10.0,
+            log_level: LogLevel::Info,
+            world: WorldOverrides::default(),
+            export_scene: None,
+            replay: None,
+            replay_fast: false,
+            batch: None,
+            trajectory: None,
+            energy: None,
+            contacts: None,
+            sweep: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    // 解析命令行参数：先读 --config 指定的文件，再用其余参数覆盖。
+    // 返回 Ok(None) 表示只需显示帮助
+    pub fn from_args(args: &[String]) -> Result<Option<Self>, String> {
+        if args.iter().any(|a| a == "-h" || a == "--help") {
+            println!("{}", USAGE);
+            return Ok(None);
+        }
+
+        let mut config = match flag_value(args, "--config")? {
+            Some(path) => Self::from_file(Path::new(path))?,
+            None => Self::default(),
+        };
+
+        let mut i = 0;
+        while i < args.len() {
+            let flag = args[i].as_str();
+            // 不带取值的开关
+            let switch = match flag {
+                "--restore" => Some(&mut config.restore),
+                "--deterministic" => Some(&mut config.deterministic),
+                "--replay-fast" => Some(&mut config.replay_fast),
+                _ => None,
+            };
+            if let Some(switch) = switch {
+                *switch = true;
+                i += 1;
+                continue;
+            }
+            let value = args
+                .get(i + 1)
+                .ok_or_else(|| format!("参数 {} 缺少取值\n{}", flag, USAGE))?;
+            match flag {
+                "--config" => {}
+                "--bind" => config.bind = value.clone(),
+                "--port" => config.port = parse(flag, value)?,
+                "--ws-port" => config.ws_port = parse(flag, value)?,
+                "--tick-rate" => config.tick_rate = parse(flag, value)?,
+                "--max-clients" => config.max_clients = parse(flag, value)?,
+                "--scene" => config.scene = Some(PathBuf::from(value)),
+                "--scenes-dir" => config.scenes_dir = PathBuf::from(value),
+                "--autosave" => config.autosave = Some(PathBuf::from(value)),
+                "--autosave-interval" => config.autosave_interval = parse(flag, value)?,
+                "--export-scene" => config.export_scene = Some(PathBuf::from(value)),
+                "--record" => config.record = Some(PathBuf::from(value)),
+                "--replay" => config.replay = Some(PathBuf::from(value)),
+                "--batch" => config.batch = Some(parse(flag, value)?),
+                "--trajectory" => config.trajectory = Some(PathBuf::from(value)),
+                "--energy" => config.energy = Some(PathBuf::from(value)),
+                "--contacts" => config.contacts = Some(PathBuf::from(value)),
+                "--sweep" => config.sweep = Some(Sweep::parse(value)?),
+                "--history-secs" => config.history_secs = parse(flag, value)?,
+                "--log-level" => {
+                    config.log_level =
+                        LogLevel::parse(value).ok_or_else(|| format!("未知的日志级别: {}", value))?
+                }
+                "--gravity" => config.world.gravity = Some(parse(flag, value)?),
+                "--linear-damping" => config.world.linear_damping = Some(parse(flag, value)?),
+                "--angular-damping" => config.world.angular_damping = Some(parse(flag, value)?),
+                _ => return Err(format!("未知参数: {}\n{}", flag, USAGE)),
+            }
+            i += 2;
+        }
+
+        config.validate()?;
+        Ok(Some(config))
+    }
+
+    fn from_file(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("无法读取配置文件 {}: {}", path.display(), e))?;
+        let mut config: Self =
+            serde_json::from_str(&text).map_err(|e| format!("配置文件 {} 格式错误: {}", path.display(), e))?;
+        // 场景文件和目录的相对路径相对于配置文件所在目录
+        if let Some(dir) = path.parent() {
+            if let Some(scene) = config.scene.as_mut().filter(|s| s.is_relative()) {
+                *scene = dir.join(&*scene);
+            }
+            if config.scenes_dir.is_relative() {
+                config.scenes_dir = dir.join(&config.scenes_dir);
+            }
+            if let Some(autosave) = config.autosave.as_mut().filter(|s| s.is_relative()) {
+                *autosave = dir.join(&*autosave);
+            }
+            if let Some(record) = config.record.as_mut().filter(|s| s.is_relative()) {
+                *record = dir.join(&*record);
+            }
+        }
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if !(1.0..=240.0).contains(&self.tick_rate) {
+            return Err("模拟频率必须在 1 到 240 Hz 之间".to_string());
+        }
+        if !(0.0..=MAX_HISTORY_SECS).contains(&self.history_secs) {
+            return Err(format!("历史记录时长必须在 0 到 {} 秒之间", MAX_HISTORY_SECS));
+        }
+        if self.autosave_interval == 0 {
+            return Err("自动保存间隔必须大于 0".to_string());
+        }
+        if self.restore && self.autosave.is_none() {
+            return Err("--restore 需要同时指定 --autosave".to_string());
+        }
+        if self.replay_fast && self.replay.is_none() {
+            return Err("--replay-fast 需要同时指定 --replay".to_string());
+        }
+        if self.replay.is_some() && (self.record.is_some() || self.restore) {
+            return Err("--replay 不能与 --record 或 --restore 同时使用".to_string());
+        }
+        match self.batch {
+            Some(0) => return Err("--batch 的步数必须大于 0".to_string()),
+            Some(_) if self.trajectory.is_none() && self.energy.is_none() && self.contacts.is_none() => {
+                return Err("--batch 需要用 --trajectory、--energy 或 --contacts 指定输出文件".to_string());
+            }
+            Some(_) if self.replay.is_some() => return Err("--batch 不能与 --replay 同时使用".to_string()),
+            None if self.trajectory.is_some()
+                || self.energy.is_some()
+                || self.contacts.is_some()
+                || self.sweep.is_some() =>
+            {
+                return Err("--trajectory、--energy、--contacts 和 --sweep 需要同时指定 --batch".to_string());
+            }
+            _ => {}
+        }
+        // 未给出的参数取默认值，只检查给出的参数
+        let mut world = WorldParams::default();
+        self.world.apply(&mut world);
+        scene::check_world(&world)?;
+        Ok(())
+    }
+
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.bind, self.port)
+    }
+
+    pub fn ws_addr(&self) -> String {
+        format!("{}:{}", self.bind, self.ws_port)
+    }
+
+    // 初始场景：指定了场景文件时从文件读取，配置中给出的世界参数优先于场景文件
+    pub fn load_scene(&self, limits: &Limits) -> Result<WorldState, String> {
+        let mut world = match &self.scene {
+            Some(path) => scene::load(path, limits)?.into_world(),
+            None => initial_scene(),
+        };
+        self.world.apply(&mut world.params);
+        Ok(world)
+    }
+}
+
+// 命令行或配置文件中给出的世界参数，覆盖场景文件中对应的值
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorldOverrides {
+    pub gravity: Option<f32>,
+    pub linear_damping: Option<f32>,
+    pub angular_damping: Option<f32>,
+}
+
+impl WorldOverrides {
+    pub fn apply(&self, params: &mut WorldParams) {
+        if let Some(gravity) = self.gravity {
+            params.gravity = gravity;
+        }
+        if let Some(linear_damping) = self.linear_damping {
+            params.linear_damping = linear_damping;
+        }
+        if let Some(angular_damping) = self.angular_damping {
+            params.angular_damping = angular_damping;
+        }
+    }
+}
+
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Result<Option<&'a str>, String> {
+    match args.iter().position(|a| a == flag) {
+        Some(i) => args
+            .get(i + 1)
+            .map(|v| Some(v.as_str()))
+            .ok_or_else(|| format!("参数 {} 缺少取值", flag)),
+        None => Ok(None),
+    }
+}
+
+fn parse<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("参数 {} 的取值无效: {}", flag, value))
+}
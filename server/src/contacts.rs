@@ -0,0 +1,138 @@
+use common::physics::{Contact, ContactEvent, ContactPhase};
+use std::collections::BTreeMap;
+
+// 不论谁是 body_a，同一对物体用同一个键
+fn pair(contact: &Contact) -> (u32, u32) {
+    (contact.body_a.min(contact.body_b), contact.body_a.max(contact.body_b))
+}
+
+// 同一对物体在多个子步中的接触合并为一条：冲量相加，相对速度取最大，位置和法线取最后一次
+pub fn merge(contacts: Vec<Contact>) -> Vec<Contact> {
+    let mut merged: BTreeMap<(u32, u32), Contact> = BTreeMap::new();
+    for contact in contacts {
+        merged
+            .entry(pair(&contact))
+            .and_modify(|m| {
+                *m = Contact {
+                    normal_impulse: m.normal_impulse + contact.normal_impulse,
+                    relative_speed: m.relative_speed.max(contact.relative_speed),
+                    ..contact
+                }
+            })
+            .or_insert(contact);
+    }
+    merged.into_values().collect()
+}
+
+// 比较相邻两个周期的接触，得到开始、持续和结束事件
+#[derive(Default)]
+pub struct ContactTracker {
+    touching: BTreeMap<(u32, u32), Contact>,
+}
+
+impl ContactTracker {
+    // 世界被整个替换后（回退、加载场景、重置）原来的接触不再成立，也不发结束事件
+    pub fn reset(&mut self) {
+        self.touching.clear();
+    }
+
+    pub fn update(&mut self, contacts: Vec<Contact>) -> Vec<ContactEvent> {
+        let mut events = Vec::new();
+        let mut touching = BTreeMap::new();
+        for contact in contacts {
+            let key = pair(&contact);
+            let phase = if self.touching.contains_key(&key) {
+                ContactPhase::Persist
+            } else {
+                ContactPhase::Begin
+            };
+            events.push(ContactEvent { phase, contact });
+            touching.insert(key, contact);
+        }
+        for (key, contact) in &self.touching {
+            if !touching.contains_key(key) {
+                events.push(ContactEvent {
+                    phase: ContactPhase::End,
+                    contact: Contact {
+                        normal_impulse: 0.0,
+                        ..*contact
+                    },
+                });
+            }
+        }
+        self.touching = touching;
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::physics::Vec2;
+
+    fn contact(body_a: u32, body_b: u32, x: f32, normal_impulse: f32, relative_speed: f32) -> Contact {
+        Contact {
+            body_a,
+            body_b,
+            point: Vec2::new(x, 0.0),
+            normal: Vec2::new(1.0, 0.0),
+            normal_impulse,
+            relative_speed,
+        }
+    }
+
+    fn phases(events: &[ContactEvent]) -> Vec<(ContactPhase, (u32, u32))> {
+        events.iter().map(|e| (e.phase, pair(&e.contact))).collect()
+    }
+
+    #[test]
+    fn merge_combines_substeps_of_same_pair() {
+        let merged = merge(vec![
+            contact(1, 2, 10.0, 3.0, 5.0),
+            contact(3, 4, 50.0, 1.0, 1.0),
+            // 同一对物体，顺序相反
+            contact(2, 1, 20.0, 4.0, 2.0),
+        ]);
+        assert_eq!(merged.len(), 2);
+        let first = merged[0];
+        assert_eq!(pair(&first), (1, 2));
+        assert_eq!(first.normal_impulse, 7.0);
+        assert_eq!(first.relative_speed, 5.0);
+        assert_eq!(first.point.x, 20.0);
+        assert_eq!(pair(&merged[1]), (3, 4));
+    }
+
+    #[test]
+    fn tracker_reports_begin_persist_end() {
+        let mut tracker = ContactTracker::default();
+        let events = tracker.update(vec![contact(1, 2, 0.0, 5.0, 1.0)]);
+        assert_eq!(phases(&events), vec![(ContactPhase::Begin, (1, 2))]);
+
+        let events = tracker.update(vec![contact(2, 1, 0.0, 5.0, 1.0), contact(3, 4, 0.0, 1.0, 1.0)]);
+        assert_eq!(
+            phases(&events),
+            vec![(ContactPhase::Persist, (1, 2)), (ContactPhase::Begin, (3, 4))]
+        );
+
+        let events = tracker.update(vec![contact(3, 4, 0.0, 1.0, 1.0)]);
+        assert_eq!(
+            phases(&events),
+            vec![(ContactPhase::Persist, (3, 4)), (ContactPhase::End, (1, 2))]
+        );
+        // 结束事件没有冲量
+        assert_eq!(events[1].contact.normal_impulse, 0.0);
+
+        assert_eq!(phases(&tracker.update(Vec::new())), vec![(ContactPhase::End, (3, 4))]);
+        assert!(tracker.update(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn reset_forgets_contacts_without_end_events() {
+        let mut tracker = ContactTracker::default();
+        tracker.update(vec![contact(1, 2, 0.0, 5.0, 1.0)]);
+        tracker.reset();
+        assert!(tracker.update(Vec::new()).is_empty());
+        let events = tracker.update(vec![contact(1, 2, 0.0, 5.0, 1.0)]);
+        assert_eq!(phases(&events), vec![(ContactPhase::Begin, (1, 2))]);
+    }
+}
@@ -0,0 +1,58 @@
+use serde::Deserialize;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+// 日志级别，越往后输出越详细
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl LogLevel {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "error" => Some(LogLevel::Error),
+            "warn" => Some(LogLevel::Warn),
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            _ => None,
+        }
+    }
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(level: LogLevel) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+macro_rules! log_at {
+    ($level:ident, $($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::LogLevel::$level) {
+            println!($($arg)*);
+        }
+    };
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => { log_at!(Error, $($arg)*) };
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => { log_at!(Warn, $($arg)*) };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => { log_at!(Info, $($arg)*) };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => { log_at!(Debug, $($arg)*) };
+}
@@ -1,255 +1,831 @@
-mod physics;
+#[macro_use]
+mod log;
+mod autosave;
+mod batch;
+mod commands;
+mod config;
+mod contacts;
+mod permissions;
+mod replay;
+mod room;
+mod scene;
+mod shutdown;
+mod transport;
+mod validation;
+mod websocket;
 
-use physics::{RigidBody, Vec2, WorldState, ClientMessage, Shape};
-use std::collections::HashMap;
-use std::io::{BufReader, BufRead, Write};
-use std::net::{TcpListener, TcpStream};
+use commands::{Issuer, WorldCommand};
+use common::lock::LockExt;
+use common::physics::{ChatMessage, ClientMessage, ContactEvent, Permissions, Role, RoomConfig, ServerMessage, Shape, Topic, WorldState};
+use common::transport::{Delivery, TransportKind};
+use config::ServerConfig;
+use replay::Replay;
+use room::{Member, Room, Rooms, SharedSender, LOBBY};
+use scene::SceneFile;
+use std::collections::HashSet;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
+use transport::{Connection, Listener};
+use validation::{Limits, RateLimiter};
+use websocket::WebSocketListener;
+
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(500);
+// 光标推送频率（Hz）
+const PRESENCE_RATE: f32 = 10.0;
+// 未指定颜色的客户端按 ID 轮流使用
+const CURSOR_PALETTE: [[u8; 3]; 8] = [
+    [255, 99, 71],
+    [65, 105, 225],
+    [50, 205, 50],
+    [255, 165, 0],
+    [186, 85, 211],
+    [0, 206, 209],
+    [255, 105, 180],
+    [240, 230, 140],
+];
 
 fn main() {
-    println!("启动物理服务器...");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config = match ServerConfig::from_args(&args) {
+        Ok(Some(config)) => config,
+        Ok(None) => return,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    log::set_level(config.log_level);
+    info!("启动物理服务器...");
 
-    let world_state = Arc::new(Mutex::new(WorldState {
-        bodies: vec![
-            RigidBody::new_circle(1, Vec2::new(200.0, 300.0), 30.0, 2.0),
-            RigidBody::new_circle(2, Vec2::new(400.0, 200.0), 25.0, 1.0),
-            RigidBody::new_rectangle(3, Vec2::new(600.0, 400.0), 80.0, 60.0, 3.0),
-            RigidBody::new_rectangle(4, Vec2::new(300.0, 500.0), 50.0, 50.0, 0.5),
-        ],
-    }));
+    let limits = Limits::from_env();
+    let scene = match config.load_scene(&limits) {
+        Ok(scene) => scene,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    // --export-scene：只转换场景文件，不启动服务
+    if let Some(path) = &config.export_scene {
+        match scene::save(path, &SceneFile::from_world(&scene)) {
+            Ok(()) => info!("初始场景已写入 {}", path.display()),
+            Err(e) => {
+                error!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
 
-    let listener = TcpListener::bind("0.0.0.0:8080").unwrap();
-    println!("服务器监听在 0.0.0.0:8080");
+    // --batch：不启动服务，批量模拟后退出
+    if config.batch.is_some() {
+        if let Err(e) = batch::run(&config, &scene, &limits) {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // --replay：读入回放记录；--replay-fast 时只重新模拟，不启动服务
+    let replay = match &config.replay {
+        Some(path) => match Replay::load(path) {
+            Ok(replay) => Some(replay),
+            Err(e) => {
+                error!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    if config.replay_fast {
+        let mut replay = replay.unwrap();
+        let world = replay.run_fast();
+        info!(
+            "房间 {} 的回放完成：第 {} 周期，校验和 {:016x}，{}",
+            replay.header.room,
+            world.tick,
+            commands::checksum(&world),
+            replay.summary()
+        );
+        for body in &world.bodies {
+            info!(
+                "物体 {} 位置 ({:.1}, {:.1}) 速度 ({:.1}, {:.1})",
+                body.id, body.position.x, body.position.y, body.velocity.x, body.velocity.y
+            );
+        }
+        std::process::exit(if replay.mismatches() > 0 { 1 } else { 0 });
+    }
 
-    let clients: Arc<Mutex<HashMap<usize, TcpStream>>> = Arc::new(Mutex::new(HashMap::new()));
-    let mut client_counter = 0;
+    let room_config = RoomConfig {
+        tick_rate: config.tick_rate,
+        spectator_rate: RoomConfig::default().spectator_rate.min(config.tick_rate),
+        deterministic: config.deterministic,
+        history_secs: config.history_secs,
+        ..Default::default()
+    };
+    let rooms = Arc::new(Rooms::new(scene, room_config.clone(), limits, config.record.clone()));
+    // 回放时大厅使用记录时的模拟频率
+    let lobby_config = match &replay {
+        Some(replay) => RoomConfig {
+            tick_rate: replay.header.tick_rate,
+            spectator_rate: room_config.spectator_rate.min(replay.header.tick_rate),
+            ..room_config
+        },
+        None => room_config,
+    };
+    let lobby = match rooms.create(LOBBY, lobby_config, true, None) {
+        Ok(lobby) => lobby,
+        Err(e) => {
+            error!("无法创建大厅: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Some(replay) = replay {
+        info!("在大厅回放房间 {} 的记录，从第 {} 周期开始", replay.header.room, replay.header.start.tick);
+        *lobby.world.locked() = replay.header.start.clone();
+        *lobby.replay.locked() = Some(replay);
+    }
 
-    let simulation_world = world_state.clone();
-    let simulation_clients = clients.clone();
-    thread::spawn(move || {
-        simulation_loop(simulation_world, simulation_clients);
-    });
+    if let (true, Some(path)) = (config.restore, &config.autosave) {
+        if path.exists() {
+            match autosave::restore(&rooms, path) {
+                Ok(created) => {
+                    info!("已从 {} 恢复自动存档", path.display());
+                    for room in created {
+                        spawn_simulation(room, rooms.clone());
+                    }
+                }
+                Err(e) => warn!("{}", e),
+            }
+        } else {
+            warn!("自动存档 {} 不存在，使用初始场景", path.display());
+        }
+    }
+    spawn_simulation(lobby, rooms.clone());
+
+    // 传输协议与模拟丢包率通过环境变量选择，例如 SANDBOX_TRANSPORT=udp SANDBOX_PACKET_LOSS=0.2
+    let transport_kind = std::env::var("SANDBOX_TRANSPORT")
+        .ok()
+        .and_then(|s| TransportKind::parse(&s))
+        .unwrap_or(TransportKind::Tcp);
+    let packet_loss = std::env::var("SANDBOX_PACKET_LOSS")
+        .ok()
+        .and_then(|s| s.trim().parse::<f32>().ok())
+        .unwrap_or(0.0);
 
-    for stream in listener.incoming() {
-        match stream {
-            Ok(stream) => {
-                println!("新的客户端连接");
-                client_counter += 1;
-                let client_id = client_counter;
+    let listener = match transport::bind(transport_kind, &config.addr(), packet_loss) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("无法监听 {}: {}", config.addr(), e);
+            std::process::exit(1);
+        }
+    };
+    info!("服务器监听在 {} ({:?}, 模拟丢包率 {})", config.addr(), transport_kind, packet_loss);
 
-                let world = world_state.clone();
-                let clients_map = clients.clone();
-                let stream_clone = stream.try_clone().unwrap();
+    let clients = Arc::new(Clients {
+        next_id: AtomicUsize::new(1),
+        online: AtomicUsize::new(0),
+    });
 
-                clients.lock().unwrap().insert(client_id, stream);
+    let settings = Arc::new(Settings {
+        // 超过该时间没有收到客户端任何消息（包括心跳）即断开，例如 SANDBOX_CLIENT_TIMEOUT_SECS=10
+        client_timeout: std::env::var("SANDBOX_CLIENT_TIMEOUT_SECS")
+            .ok()
+            .and_then(|s| s.trim().parse::<u64>().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(Duration::from_secs(10)),
+        // 握手时出示该口令的客户端获得教师权限，未设置则没有教师
+        teacher_token: std::env::var("SANDBOX_TEACHER_TOKEN")
+            .ok()
+            .filter(|s| !s.trim().is_empty()),
+        // 例如 SANDBOX_FORCE_SPECTATOR=1：除教师外所有客户端都只能观看
+        force_spectator: std::env::var("SANDBOX_FORCE_SPECTATOR")
+            .map(|s| matches!(s.trim(), "1" | "true" | "yes"))
+            .unwrap_or(false),
+        max_clients: config.max_clients,
+        scenes_dir: config.scenes_dir.clone(),
+    });
 
+    // 浏览器查看器使用的 WebSocket 端口，为 0 表示关闭
+    if config.ws_port != 0 {
+        let ws_addr = config.ws_addr();
+        match WebSocketListener::bind(&ws_addr) {
+            Ok(ws_listener) => {
+                info!("WebSocket 查看器监听在 http://{}/", ws_addr);
+                let rooms = rooms.clone();
+                let clients = clients.clone();
+                let settings = settings.clone();
                 thread::spawn(move || {
-                    let mut reader = BufReader::new(stream_clone);
-                    let mut line = String::new();
-
-                    loop {
-                        line.clear();
-                        match reader.read_line(&mut line) {
-                            Ok(0) => {
-                                println!("客户端 {} 断开连接", client_id);
-                                break;
-                            }
-                            Ok(_) => {
-                                if !line.trim().is_empty() {
-                                    if let Ok(message) = serde_json::from_str::<ClientMessage>(&line.trim()) {
-                                        handle_client_message(message, world.clone());
-                                    }
-                                }
-                            }
-                            Err(_) => {
-                                println!("从客户端 {} 读取错误", client_id);
-                                break;
-                            }
-                        }
-                    }
+                    accept_loop(Box::new(ws_listener), rooms, clients, settings);
+                });
+            }
+            Err(e) => warn!("WebSocket 端口 {} 监听失败: {}", config.ws_port, e),
+        }
+    }
+
+    if let Some(path) = &config.autosave {
+        autosave::spawn(rooms.clone(), path.clone(), Duration::from_secs(config.autosave_interval));
+        info!("每 {} 秒自动保存到 {}", config.autosave_interval, path.display());
+    }
+    {
+        let rooms = rooms.clone();
+        let autosave = config.autosave.clone();
+        thread::spawn(move || console_loop(rooms, autosave));
+    }
+    {
+        let rooms = rooms.clone();
+        let autosave = config.autosave.clone();
+        shutdown::install(move || {
+            info!("收到退出信号");
+            save_now(&rooms, autosave.as_deref());
+            info!("服务器退出");
+        });
+    }
 
-                    clients_map.lock().unwrap().remove(&client_id);
+    accept_loop(listener, rooms, clients, settings);
+}
+
+// 开启自动保存时立即保存所有房间
+fn save_now(rooms: &Rooms, autosave: Option<&Path>) {
+    if let Some(path) = autosave {
+        match autosave::save(rooms, path) {
+            Ok(count) => info!("已保存 {} 个房间到 {}", count, path.display()),
+            Err(e) => error!("{}", e),
+        }
+    }
+}
+
+// 服务器终端命令：save 立即保存，export 把房间当前的世界写成场景文件，quit 保存后退出。
+// 标准输入关闭时（例如作为后台服务运行）不再读取
+fn console_loop(rooms: Arc<Rooms>, autosave: Option<PathBuf>) {
+    for line in std::io::stdin().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (Some("save"), None, _) => save_now(&rooms, autosave.as_deref()),
+            (Some("export"), Some(name), Some(path)) => export_room(&rooms, name, Path::new(path)),
+            (Some("quit" | "exit"), None, _) => {
+                save_now(&rooms, autosave.as_deref());
+                info!("服务器退出");
+                std::process::exit(0);
+            }
+            (None, _, _) => {}
+            _ => warn!("未知命令: {}（可用命令: save, export <房间> <文件>, quit）", line.trim()),
+        }
+    }
+}
+
+fn export_room(rooms: &Rooms, name: &str, path: &Path) {
+    let Some(room) = rooms.get(name) else {
+        warn!("房间 {} 不存在", name);
+        return;
+    };
+    let scene = SceneFile::from_world(&room.world.locked());
+    match scene::save(path, &scene) {
+        Ok(()) => info!("房间 {} 的当前场景已写入 {}", name, path.display()),
+        Err(e) => error!("{}", e),
+    }
+}
+
+// 服务器运行参数，所有连接共享
+struct Settings {
+    client_timeout: Duration,
+    teacher_token: Option<String>,
+    force_spectator: bool,
+    max_clients: usize, // 0 表示不限
+    scenes_dir: PathBuf,
+}
+
+// 各监听端口共用的客户端计数
+struct Clients {
+    next_id: AtomicUsize,
+    // 已接受、连接线程尚未结束的连接数，包括还没有完成握手的连接
+    online: AtomicUsize,
+}
+
+impl Clients {
+    // 占用一个在线名额，已满时返回 None。名额在连接线程结束、返回值被丢弃时归还
+    fn reserve(self: &Arc<Self>, max_clients: usize) -> Option<OnlineSlot> {
+        self.online
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (max_clients == 0 || n < max_clients).then_some(n + 1)
+            })
+            .ok()
+            .map(|_| OnlineSlot(self.clone()))
+    }
+}
+
+struct OnlineSlot(Arc<Clients>);
+
+impl Drop for OnlineSlot {
+    fn drop(&mut self) {
+        self.0.online.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn accept_loop(mut listener: Box<dyn Listener>, rooms: Arc<Rooms>, clients: Arc<Clients>, settings: Arc<Settings>) {
+    loop {
+        match listener.accept() {
+            Ok(mut connection) => {
+                // 在启动连接线程之前占用名额，同时到达的连接不会超过上限
+                let Some(slot) = clients.reserve(settings.max_clients) else {
+                    warn!("在线客户端已达上限 {}，拒绝连接 {}", settings.max_clients, connection.peer);
+                    let message = ServerMessage::Error {
+                        message: "服务器已满，请稍后再试".to_string(),
+                    };
+                    let _ = connection
+                        .sender
+                        .send(&serde_json::to_string(&message).unwrap(), Delivery::Reliable);
+                    connection.sender.close();
+                    continue;
+                };
+                info!("新的客户端连接: {}", connection.peer);
+                let client_id = clients.next_id.fetch_add(1, Ordering::SeqCst);
+                let rooms = rooms.clone();
+                let settings = settings.clone();
+
+                thread::spawn(move || {
+                    let _slot = slot;
+                    client_loop(client_id, connection, rooms, settings);
                 });
             }
             Err(e) => {
-                println!("连接错误: {}", e);
+                warn!("连接错误: {}", e);
             }
         }
     }
 }
 
-fn handle_client_message(message: ClientMessage, world: Arc<Mutex<WorldState>>) {
-    println!("收到客户端消息: {:?}", message);
+// 一个客户端连接的会话状态
+struct Session {
+    client_id: usize,
+    name: String,
+    role: Role,
+    spectator: bool,
+    color: [u8; 3],
+    sender: SharedSender,
+    room: Arc<Room>,
+    rate_limiter: RateLimiter,
+    // 订阅随会话保留，切换房间后继续有效
+    subscriptions: HashSet<Topic>,
+}
+
+impl Session {
+    fn send(&self, message: &ServerMessage) {
+        let json = serde_json::to_string(message).unwrap();
+        let _ = self.sender.locked().send(&json, Delivery::Reliable);
+    }
+
+    fn send_error(&self, message: String) {
+        warn!("客户端 {} 请求失败: {}", self.client_id, message);
+        self.send(&ServerMessage::Error { message });
+    }
+
+    // 修改世界的命令交给房间执行，失败时回复错误
+    fn submit(&self, command: WorldCommand, rooms: &Rooms) {
+        let issuer = Issuer {
+            client_id: self.client_id,
+            role: self.role,
+        };
+        if let Err(e) = self.room.submit(issuer, &self.sender, command, &rooms.limits) {
+            self.send_error(e);
+        }
+    }
+
+    fn set_permissions(&self, permissions: Permissions) {
+        let issuer = Issuer {
+            client_id: self.client_id,
+            role: self.role,
+        };
+        self.room.set_permissions(issuer, &self.sender, permissions);
+    }
+
+    fn member(&self) -> Member {
+        Member {
+            sender: self.sender.clone(),
+            name: self.name.clone(),
+            role: self.role,
+            spectator: self.spectator,
+            color: self.color,
+            cursor: None,
+            drag_start: None,
+            subscriptions: self.subscriptions.clone(),
+        }
+    }
+
+    // 仅教师可执行的命令，非教师时回复错误并返回 false
+    fn require_teacher(&self, what: &str) -> bool {
+        if self.role == Role::Teacher {
+            return true;
+        }
+        self.send_error(format!("只有教师可以{}", what));
+        false
+    }
+
+    // 告知客户端所在的房间及其模拟频率
+    fn send_joined(&self) {
+        self.send(&ServerMessage::JoinedRoom {
+            name: self.room.name.clone(),
+            tick_rate: self.room.config.tick_rate,
+        });
+    }
+
+    // 离开当前房间并加入新房间
+    fn switch_room(&mut self, rooms: &Rooms, name: &str) {
+        if name.trim() == self.room.name {
+            self.send_joined();
+            return;
+        }
+        match rooms.join(name, self.client_id, self.member()) {
+            Ok(room) => {
+                self.room.leave(self.client_id);
+                self.room = room;
+                info!("客户端 {} 加入房间 {}", self.client_id, self.room.name);
+                self.send_joined();
+                self.send(&ServerMessage::ChatHistory { messages: self.room.chat_history() });
+            }
+            Err(e) => self.send_error(e),
+        }
+    }
+}
+
+fn client_loop(client_id: usize, connection: Connection, rooms: Arc<Rooms>, settings: Arc<Settings>) {
+    let Connection {
+        sender,
+        mut receiver,
+        closer,
+        ..
+    } = connection;
+    let sender: SharedSender = Arc::new(Mutex::new(sender));
+    let name = format!("客户端{}", client_id);
+    let color = CURSOR_PALETTE[client_id % CURSOR_PALETTE.len()];
+    let member = Member {
+        sender: sender.clone(),
+        name: name.clone(),
+        role: Role::Student,
+        spectator: settings.force_spectator,
+        color,
+        cursor: None,
+        drag_start: None,
+        subscriptions: HashSet::new(),
+    };
+    let room = match rooms.join(LOBBY, client_id, member) {
+        Ok(room) => room,
+        Err(e) => {
+            error!("客户端 {} 无法加入大厅: {}", client_id, e);
+            sender.locked().close();
+            return;
+        }
+    };
+    let mut session = Session {
+        client_id,
+        name,
+        role: Role::Student,
+        spectator: settings.force_spectator,
+        color,
+        sender,
+        room,
+        rate_limiter: RateLimiter::new(),
+        subscriptions: HashSet::new(),
+    };
+    session.send_joined();
+    session.send(&ServerMessage::ChatHistory { messages: session.room.chat_history() });
+
+    // 看门狗：半开连接不会报错，只能靠心跳超时发现并主动关闭。
+    // 发送端可能正阻塞在写入上，不能等它的锁，直接关闭底层连接
+    let last_seen = Arc::new(Mutex::new(Instant::now()));
+    let connected = Arc::new(AtomicBool::new(true));
+    {
+        let last_seen = last_seen.clone();
+        let connected = connected.clone();
+        let client_timeout = settings.client_timeout;
+        thread::spawn(move || {
+            while connected.load(Ordering::SeqCst) {
+                thread::sleep(WATCHDOG_INTERVAL);
+                if last_seen.locked().elapsed() > client_timeout {
+                    warn!("客户端 {} 心跳超时，断开连接", client_id);
+                    closer();
+                    break;
+                }
+            }
+        });
+    }
+
+    loop {
+        match receiver.recv() {
+            Ok(None) => {
+                info!("客户端 {} 断开连接", client_id);
+                break;
+            }
+            Ok(Some(line)) => {
+                *last_seen.locked() = Instant::now();
+                if let Ok(message) = serde_json::from_str::<ClientMessage>(&line) {
+                    // 处理某条消息时 panic 只丢弃这条消息，连接和房间照常运行
+                    let handled = panic::catch_unwind(AssertUnwindSafe(|| {
+                        handle_client_message(message, &mut session, &rooms, &settings);
+                    }));
+                    if handled.is_err() {
+                        error!("处理客户端 {} 的消息时出错", client_id);
+                        session.send(&ServerMessage::Error { message: "服务器内部错误".to_string() });
+                    }
+                }
+            }
+            Err(_) => {
+                warn!("从客户端 {} 读取错误", client_id);
+                break;
+            }
+        }
+    }
+
+    connected.store(false, Ordering::SeqCst);
+    session.room.leave(client_id);
+}
+
+fn handle_client_message(message: ClientMessage, session: &mut Session, rooms: &Arc<Rooms>, settings: &Settings) {
+    // 先限速再校验，违规的消息只回复错误不执行
+    if let Err(e) = session
+        .rate_limiter
+        .check(&message, &rooms.limits)
+        .and_then(|_| rooms.limits.validate(&message))
+    {
+        session.send_error(e);
+        return;
+    }
+    // 心跳消息很频繁，直接回复且不打印日志
+    if let ClientMessage::Ping { nonce } = message {
+        session.send(&ServerMessage::Pong { nonce });
+        return;
+    }
+    // 观众只能握手、查看、切换房间和订阅附加数据
+    if session.spectator
+        && !matches!(
+            message,
+            ClientMessage::Hello { .. }
+                | ClientMessage::ListRooms
+                | ClientMessage::ListUsers
+                | ClientMessage::JoinRoom { .. }
+                | ClientMessage::Subscribe { .. }
+        )
+    {
+        session.send_error("观众模式下不能发送命令".to_string());
+        return;
+    }
+    // 光标上报同样频繁，不打印日志
+    if let ClientMessage::UpdatePresence { cursor, drag_start } = message {
+        session.room.update_presence(session.client_id, cursor, drag_start);
+        return;
+    }
+    debug!("收到客户端消息: {:?}", message);
     match message {
-        ClientMessage::ApplyImpulse { body_id, impulse } => {
-            let mut world = world.lock().unwrap();
-            if let Some(body) = world.bodies.iter_mut().find(|b| b.id == body_id) {
-                body.velocity = body.velocity + impulse * (1.0 / body.mass);
-                println!("对物体 {} 施加冲量: {:?}", body_id, impulse);
+        ClientMessage::Hello { name, token, spectator, color } => {
+            let name = name.trim();
+            if !name.is_empty() {
+                session.name = name.chars().take(32).collect();
+            }
+            session.role = match (&settings.teacher_token, &token) {
+                (Some(expected), Some(token)) if expected == token => Role::Teacher,
+                _ => Role::Student,
+            };
+            // 服务器可强制学生为观众，教师不受影响
+            session.spectator = spectator || (settings.force_spectator && session.role != Role::Teacher);
+            if let Some(color) = color {
+                session.color = color;
             }
+            info!(
+                "客户端 {} 自称 {}，角色 {:?}{}",
+                session.client_id,
+                session.name,
+                session.role,
+                if session.spectator { "（观众）" } else { "" }
+            );
+            session.send(&ServerMessage::Welcome {
+                client_id: session.client_id,
+                role: session.role,
+                spectator: session.spectator,
+            });
+            session.room.update_member(session.client_id, session.member());
         }
-        ClientMessage::AddRectangle { position, width, height, mass } => {
-            let mut world = world.lock().unwrap();
-            let new_id = world.bodies.iter().map(|b| b.id).max().unwrap_or(0) + 1;
-            let new_rect = RigidBody::new_rectangle(new_id, position, width, height, mass);
-            world.bodies.push(new_rect);
-            println!("添加新矩形，ID: {}, 位置: {:?}", new_id, position);
-            println!("当前物体列表:");
-            for b in &world.bodies {
-                println!("ID: {}, 位置: {:?}, 形状: {:?}", b.id, b.position, b.shape);
+        ClientMessage::Ping { .. } => {}
+        ClientMessage::Subscribe { topic, enabled } => {
+            if enabled {
+                session.subscriptions.insert(topic);
+            } else {
+                session.subscriptions.remove(&topic);
             }
+            session.room.set_subscriptions(session.client_id, &session.subscriptions);
+        }
+        ClientMessage::ApplyImpulse { body_id, impulse } => {
+            session.submit(WorldCommand::ApplyImpulse { body_id, impulse }, rooms);
+        }
+        ClientMessage::AddRectangle { position, width, height, mass } => {
+            let shape = Shape::Rectangle { width, height };
+            session.submit(WorldCommand::AddBody { position, shape, mass }, rooms);
         }
         ClientMessage::AddCircle { position, radius, mass } => {
-            let mut world = world.lock().unwrap();
-            let new_id = world.bodies.iter().map(|b| b.id).max().unwrap_or(0) + 1;
-            let new_circle = RigidBody::new_circle(new_id, position, radius, mass);
-            world.bodies.push(new_circle);
-            println!("添加新圆，ID: {}, 位置: {:?}, 半径: {}", new_id, position, radius);
-            println!("当前物体列表:");
-            for b in &world.bodies {
-                println!("ID: {}, 位置: {:?}, 形状: {:?}", b.id, b.position, b.shape);
+            let shape = Shape::Circle { radius };
+            session.submit(WorldCommand::AddBody { position, shape, mass }, rooms);
+        }
+        ClientMessage::ListRooms => {
+            session.send(&ServerMessage::RoomList { rooms: rooms.list() });
+        }
+        ClientMessage::UpdatePresence { .. } => {}
+        ClientMessage::Chat { text } => {
+            let message = ChatMessage {
+                client_id: session.client_id,
+                name: session.name.clone(),
+                text: text.trim().to_string(),
+                server_time: rooms.epoch.elapsed().as_secs_f64(),
+            };
+            info!("[{}] {}: {}", session.room.name, message.name, message.text);
+            session.room.chat(message);
+        }
+        ClientMessage::AddLabel { text, anchor } => {
+            if !session.require_teacher("添加标注") {
+                return;
+            }
+            session.submit(WorldCommand::AddLabel { text, anchor }, rooms);
+        }
+        ClientMessage::RemoveLabel { label_id } => {
+            if !session.require_teacher("删除标注") {
+                return;
+            }
+            session.submit(WorldCommand::RemoveLabel { label_id }, rooms);
+        }
+        ClientMessage::ListUsers => {
+            session.send(&ServerMessage::UserList { users: session.room.users() });
+        }
+        ClientMessage::CreateRoom { name, config } => {
+            let config = config.unwrap_or_else(|| rooms.default_config.clone());
+            match rooms.create(&name, config, false, Some(session.client_id)) {
+                Ok(room) => {
+                    info!("客户端 {} 新建房间 {}", session.client_id, room.name);
+                    spawn_simulation(room.clone(), rooms.clone());
+                    session.switch_room(rooms, &room.name);
+                }
+                Err(e) => session.send_error(e),
+            }
+        }
+        ClientMessage::JoinRoom { name } => {
+            session.switch_room(rooms, &name);
+        }
+        ClientMessage::SetPermissions { permissions } => {
+            if !session.require_teacher("修改房间权限") {
+                return;
+            }
+            session.set_permissions(permissions);
+        }
+        ClientMessage::SetBodyProperties { body_id, properties } => {
+            session.submit(WorldCommand::SetBodyProperties { body_id, properties }, rooms);
+        }
+        ClientMessage::SetPaused { paused } => {
+            if !session.require_teacher("暂停或继续模拟") {
+                return;
+            }
+            session.submit(WorldCommand::SetPaused { paused }, rooms);
+        }
+        ClientMessage::StepSimulation { steps } => {
+            if !session.require_teacher("单步执行模拟") {
+                return;
+            }
+            session.submit(WorldCommand::Step { steps }, rooms);
+        }
+        ClientMessage::SetTimeScale { time_scale } => {
+            if !session.require_teacher("修改时间倍率") {
+                return;
+            }
+            session.submit(WorldCommand::SetTimeScale { time_scale }, rooms);
+        }
+        ClientMessage::RemoveBody { body_id } => {
+            session.submit(WorldCommand::RemoveBody { body_id }, rooms);
+        }
+        ClientMessage::RemoveOwnedBodies => {
+            session.submit(WorldCommand::RemoveOwnedBodies, rooms);
+        }
+        ClientMessage::RemoveBodiesInRect { min, max } => {
+            session.submit(WorldCommand::RemoveBodiesInRect { min, max }, rooms);
+        }
+        ClientMessage::ListScenes => {
+            session.send(&ServerMessage::SceneList { scenes: scene::list(&settings.scenes_dir) });
+        }
+        ClientMessage::SaveScene { name } => {
+            if !session.require_teacher("保存场景") {
+                return;
+            }
+            let scene = SceneFile::from_world(&session.room.world.locked());
+            let result = scene::library_path(&settings.scenes_dir, &name).and_then(|path| {
+                std::fs::create_dir_all(&settings.scenes_dir)
+                    .map_err(|e| format!("无法创建场景目录 {}: {}", settings.scenes_dir.display(), e))?;
+                scene::save(&path, &scene)
+            });
+            match result {
+                Ok(()) => {
+                    info!("客户端 {} 把房间 {} 保存为场景 {}", session.client_id, session.room.name, name.trim());
+                    session.send(&ServerMessage::SceneSaved { name: name.trim().to_string() });
+                }
+                Err(e) => session.send_error(e),
             }
         }
+        ClientMessage::LoadScene { name } => {
+            if !session.require_teacher("加载场景") {
+                return;
+            }
+            let loaded = scene::library_path(&settings.scenes_dir, &name).and_then(|path| {
+                if !path.is_file() {
+                    return Err(format!("场景 {} 不存在", name.trim()));
+                }
+                scene::load(&path, &rooms.limits)
+            });
+            match loaded {
+                Ok(scene) => {
+                    info!("客户端 {} 在房间 {} 加载场景 {}", session.client_id, session.room.name, name.trim());
+                    session.submit(WorldCommand::LoadScene { scene }, rooms);
+                }
+                Err(e) => session.send_error(e),
+            }
+        }
+        ClientMessage::Rewind { tick } => {
+            if !session.require_teacher("回退世界") {
+                return;
+            }
+            session.submit(WorldCommand::Rewind { tick }, rooms);
+        }
+        ClientMessage::ResetWorld => {
+            let allowed = permissions::can_reset(&session.room.permissions.locked(), session.role);
+            if !allowed {
+                session.send_error("没有权限重置世界".to_string());
+                return;
+            }
+            info!("客户端 {} 重置房间 {}", session.client_id, session.room.name);
+            let scene = SceneFile::from_world(&rooms.scene);
+            session.submit(WorldCommand::LoadScene { scene }, rooms);
+        }
+    }
+}
+
+fn spawn_simulation(room: Arc<Room>, rooms: Arc<Rooms>) {
+    // 在模拟线程启动前开始记录，之后执行的所有命令都会被记下
+    if let Some(dir) = &rooms.record_dir {
+        room.start_recording(dir);
     }
+    thread::spawn(move || {
+        simulation_loop(room, rooms);
+    });
 }
 
-fn simulation_loop(world: Arc<Mutex<WorldState>>, clients: Arc<Mutex<HashMap<usize, TcpStream>>>) {
-    let fixed_dt = 1.0 / 60.0;
+fn simulation_loop(room: Arc<Room>, rooms: Arc<Rooms>) {
+    let fixed_dt = 1.0 / room.config.tick_rate;
     let step_duration = Duration::from_secs_f32(fixed_dt);
+    let idle_timeout = Duration::from_secs(room.config.idle_timeout_secs);
+    let mut empty_since: Option<Instant> = None;
+    // 每隔几个周期才给观众发一次快照
+    let spectator_interval = (room.config.tick_rate / room.config.spectator_rate).round().max(1.0) as u64;
+    let presence_interval = (room.config.tick_rate / PRESENCE_RATE).round().max(1.0) as u64;
+    let mut frame: u64 = 0;
+    let mut last_good: Option<WorldState> = None;
 
     loop {
         let step_start = Instant::now();
 
-        {
-            let mut world = world.lock().unwrap();
-            
-            for body in &mut world.bodies {
-                // 重力
-                body.velocity.y += 98.0 * fixed_dt;
-                // 更新位置
-                body.position = body.position + body.velocity * fixed_dt;
-                // 更新角度
-                body.angle += body.angular_velocity * fixed_dt;
-                // 边界碰撞检测 - 根据形状类型
-                match body.shape {
-                    Shape::Circle { radius } => {
-                        if body.position.x - radius < 0.0 {
-                            body.position.x = radius;
-                            body.velocity.x = -body.velocity.x * 0.8;
-                        } else if body.position.x + radius > 1200.0 {
-                            body.position.x = 1200.0 - radius;
-                            body.velocity.x = -body.velocity.x * 0.8;
-                        }
-                        if body.position.y - radius < 0.0 {
-                            body.position.y = radius;
-                            body.velocity.y = -body.velocity.y * 0.8;
-                        } else if body.position.y + radius > 800.0 {
-                            body.position.y = 800.0 - radius;
-                            body.velocity.y = -body.velocity.y * 0.8;
-                        }
-                    }
-                    Shape::Rectangle { width, height } => {
-                        let half_width = width / 2.0;
-                        let half_height = height / 2.0;
-                        if body.position.x - half_width < 0.0 {
-                            body.position.x = half_width;
-                            body.velocity.x = -body.velocity.x * 0.8;
-                            body.angular_velocity += body.velocity.y * 0.01;
-                        } else if body.position.x + half_width > 1200.0 {
-                            body.position.x = 1200.0 - half_width;
-                            body.velocity.x = -body.velocity.x * 0.8;
-                            body.angular_velocity += body.velocity.y * 0.01;
-                        }
-                        if body.position.y - half_height < 0.0 {
-                            body.position.y = half_height;
-                            body.velocity.y = -body.velocity.y * 0.8;
-                            body.angular_velocity += body.velocity.x * 0.01;
-                        } else if body.position.y + half_height > 800.0 {
-                            body.position.y = 800.0 - half_height;
-                            body.velocity.y = -body.velocity.y * 0.8;
-                            body.angular_velocity += body.velocity.x * 0.01;
-                        }
-                    }
-                }
-                // 阻尼
-                body.velocity = body.velocity * 0.995;
-                body.angular_velocity *= 0.99; // 角速度阻尼
-                // 碰撞特效帧数递减
-                if body.collision_frames > 0 {
-                    body.collision_frames -= 1;
-                }
+        let stepped = panic::catch_unwind(AssertUnwindSafe(|| advance(&room, &rooms, fixed_dt)));
+        let (snapshot, events, announcements) = match stepped {
+            Ok(stepped) => stepped,
+            Err(_) => {
+                // 模拟出错时退回上一帧，避免异常状态继续扩散
+                error!("房间 {} 模拟出错，已恢复到上一帧", room.name);
+                let restored = last_good.clone().unwrap_or_else(|| rooms.scene.clone());
+                room.recover(restored);
+                (room.world.locked().clone(), Vec::new(), Vec::new())
             }
-            
-            // 简化的碰撞检测
-            let body_count = world.bodies.len();
-            for i in 0..body_count {
-                for j in i + 1..body_count {
-                    let pos_i = world.bodies[i].position;
-                    let pos_j = world.bodies[j].position;
-                    let vel_i = world.bodies[i].velocity;
-                    let vel_j = world.bodies[j].velocity;
-                    let mass_i = world.bodies[i].mass;
-                    let mass_j = world.bodies[j].mass;
-                    let shape_i = world.bodies[i].shape;
-                    let shape_j = world.bodies[j].shape;
-                    let (min_i, max_i) = get_bounding_box_from_data(pos_i, shape_i);
-                    let (min_j, max_j) = get_bounding_box_from_data(pos_j, shape_j);
-                    if max_i.x >= min_j.x && min_i.x <= max_j.x &&
-                       max_i.y >= min_j.y && min_i.y <= max_j.y {
-                        let normal = (pos_i - pos_j).normalize();
-                        let overlap = calculate_overlap_from_data(pos_i, shape_i, pos_j, shape_j);
-                        if overlap > 0.0 {
-                            // 设置碰撞特效帧数
-                            world.bodies[i].collision_frames = 10;
-                            world.bodies[j].collision_frames = 10;
-                            world.bodies[i].position = world.bodies[i].position + normal * (overlap * 0.5);
-                            world.bodies[j].position = world.bodies[j].position - normal * (overlap * 0.5);
-                            let relative_velocity = vel_i - vel_j;
-                            let velocity_along_normal = relative_velocity.x * normal.x + relative_velocity.y * normal.y;
-                            if velocity_along_normal > 0.0 {
-                                continue;
-                            }
-                            let restitution = 0.8;
-                            let mut impulse_magnitude = -(1.0 + restitution) * velocity_along_normal;
-                            impulse_magnitude /= 1.0 / mass_i + 1.0 / mass_j;
-                            let impulse = normal * impulse_magnitude;
-                            world.bodies[i].velocity = world.bodies[i].velocity + impulse * (1.0 / mass_i);
-                            world.bodies[j].velocity = world.bodies[j].velocity - impulse * (1.0 / mass_j);
-                            // 真实角冲量计算（仅对矩形，近似碰撞点在边缘）
-                            for (idx, impulse_sign) in [(i, 1.0), (j, -1.0)] {
-                                let body = &mut world.bodies[idx];
-                                if let Shape::Rectangle { width, height } = body.shape {
-                                    let r = (pos_j - pos_i).normalize() * (width.min(height) / 2.0);
-                                    let tau = r.x * impulse.y - r.y * impulse.x;
-                                    let inertia = (1.0 / 12.0) * body.mass * (width * width + height * height);
-                                    if inertia > 0.0 {
-                                        body.angular_velocity += impulse_sign * tau / inertia;
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-            
-            let world_json = serde_json::to_string(&*world).unwrap();
-            let message = format!("{}\n", world_json);
-            
-            let mut clients = clients.lock().unwrap();
-            let mut disconnected = Vec::new();
-            
-            for (&client_id, stream) in clients.iter_mut() {
-                if let Err(_) = stream.write_all(message.as_bytes()) {
-                    disconnected.push(client_id);
+        };
+        last_good = Some(snapshot.clone());
+        for message in &announcements {
+            room.broadcast(message, Delivery::Reliable);
+        }
+        let include_spectators = frame.is_multiple_of(spectator_interval);
+        room.broadcast_contacts(snapshot.tick, events);
+        room.broadcast_diagnostics(&snapshot, include_spectators);
+        room.broadcast_snapshot(snapshot, include_spectators);
+        if frame.is_multiple_of(presence_interval) {
+            room.broadcast_presence();
+        }
+        frame += 1;
+
+        // 非常驻房间无人超过超时时间后关闭
+        if !room.persistent {
+            if room.members.locked().is_empty() {
+                let since = *empty_since.get_or_insert(step_start);
+                if since.elapsed() >= idle_timeout && rooms.remove_if_empty(&room) {
+                    info!("房间 {} 空闲超时，已关闭", room.name);
+                    break;
                 }
-                let _ = stream.flush();
-            }
-            
-            for client_id in disconnected {
-                clients.remove(&client_id);
-                println!("客户端 {} 断开连接", client_id);
+            } else {
+                empty_since = None;
             }
         }
 
@@ -260,34 +836,26 @@ fn simulation_loop(world: Arc<Mutex<WorldState>>, clients: Arc<Mutex<HashMap<usi
     }
 }
 
-fn get_bounding_box_from_data(position: Vec2, shape: Shape) -> (Vec2, Vec2) {
-    match shape {
-        Shape::Circle { radius } => {
-            let min = Vec2::new(position.x - radius, position.y - radius);
-            let max = Vec2::new(position.x + radius, position.y + radius);
-            (min, max)
-        }
-        Shape::Rectangle { width, height } => {
-            // 简化的包围盒（不考虑旋转）
-            let half_width = width / 2.0;
-            let half_height = height / 2.0;
-            let min = Vec2::new(position.x - half_width, position.y - half_height);
-            let max = Vec2::new(position.x + half_width, position.y + half_height);
-            (min, max)
+// 推进一个模拟周期，返回要广播的快照、本周期的接触事件和排队执行的权限变更
+fn advance(room: &Room, rooms: &Rooms, fixed_dt: f32) -> (WorldState, Vec<ContactEvent>, Vec<ServerMessage>) {
+    let mut world = room.world.locked();
+
+    // 先执行排队到本周期的命令（或回放记录中的命令），再推进模拟
+    let announcements = room.apply_queued(&mut world, &rooms.limits);
+    room.feed_replay(&mut world);
+    let tick = world.tick;
+    let contacts = commands::step_tick(&mut world, fixed_dt);
+    // 暂停时周期不前进，接触状态保持不变
+    let mut events = Vec::new();
+    if world.tick != tick {
+        events = room.contacts.locked().update(contacts);
+        room.push_history(&world);
+        if world.tick.is_multiple_of(room.config.tick_rate as u64) {
+            room.checkpoint(&world);
         }
     }
-}
 
-fn calculate_overlap_from_data(pos_i: Vec2, shape_i: Shape, pos_j: Vec2, shape_j: Shape) -> f32 {
-    let (min_i, max_i) = get_bounding_box_from_data(pos_i, shape_i);
-    let (min_j, max_j) = get_bounding_box_from_data(pos_j, shape_j);
-    
-    let overlap_x = (max_i.x - min_j.x).min(max_j.x - min_i.x);
-    let overlap_y = (max_i.y - min_j.y).min(max_j.y - min_i.y);
-    
-    if overlap_x < 0.0 || overlap_y < 0.0 {
-        0.0
-    } else {
-        overlap_x.min(overlap_y)
-    }
-}
\ No newline at end of file
+    // 时间戳供客户端插值使用
+    world.server_time = rooms.epoch.elapsed().as_secs_f64();
+    (world.clone(), events, announcements)
+}
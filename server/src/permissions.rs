@@ -0,0 +1,40 @@
+use common::physics::{Access, Permissions, RigidBody, Role};
+
+// 对物体的操作种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Push,
+    Edit,
+    Delete,
+}
+
+impl Action {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Action::Push => "推动",
+            Action::Edit => "修改",
+            Action::Delete => "删除",
+        }
+    }
+}
+
+pub fn is_allowed(permissions: &Permissions, action: Action, role: Role, client_id: usize, body: &RigidBody) -> bool {
+    if role == Role::Teacher {
+        return true;
+    }
+    let access = match action {
+        Action::Push => permissions.push,
+        Action::Edit => permissions.edit,
+        Action::Delete => permissions.delete,
+    };
+    match access {
+        Access::Everyone => true,
+        Access::OwnerOnly => body.owner.is_none_or(|owner| owner == client_id),
+        Access::TeacherOnly => false,
+    }
+}
+
+// 重置整个世界会删除所有人的物体，只有教师或删除权限对所有人开放时才允许
+pub fn can_reset(permissions: &Permissions, role: Role) -> bool {
+    role == Role::Teacher || permissions.delete == Access::Everyone
+}
@@ -0,0 +1,176 @@
+use common::physics::WorldState;
+use crate::commands::{self, WorldCommand};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// 回放文件格式版本。文件第一行是 ReplayHeader，之后每行一条 ReplayEntry，
+// 边运行边追加，服务器中途崩溃时已写入的部分仍可回放
+pub const REPLAY_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub struct ReplayHeader {
+    pub version: u32,
+    pub room: String,
+    pub tick_rate: f32,
+    // 开始记录时的完整世界，包括物体的所有者和模拟进度
+    pub start: WorldState,
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum ReplayEntry {
+    // 在第 tick 周期开始时执行的命令（已通过权限检查）
+    Command {
+        tick: u64,
+        client_id: usize,
+        command: WorldCommand,
+    },
+    // 第 tick 周期结束时世界的校验和，回放时用来确认结果一致
+    Checksum { tick: u64, checksum: u64 },
+}
+
+impl ReplayEntry {
+    fn tick(&self) -> u64 {
+        match self {
+            ReplayEntry::Command { tick, .. } | ReplayEntry::Checksum { tick, .. } => *tick,
+        }
+    }
+}
+
+// 正在写入的回放记录
+pub struct Recorder {
+    path: PathBuf,
+    file: BufWriter<File>,
+}
+
+impl Recorder {
+    // 在 dir 中新建 房间名-时间戳.jsonl 并写入开始时的世界
+    pub fn create(dir: &Path, room: &str, tick_rate: f32, start: &WorldState) -> io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let safe_name: String = room
+            .chars()
+            .map(|c| if c.is_alphanumeric() || c == '-' { c } else { '_' })
+            .collect();
+        let path = dir.join(format!("{}-{}.jsonl", safe_name, secs));
+        let header = ReplayHeader {
+            version: REPLAY_VERSION,
+            room: room.to_string(),
+            tick_rate,
+            start: start.clone(),
+        };
+        let mut file = BufWriter::new(File::create(&path)?);
+        writeln!(file, "{}", serde_json::to_string(&header).unwrap())?;
+        file.flush()?;
+        Ok(Self { path, file })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record(&mut self, entry: &ReplayEntry) -> io::Result<()> {
+        writeln!(self.file, "{}", serde_json::to_string(entry).unwrap())?;
+        self.file.flush()
+    }
+}
+
+// 读入的回放记录，按周期逐条执行
+pub struct Replay {
+    pub header: ReplayHeader,
+    entries: VecDeque<ReplayEntry>,
+    verified: usize,
+    mismatches: usize,
+    // 上次 feed 执行了替换整个世界的命令，由房间取走后清除
+    pub replaced_world: bool,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("无法读取回放文件 {}: {}", path.display(), e))?;
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        let header: ReplayHeader = lines
+            .next()
+            .ok_or_else(|| format!("回放文件 {} 是空的", path.display()))
+            .and_then(|line| {
+                serde_json::from_str(line).map_err(|e| format!("回放文件 {} 开头格式错误: {}", path.display(), e))
+            })?;
+        if header.version > REPLAY_VERSION {
+            return Err(format!("回放版本 {} 比服务器支持的版本 {} 新", header.version, REPLAY_VERSION));
+        }
+        if !(1.0..=240.0).contains(&header.tick_rate) {
+            return Err("回放的模拟频率必须在 1 到 240 Hz 之间".to_string());
+        }
+        let mut entries = VecDeque::new();
+        for (i, line) in lines.enumerate() {
+            match serde_json::from_str(line) {
+                Ok(entry) => entries.push_back(entry),
+                Err(e) => {
+                    // 服务器崩溃时最后一行可能只写了一半
+                    warn!("回放文件第 {} 行无法解析，之后的记录被忽略: {}", i + 2, e);
+                    break;
+                }
+            }
+        }
+        Ok(Self {
+            header,
+            entries,
+            verified: 0,
+            mismatches: 0,
+            replaced_world: false,
+        })
+    }
+
+    // 在周期开始时执行记录中属于当前周期的命令、核对校验和。返回 false 表示记录已经播完
+    pub fn feed(&mut self, world: &mut WorldState) -> bool {
+        while let Some(entry) = self.entries.front() {
+            if entry.tick() > world.tick {
+                break;
+            }
+            match self.entries.pop_front().unwrap() {
+                ReplayEntry::Command { tick, .. } if tick < world.tick => {
+                    warn!("回放记录中第 {} 周期的命令已经错过，跳过", tick);
+                }
+                ReplayEntry::Command { client_id, command, .. } => {
+                    commands::execute(world, client_id, &command);
+                    self.replaced_world |= command.replaces_world();
+                }
+                ReplayEntry::Checksum { tick, checksum } => {
+                    if tick == world.tick && commands::checksum(world) == checksum {
+                        self.verified += 1;
+                    } else {
+                        self.mismatches += 1;
+                        warn!("回放在第 {} 周期与记录不一致", tick);
+                    }
+                }
+            }
+        }
+        // 暂停且没有单步时周期不再前进，剩下的记录永远等不到
+        if world.paused && world.pending_steps == 0 && !self.entries.is_empty() {
+            warn!("回放在第 {} 周期停在暂停状态，剩余 {} 条记录无法执行", world.tick, self.entries.len());
+            self.entries.clear();
+        }
+        !self.entries.is_empty()
+    }
+
+    pub fn mismatches(&self) -> usize {
+        self.mismatches
+    }
+
+    pub fn summary(&self) -> String {
+        format!("{} 个校验点一致，{} 个不一致", self.verified, self.mismatches)
+    }
+
+    // 不连接客户端，尽快重新模拟整个记录，返回最后的世界
+    pub fn run_fast(&mut self) -> WorldState {
+        let fixed_dt = 1.0 / self.header.tick_rate;
+        let mut world = self.header.start.clone();
+        while self.feed(&mut world) {
+            commands::step_tick(&mut world, fixed_dt);
+        }
+        world
+    }
+}
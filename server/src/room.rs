@@ -0,0 +1,722 @@
+use common::lock::LockExt;
+use common::physics::{
+    ChatMessage, ContactEvent, Permissions, PresenceInfo, RigidBody, Role, RoomConfig, RoomInfo, ServerMessage, Topic, UserInfo, Vec2,
+    WorldState,
+};
+use common::transport::{Delivery, MessageSender};
+use crate::commands::{self, Issuer, WorldCommand};
+use crate::contacts::ContactTracker;
+use crate::replay::{Recorder, Replay, ReplayEntry};
+use crate::transport;
+use crate::validation::Limits;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+pub type SharedSender = Arc<Mutex<Box<dyn MessageSender>>>;
+
+// 默认房间，客户端连接后自动加入，永不关闭
+pub const LOBBY: &str = "lobby";
+const MAX_ROOM_NAME_LEN: usize = 32;
+// 为后加入的成员保留的聊天记录条数
+const CHAT_HISTORY_LEN: usize = 50;
+// 回退历史最多保留的秒数，限制内存占用
+pub const MAX_HISTORY_SECS: f32 = 60.0;
+
+// 房间成员：发送端、用于用户列表的身份信息以及光标状态
+#[derive(Clone)]
+pub struct Member {
+    pub sender: SharedSender,
+    pub name: String,
+    pub role: Role,
+    pub spectator: bool,
+    pub color: [u8; 3],
+    pub cursor: Option<Vec2>,
+    pub drag_start: Option<Vec2>,
+    pub subscriptions: HashSet<Topic>,
+}
+
+// 确定性房间中排队的请求：修改世界的命令，以及影响之后命令权限检查的权限修改
+enum QueuedAction {
+    World(WorldCommand),
+    SetPermissions(Permissions),
+}
+
+// 确定性房间中等待执行的命令
+struct QueuedCommand {
+    // 排队时的周期，命令在该周期开始时执行
+    tick: u64,
+    issuer: Issuer,
+    sender: SharedSender,
+    action: QueuedAction,
+}
+
+// 一个房间：独立的世界、配置、成员和模拟线程
+pub struct Room {
+    pub name: String,
+    pub config: RoomConfig,
+    pub persistent: bool,
+    // 新建房间的客户端，服务器创建的房间为 None
+    pub creator: Option<usize>,
+    pub world: Mutex<WorldState>,
+    pub members: Mutex<HashMap<usize, Member>>,
+    // 教师可在运行时修改，初始值来自房间配置
+    pub permissions: Mutex<Permissions>,
+    // 确定性模式下排队到下一周期执行的命令
+    commands: Mutex<Vec<QueuedCommand>>,
+    // 正在写入的回放记录
+    pub recorder: Mutex<Option<Recorder>>,
+    // 回放模式下尚未执行的记录，播完后房间恢复正常
+    pub replay: Mutex<Option<Replay>>,
+    // 最近若干秒每个周期的世界，用于回退
+    history: Mutex<VecDeque<WorldState>>,
+    // 上一周期正在接触的物体，用于判断接触开始和结束
+    pub contacts: Mutex<ContactTracker>,
+    chat_history: Mutex<VecDeque<ChatMessage>>,
+}
+
+impl Room {
+    // 提交修改世界的命令：普通房间立即执行并返回结果，
+    // 确定性房间排队到下一周期开始时执行，届时的错误发给 sender
+    pub fn submit(&self, issuer: Issuer, sender: &SharedSender, command: WorldCommand, limits: &Limits) -> Result<(), String> {
+        if self.replay.locked().is_some() {
+            return Err("房间正在回放，不能修改世界".to_string());
+        }
+        if self.config.deterministic {
+            self.enqueue(issuer, sender, QueuedAction::World(command));
+            return Ok(());
+        }
+        let mut world = self.world.locked();
+        self.apply(&mut world, issuer, command, limits)
+    }
+
+    // 修改房间权限（调用者已确认是教师）。确定性房间与世界命令一起排队，
+    // 同一周期内排在它之后的命令按新权限检查
+    pub fn set_permissions(&self, issuer: Issuer, sender: &SharedSender, permissions: Permissions) {
+        if self.config.deterministic {
+            self.enqueue(issuer, sender, QueuedAction::SetPermissions(permissions));
+            return;
+        }
+        let message = self.store_permissions(permissions);
+        self.broadcast(&message, Delivery::Reliable);
+    }
+
+    // 持有世界锁时入队，记录的周期就是命令实际执行的周期
+    fn enqueue(&self, issuer: Issuer, sender: &SharedSender, action: QueuedAction) {
+        let world = self.world.locked();
+        self.commands.locked().push(QueuedCommand {
+            tick: world.tick,
+            issuer,
+            sender: sender.clone(),
+            action,
+        });
+    }
+
+    // 返回通知成员的权限变更消息
+    fn store_permissions(&self, permissions: Permissions) -> ServerMessage {
+        info!("房间 {} 权限修改为 {:?}", self.name, permissions);
+        *self.permissions.locked() = permissions.clone();
+        ServerMessage::PermissionsChanged { permissions }
+    }
+
+    // 在周期开始时执行排队的命令。按排队的周期和客户端 ID 排序，同一客户端的命令保持发送顺序，
+    // 结果与各连接线程的调度先后无关。返回的权限变更由调用者在释放世界锁后广播
+    pub fn apply_queued(&self, world: &mut WorldState, limits: &Limits) -> Vec<ServerMessage> {
+        let mut queued = std::mem::take(&mut *self.commands.locked());
+        queued.sort_by_key(|q| (q.tick, q.issuer.client_id));
+        let mut announcements = Vec::new();
+        for q in queued {
+            let result = match q.action {
+                QueuedAction::World(command) => self.apply(world, q.issuer, command, limits),
+                QueuedAction::SetPermissions(permissions) => {
+                    announcements.push(self.store_permissions(permissions));
+                    Ok(())
+                }
+            };
+            if let Err(message) = result {
+                warn!("客户端 {} 请求失败: {}", q.issuer.client_id, message);
+                let json = serde_json::to_string(&ServerMessage::Error { message }).unwrap();
+                let _ = q.sender.locked().send(&json, Delivery::Reliable);
+            }
+        }
+        announcements
+    }
+
+    fn apply(&self, world: &mut WorldState, issuer: Issuer, command: WorldCommand, limits: &Limits) -> Result<(), String> {
+        let command = match command {
+            WorldCommand::Rewind { tick } => self.rewind_to(tick)?,
+            command => commands::authorize(world, &self.permissions.locked(), limits, issuer, command)?,
+        };
+        // 回退会改变周期数，日志和回放记录用执行前的周期
+        let tick = world.tick;
+        commands::execute(world, issuer.client_id, &command);
+        if command.replaces_world() {
+            self.contacts.locked().reset();
+        }
+        let description = command.describe();
+        if command.is_frequent() {
+            debug!("房间 {} 第 {} 周期 客户端 {} {}", self.name, tick, issuer.client_id, description);
+        } else {
+            info!("房间 {} 第 {} 周期 客户端 {} {}", self.name, tick, issuer.client_id, description);
+        }
+        self.record(ReplayEntry::Command {
+            tick,
+            client_id: issuer.client_id,
+            command,
+        });
+        Ok(())
+    }
+
+    fn history_len(&self) -> usize {
+        (self.config.history_secs * self.config.tick_rate).round() as usize
+    }
+
+    // 每个周期结束时保存世界，超出时长的最早记录被丢弃
+    pub fn push_history(&self, world: &WorldState) {
+        let len = self.history_len();
+        if len == 0 {
+            return;
+        }
+        let mut history = self.history.locked();
+        history.push_back(world.clone());
+        while history.len() > len {
+            history.pop_front();
+        }
+    }
+
+    // 取出历史中该周期的世界。之后的记录属于被放弃的时间线，一并删除
+    fn rewind_to(&self, tick: u64) -> Result<WorldCommand, String> {
+        let mut history = self.history.locked();
+        let Some(index) = history.iter().position(|s| s.tick == tick) else {
+            return match (history.front(), history.back()) {
+                (Some(oldest), Some(newest)) => Err(format!(
+                    "第 {} 周期不在历史记录中，只能回退到第 {} 到 {} 周期",
+                    tick, oldest.tick, newest.tick
+                )),
+                _ => Err("没有可以回退的历史记录".to_string()),
+            };
+        };
+        history.truncate(index + 1);
+        Ok(WorldCommand::RestoreState {
+            state: Box::new(history[index].clone()),
+        })
+    }
+
+    // 模拟出错后退回到 restored，和回退一样执行并写入回放记录（客户端编号 0 表示服务器自身），
+    // 否则回放会从出错前的状态继续，与实际运行不一致
+    pub fn recover(&self, restored: WorldState) {
+        let tick = restored.tick;
+        let command = WorldCommand::RestoreState {
+            state: Box::new(restored),
+        };
+        commands::execute(&mut self.world.locked(), 0, &command);
+        self.contacts.locked().reset();
+        self.record(ReplayEntry::Command {
+            tick,
+            client_id: 0,
+            command,
+        });
+    }
+
+    // 开始记录回放，从当前的世界开始
+    pub fn start_recording(&self, dir: &Path) {
+        let world = self.world.locked();
+        match Recorder::create(dir, &self.name, self.config.tick_rate, &world) {
+            Ok(recorder) => {
+                info!("房间 {} 的回放记录写入 {}", self.name, recorder.path().display());
+                *self.recorder.locked() = Some(recorder);
+            }
+            Err(e) => warn!("无法为房间 {} 创建回放记录: {}", self.name, e),
+        }
+    }
+
+    // 写入失败时停止记录，房间照常运行
+    fn record(&self, entry: ReplayEntry) {
+        let mut recorder = self.recorder.locked();
+        if let Some(r) = recorder.as_mut() {
+            if let Err(e) = r.record(&entry) {
+                warn!("房间 {} 的回放记录 {} 写入失败，停止记录: {}", self.name, r.path().display(), e);
+                *recorder = None;
+            }
+        }
+    }
+
+    // 每秒一次的校验点：确定性房间打印校验和，正在记录时写入回放供回放时核对
+    pub fn checkpoint(&self, world: &WorldState) {
+        if !self.config.deterministic && self.recorder.locked().is_none() {
+            return;
+        }
+        let checksum = commands::checksum(world);
+        debug!("房间 {} 第 {} 周期校验和 {:016x}", self.name, world.tick, checksum);
+        self.record(ReplayEntry::Checksum { tick: world.tick, checksum });
+    }
+
+    // 回放模式：执行记录中当前周期的命令，播完后房间恢复正常
+    pub fn feed_replay(&self, world: &mut WorldState) {
+        let mut replay = self.replay.locked();
+        let Some(r) = replay.as_mut() else {
+            return;
+        };
+        let more = r.feed(world);
+        if std::mem::take(&mut r.replaced_world) {
+            self.contacts.locked().reset();
+        }
+        if !more {
+            info!("房间 {} 回放结束，{}", self.name, r.summary());
+            *replay = None;
+        }
+    }
+
+    // 向房间内所有成员广播，连接已断开的成员被移除
+    pub fn broadcast(&self, message: &ServerMessage, delivery: Delivery) {
+        self.broadcast_to(message, delivery, |_| true);
+    }
+
+    // 广播世界快照；观众只在 include_spectators 为 true 时收到，以降低其快照频率
+    pub fn broadcast_snapshot(&self, state: WorldState, include_spectators: bool) {
+        let message = ServerMessage::WorldState(state);
+        self.broadcast_to(&message, Delivery::Unreliable, |member| {
+            include_spectators || !member.spectator
+        });
+    }
+
+    // 有成员订阅时才计算能量和动量，与快照一样观众按较低的频率接收
+    pub fn broadcast_diagnostics(&self, state: &WorldState, include_spectators: bool) {
+        let wanted = |member: &Member| {
+            member.subscriptions.contains(&Topic::Diagnostics) && (include_spectators || !member.spectator)
+        };
+        if !self.members.locked().values().any(wanted) {
+            return;
+        }
+        let message = ServerMessage::Diagnostics(state.diagnostics());
+        self.broadcast_to(&message, Delivery::Unreliable, wanted);
+    }
+
+    // 接触事件不能丢，走可靠通道。订阅了的成员（包括观众）每个周期都收到
+    pub fn broadcast_contacts(&self, tick: u64, events: Vec<ContactEvent>) {
+        let wanted = |member: &Member| member.subscriptions.contains(&Topic::Contacts);
+        if events.is_empty() || !self.members.locked().values().any(wanted) {
+            return;
+        }
+        self.broadcast_to(&ServerMessage::Contacts { tick, events }, Delivery::Reliable, wanted);
+    }
+
+    fn broadcast_to(&self, message: &ServerMessage, delivery: Delivery, filter: impl Fn(&Member) -> bool) {
+        let json = serde_json::to_string(message).unwrap();
+        let mut members = self.members.locked();
+        let mut disconnected = Vec::new();
+
+        for (&client_id, member) in members.iter().filter(|(_, member)| filter(member)) {
+            match member.sender.locked().send(&json, delivery) {
+                Ok(()) => {}
+                Err(e) if transport::is_disconnect(&e) => disconnected.push(client_id),
+                Err(e) => warn!("向客户端 {} 发送消息失败: {}", client_id, e),
+            }
+        }
+
+        for client_id in disconnected {
+            members.remove(&client_id);
+            info!("客户端 {} 断开连接", client_id);
+        }
+    }
+
+    // 记录并转发聊天消息
+    pub fn chat(&self, message: ChatMessage) {
+        {
+            let mut history = self.chat_history.locked();
+            history.push_back(message.clone());
+            while history.len() > CHAT_HISTORY_LEN {
+                history.pop_front();
+            }
+        }
+        self.broadcast(&ServerMessage::Chat(message), Delivery::Reliable);
+    }
+
+    pub fn chat_history(&self) -> Vec<ChatMessage> {
+        self.chat_history.locked().iter().cloned().collect()
+    }
+
+    pub fn users(&self) -> Vec<UserInfo> {
+        let members = self.members.locked();
+        let mut users: Vec<UserInfo> = members
+            .iter()
+            .map(|(&client_id, member)| UserInfo {
+                client_id,
+                name: member.name.clone(),
+                role: member.role,
+                spectator: member.spectator,
+                color: member.color,
+            })
+            .collect();
+        users.sort_by_key(|user| user.client_id);
+        users
+    }
+
+    // 成员变化后向房间内所有人推送新的用户列表
+    pub fn broadcast_users(&self) {
+        let users = self.users();
+        self.broadcast(&ServerMessage::UserList { users }, Delivery::Reliable);
+    }
+
+    // 更新成员信息（如握手后改名），保留光标状态，成员不在房间内时忽略
+    pub fn update_member(&self, client_id: usize, member: Member) {
+        if let Some(entry) = self.members.locked().get_mut(&client_id) {
+            *entry = Member {
+                cursor: entry.cursor,
+                drag_start: entry.drag_start,
+                ..member
+            };
+        }
+        self.broadcast_users();
+    }
+
+    pub fn set_subscriptions(&self, client_id: usize, subscriptions: &HashSet<Topic>) {
+        if let Some(member) = self.members.locked().get_mut(&client_id) {
+            member.subscriptions = subscriptions.clone();
+        }
+    }
+
+    pub fn update_presence(&self, client_id: usize, cursor: Vec2, drag_start: Option<Vec2>) {
+        if let Some(member) = self.members.locked().get_mut(&client_id) {
+            member.cursor = Some(cursor);
+            member.drag_start = drag_start;
+        }
+    }
+
+    // 推送所有已上报光标的成员，丢失无妨，下一次推送会覆盖
+    pub fn broadcast_presence(&self) {
+        let users: Vec<PresenceInfo> = {
+            let members = self.members.locked();
+            members
+                .iter()
+                .filter_map(|(&client_id, member)| {
+                    member.cursor.map(|cursor| PresenceInfo {
+                        client_id,
+                        cursor,
+                        drag_start: member.drag_start,
+                    })
+                })
+                .collect()
+        };
+        if !users.is_empty() {
+            self.broadcast(&ServerMessage::Presence { users }, Delivery::Unreliable);
+        }
+    }
+
+    pub fn leave(&self, client_id: usize) {
+        self.members.locked().remove(&client_id);
+        self.broadcast_users();
+    }
+}
+
+// 房间表。加锁顺序固定为 先房间表 后成员表
+pub struct Rooms {
+    pub epoch: Instant,
+    // 新房间和重置世界使用的初始场景
+    pub scene: WorldState,
+    // 新建房间未指定配置时使用
+    pub default_config: RoomConfig,
+    pub limits: Limits,
+    // 设置后每个房间都把回放记录写到该目录
+    pub record_dir: Option<PathBuf>,
+    rooms: Mutex<HashMap<String, Arc<Room>>>,
+}
+
+impl Rooms {
+    pub fn new(scene: WorldState, default_config: RoomConfig, limits: Limits, record_dir: Option<PathBuf>) -> Self {
+        Self {
+            epoch: Instant::now(),
+            scene,
+            default_config,
+            limits,
+            record_dir,
+            rooms: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // 新建房间，成功后由调用方启动模拟线程。客户端新建的房间受房间总数和每个客户端房间数的限制
+    pub fn create(&self, name: &str, config: RoomConfig, persistent: bool, creator: Option<usize>) -> Result<Arc<Room>, String> {
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_ROOM_NAME_LEN {
+            return Err(format!("房间名长度必须在 1 到 {} 个字符之间", MAX_ROOM_NAME_LEN));
+        }
+        if !(1.0..=240.0).contains(&config.tick_rate) {
+            return Err("模拟频率必须在 1 到 240 Hz 之间".to_string());
+        }
+        if !(0.1..=config.tick_rate).contains(&config.spectator_rate) {
+            return Err("观众快照频率必须在 0.1 Hz 到模拟频率之间".to_string());
+        }
+        if !(0.0..=MAX_HISTORY_SECS).contains(&config.history_secs) {
+            return Err(format!("历史记录时长必须在 0 到 {} 秒之间", MAX_HISTORY_SECS));
+        }
+
+        let mut rooms = self.rooms.locked();
+        if rooms.contains_key(name) {
+            return Err(format!("房间 {} 已存在", name));
+        }
+        if let Some(client_id) = creator {
+            if rooms.len() >= self.limits.max_rooms {
+                return Err(format!("房间数量已达上限 {}", self.limits.max_rooms));
+            }
+            let created = rooms.values().filter(|room| room.creator == Some(client_id)).count();
+            if created >= self.limits.max_rooms_per_client {
+                return Err(format!("每个客户端最多新建 {} 个房间", self.limits.max_rooms_per_client));
+            }
+        }
+        let room = Arc::new(Room {
+            name: name.to_string(),
+            permissions: Mutex::new(config.permissions.clone()),
+            config,
+            persistent,
+            creator,
+            world: Mutex::new(self.scene.clone()),
+            members: Mutex::new(HashMap::new()),
+            commands: Mutex::new(Vec::new()),
+            recorder: Mutex::new(None),
+            replay: Mutex::new(None),
+            history: Mutex::new(VecDeque::new()),
+            contacts: Mutex::new(ContactTracker::default()),
+            chat_history: Mutex::new(VecDeque::new()),
+        });
+        rooms.insert(room.name.clone(), room.clone());
+        Ok(room)
+    }
+
+    // 把客户端加入指定房间（不会自动离开原房间）
+    pub fn join(&self, name: &str, client_id: usize, member: Member) -> Result<Arc<Room>, String> {
+        let room = {
+            let rooms = self.rooms.locked();
+            let room = rooms
+                .get(name.trim())
+                .ok_or_else(|| format!("房间 {} 不存在", name.trim()))?;
+            room.members.locked().insert(client_id, member);
+            room.clone()
+        };
+        room.broadcast_users();
+        Ok(room)
+    }
+
+    pub fn list(&self) -> Vec<RoomInfo> {
+        let rooms = self.rooms.locked();
+        let mut list: Vec<RoomInfo> = rooms
+            .values()
+            .map(|room| RoomInfo {
+                name: room.name.clone(),
+                clients: room.members.locked().len(),
+                bodies: room.world.locked().bodies.len(),
+            })
+            .collect();
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        list
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<Room>> {
+        self.rooms.locked().get(name).cloned()
+    }
+
+    pub fn all(&self) -> Vec<Arc<Room>> {
+        self.rooms.locked().values().cloned().collect()
+    }
+
+    // 房间仍然无人时将其移除，返回是否已移除
+    pub fn remove_if_empty(&self, room: &Room) -> bool {
+        let mut rooms = self.rooms.locked();
+        if !room.members.locked().is_empty() {
+            return false;
+        }
+        rooms.remove(&room.name);
+        true
+    }
+}
+
+pub fn initial_scene() -> WorldState {
+    WorldState {
+        bodies: vec![
+            RigidBody::new_circle(1, Vec2::new(200.0, 300.0), 30.0, 2.0),
+            RigidBody::new_circle(2, Vec2::new(400.0, 200.0), 25.0, 1.0),
+            RigidBody::new_rectangle(3, Vec2::new(600.0, 400.0), 80.0, 60.0, 3.0),
+            RigidBody::new_rectangle(4, Vec2::new(300.0, 500.0), 50.0, 50.0, 0.5),
+        ],
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::physics::Shape;
+    use crate::commands::{checksum, step_tick};
+    use std::io;
+    use std::sync::Barrier;
+    use std::thread;
+
+    const CLIENTS: usize = 3;
+    const TICKS: u64 = 120;
+
+    struct NullSender;
+
+    impl MessageSender for NullSender {
+        fn send(&mut self, _message: &str, _delivery: Delivery) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) {}
+    }
+
+    // 客户端在某个周期要发送的命令，同一客户端一个周期内可以有多条
+    fn commands_for(client_id: usize, tick: u64) -> Vec<WorldCommand> {
+        if tick % 20 != 5 {
+            return Vec::new();
+        }
+        let offset = client_id as f32 * 100.0;
+        vec![
+            WorldCommand::AddBody {
+                position: Vec2::new(400.0 + offset, 100.0),
+                shape: Shape::Circle { radius: 10.0 + client_id as f32 },
+                mass: 1.0 + client_id as f32,
+            },
+            WorldCommand::ApplyImpulse {
+                body_id: 1,
+                impulse: Vec2::new(50.0 - offset, -80.0),
+            },
+        ]
+    }
+
+    // 每个客户端在自己的线程中提交命令，schedule 决定各线程在每个周期的提交方式
+    fn run(schedule: impl Fn(u64, Vec<Box<dyn FnOnce() + Send>>)) -> u64 {
+        let config = RoomConfig {
+            deterministic: true,
+            ..Default::default()
+        };
+        let rooms = Rooms::new(initial_scene(), config.clone(), Limits::default(), None);
+        let room = rooms.create("test", config, false, None).unwrap();
+        let limits = Limits::default();
+        let dt = 1.0 / room.config.tick_rate;
+        for tick in 0..TICKS {
+            let submitters = (1..=CLIENTS)
+                .map(|client_id| {
+                    let room = room.clone();
+                    let limits = limits.clone();
+                    Box::new(move || {
+                        let sender: SharedSender = Arc::new(Mutex::new(Box::new(NullSender)));
+                        let issuer = Issuer {
+                            client_id,
+                            role: Role::Teacher,
+                        };
+                        for command in commands_for(client_id, tick) {
+                            room.submit(issuer, &sender, command, &limits).unwrap();
+                        }
+                    }) as Box<dyn FnOnce() + Send>
+                })
+                .collect();
+            schedule(tick, submitters);
+            let mut world = room.world.locked();
+            room.apply_queued(&mut world, &limits);
+            step_tick(&mut world, dt);
+        }
+        let world = room.world.locked();
+        assert_eq!(world.tick, TICKS);
+        checksum(&world)
+    }
+
+    // 按给定顺序依次启动线程，前一个线程提交完才启动下一个
+    fn in_order(order: &'static [usize]) -> impl Fn(u64, Vec<Box<dyn FnOnce() + Send>>) {
+        move |_, submitters| {
+            let mut submitters: Vec<_> = submitters.into_iter().map(Some).collect();
+            for &index in order {
+                let submit = submitters[index].take().unwrap();
+                thread::spawn(submit).join().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn queued_commands_do_not_depend_on_thread_interleaving() {
+        let expected = run(in_order(&[0, 1, 2]));
+        assert_eq!(run(in_order(&[2, 1, 0])), expected);
+        assert_eq!(run(in_order(&[1, 2, 0])), expected);
+        // 每个周期换一种顺序
+        assert_eq!(
+            run(|tick, submitters| {
+                let orders: [&'static [usize]; 3] = [&[2, 0, 1], &[0, 2, 1], &[1, 0, 2]];
+                in_order(orders[tick as usize % orders.len()])(tick, submitters)
+            }),
+            expected
+        );
+        // 所有线程同时开始提交，顺序由调度决定
+        assert_eq!(
+            run(|_, submitters| {
+                let barrier = Arc::new(Barrier::new(submitters.len()));
+                let handles: Vec<_> = submitters
+                    .into_iter()
+                    .map(|submit| {
+                        let barrier = barrier.clone();
+                        thread::spawn(move || {
+                            barrier.wait();
+                            submit();
+                        })
+                    })
+                    .collect();
+                for handle in handles {
+                    handle.join().unwrap();
+                }
+            }),
+            expected
+        );
+    }
+
+    #[test]
+    fn client_created_rooms_are_limited() {
+        let limits = Limits {
+            max_rooms: 4,
+            max_rooms_per_client: 2,
+            ..Default::default()
+        };
+        let rooms = Rooms::new(initial_scene(), RoomConfig::default(), limits, None);
+        rooms.create(LOBBY, RoomConfig::default(), true, None).unwrap();
+        rooms.create("a1", RoomConfig::default(), false, Some(1)).unwrap();
+        rooms.create("a2", RoomConfig::default(), false, Some(1)).unwrap();
+        assert!(rooms.create("a3", RoomConfig::default(), false, Some(1)).is_err());
+        rooms.create("b1", RoomConfig::default(), false, Some(2)).unwrap();
+        assert!(rooms.create("c1", RoomConfig::default(), false, Some(3)).is_err());
+        // 服务器自己创建的房间（例如从存档恢复）不受限制
+        rooms.create("restored", RoomConfig::default(), false, None).unwrap();
+    }
+
+    #[test]
+    fn queued_commands_change_the_world() {
+        let without_commands = {
+            let mut world = initial_scene();
+            for _ in 0..TICKS {
+                step_tick(&mut world, 1.0 / 60.0);
+            }
+            checksum(&world)
+        };
+        assert_ne!(run(in_order(&[0, 1, 2])), without_commands);
+    }
+
+    #[test]
+    fn recovery_is_written_to_the_recording() {
+        let dir = std::env::temp_dir().join(format!("sandbox-recover-test-{}", std::process::id()));
+        let rooms = Rooms::new(initial_scene(), RoomConfig::default(), Limits::default(), None);
+        let room = rooms.create(LOBBY, RoomConfig::default(), true, None).unwrap();
+        room.start_recording(&dir);
+        let fixed_dt = 1.0 / room.config.tick_rate;
+        for _ in 0..10 {
+            step_tick(&mut room.world.locked(), fixed_dt);
+        }
+        let last_good = room.world.locked().clone();
+        // 模拟出错的一步：世界已被改动，随后退回上一帧
+        step_tick(&mut room.world.locked(), fixed_dt);
+        room.world.locked().bodies.clear();
+        room.recover(last_good.clone());
+        let recovered = room.world.locked().clone();
+        assert_eq!(recovered.tick, last_good.tick);
+        let path = room.recorder.locked().take().unwrap().path().to_path_buf();
+
+        let replayed = Replay::load(&path).unwrap().run_fast();
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(checksum(&replayed), checksum(&recovered));
+    }
+}
@@ -0,0 +1,312 @@
+use common::physics::{ForceField, Joint, Label, RigidBody, Vec2, WorldParams, WorldState};
+use crate::validation::Limits;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// 当前的场景文件格式版本，修改格式时加一并在 migrate 中补上旧版本的转换
+pub const SCENE_VERSION: u32 = 3;
+const MAX_SCENE_NAME_LEN: usize = 64;
+const MAX_FORCE_FIELDS: usize = 32;
+// 力场加速度的上限，与重力相同
+const MAX_FIELD_ACCELERATION: f32 = 10_000.0;
+
+// 场景文件：物体（含材质和类型）、关节、力场、标注和世界参数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneFile {
+    pub version: u32,
+    #[serde(default)]
+    pub world: WorldParams,
+    pub bodies: Vec<RigidBody>,
+    #[serde(default)]
+    pub joints: Vec<Joint>,
+    #[serde(default)]
+    pub force_fields: Vec<ForceField>,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+    // 下一个新物体的 ID，保存后继续运行也不会重用已删除物体的 ID
+    pub next_id: u32,
+}
+
+impl SceneFile {
+    // 保存时去掉只在本次运行中有意义的状态
+    pub fn from_world(world: &WorldState) -> Self {
+        Self {
+            version: SCENE_VERSION,
+            world: world.params,
+            bodies: world
+                .bodies
+                .iter()
+                .map(|body| RigidBody {
+                    owner: None,
+                    collision_frames: 0,
+                    ..body.clone()
+                })
+                .collect(),
+            joints: world.joints.clone(),
+            force_fields: world.force_fields.clone(),
+            labels: world.labels.clone(),
+            next_id: world.next_id,
+        }
+    }
+
+    pub fn into_world(self) -> WorldState {
+        let mut world = WorldState {
+            bodies: self.bodies,
+            joints: self.joints,
+            force_fields: self.force_fields,
+            labels: self.labels,
+            params: self.world,
+            next_id: self.next_id,
+            ..Default::default()
+        };
+        for body in &mut world.bodies {
+            body.update_inertia();
+        }
+        // 碰撞按物体在列表中的顺序处理，按 ID 排序使结果不依赖文件中的顺序
+        world.bodies.sort_by_key(|b| b.id);
+        world.prune_labels();
+        world
+    }
+
+    pub fn parse(text: &str, limits: &Limits) -> Result<Self, String> {
+        let value: Value = serde_json::from_str(text).map_err(|e| format!("不是有效的 JSON: {}", e))?;
+        let scene: Self = serde_json::from_value(migrate(value)?).map_err(|e| format!("格式错误: {}", e))?;
+        scene.validate(limits)?;
+        Ok(scene)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    fn validate(&self, limits: &Limits) -> Result<(), String> {
+        check_world(&self.world)?;
+        if self.bodies.len() > limits.max_bodies_per_world {
+            return Err(format!("物体数量超过上限 {}", limits.max_bodies_per_world));
+        }
+        let mut ids = HashSet::new();
+        for body in &self.bodies {
+            if !ids.insert(body.id) {
+                return Err(format!("物体 ID {} 重复", body.id));
+            }
+            limits.check_body(body)?;
+        }
+        if self.joints.len() > limits.max_bodies_per_world {
+            return Err(format!("关节数量超过上限 {}", limits.max_bodies_per_world));
+        }
+        for joint in &self.joints {
+            for id in [joint.body_a, joint.body_b] {
+                if !ids.contains(&id) {
+                    return Err(format!("关节连接的物体 {} 不存在", id));
+                }
+            }
+            if joint.body_a == joint.body_b {
+                return Err(format!("关节不能连接物体 {} 自身", joint.body_a));
+            }
+            if !(joint.length.is_finite() && joint.length > 0.0) {
+                return Err("关节长度必须大于 0".to_string());
+            }
+        }
+        if self.force_fields.len() > MAX_FORCE_FIELDS {
+            return Err(format!("力场数量超过上限 {}", MAX_FORCE_FIELDS));
+        }
+        for field in &self.force_fields {
+            let finite = |v: Vec2| v.x.is_finite() && v.y.is_finite();
+            if !finite(field.min) || !finite(field.max) || field.min.x > field.max.x || field.min.y > field.max.y {
+                return Err("力场区域无效".to_string());
+            }
+            if !finite(field.acceleration) || field.acceleration.length() > MAX_FIELD_ACCELERATION {
+                return Err(format!("力场加速度不能超过 {}", MAX_FIELD_ACCELERATION));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn check_world(world: &WorldParams) -> Result<(), String> {
+    if !world.gravity.is_finite() || world.gravity.abs() > 10_000.0 {
+        return Err("重力加速度必须在 -10000 到 10000 之间".to_string());
+    }
+    for (what, value) in [("速度", world.linear_damping), ("角速度", world.angular_damping)] {
+        if !(0.0..=1.0).contains(&value) {
+            return Err(format!("{}阻尼必须在 0 到 1 之间", what));
+        }
+    }
+    Ok(())
+}
+
+// 把旧版本的场景逐版升级到当前版本
+fn migrate(mut value: Value) -> Result<Value, String> {
+    let Some(object) = value.as_object_mut() else {
+        return Err("场景必须是 JSON 对象".to_string());
+    };
+    // 版本 0：没有版本号，直接保存的 WorldState
+    let mut version = match object.get("version") {
+        None => 0,
+        Some(v) => v.as_u64().ok_or("版本号必须是整数")? as u32,
+    };
+    if version > SCENE_VERSION {
+        return Err(format!("场景版本 {} 比服务器支持的版本 {} 新", version, SCENE_VERSION));
+    }
+    while version < SCENE_VERSION {
+        match version {
+            0 => {
+                // 世界参数从 params 改名为 world，模拟进度等运行状态不再保存
+                if let Some(params) = object.remove("params") {
+                    object.insert("world".to_string(), params);
+                }
+                for key in ["tick", "server_time", "paused", "time_scale"] {
+                    object.remove(key);
+                }
+            }
+            1 => {
+                // 加入 next_id，从已有物体的最大 ID 之后开始
+                let max_id = object
+                    .get("bodies")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|body| body.get("id")?.as_u64())
+                    .max()
+                    .unwrap_or(0);
+                object.insert("next_id".to_string(), Value::from(max_id + 1));
+            }
+            2 => {
+                // 加入关节和力场，旧场景没有，读取时为空
+            }
+            _ => unreachable!(),
+        }
+        version += 1;
+        object.insert("version".to_string(), Value::from(version));
+    }
+    Ok(value)
+}
+
+pub fn load(path: &Path, limits: &Limits) -> Result<SceneFile, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("无法读取场景文件 {}: {}", path.display(), e))?;
+    SceneFile::parse(&text, limits).map_err(|e| format!("场景文件 {} {}", path.display(), e))
+}
+
+pub fn save(path: &Path, scene: &SceneFile) -> Result<(), String> {
+    write_atomic(path, &scene.to_json()).map_err(|e| format!("无法写入场景文件 {}: {}", path.display(), e))
+}
+
+// 先写到同目录的临时文件并落盘，再改名覆盖目标文件，写到一半崩溃也不会留下损坏的文件
+pub fn write_atomic(path: &Path, contents: &str) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    {
+        let mut file = File::create(&tmp)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+    }
+    std::fs::rename(&tmp, path)
+}
+
+// 服务器场景目录中的场景，名字只允许字母、数字、- 和 _，避免访问目录之外的文件
+pub fn library_path(dir: &Path, name: &str) -> Result<PathBuf, String> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_SCENE_NAME_LEN {
+        return Err(format!("场景名长度必须在 1 到 {} 个字符之间", MAX_SCENE_NAME_LEN));
+    }
+    if !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+        return Err("场景名只能包含字母、数字、- 和 _".to_string());
+    }
+    Ok(dir.join(format!("{}.json", name)))
+}
+
+// 场景目录中的场景名，按名字排序
+pub fn list(dir: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            if path.extension()? != "json" {
+                return None;
+            }
+            path.file_stem()?.to_str().map(str::to_string)
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::room::initial_scene;
+    use serde_json::json;
+
+    // 旧版本直接保存的 WorldState，只有当时已有的字段
+    fn version_0() -> Value {
+        let world = initial_scene();
+        json!({
+            "bodies": world.bodies,
+            "params": world.params,
+            "tick": 500,
+            "server_time": 8.3,
+            "paused": true,
+            "time_scale": 1.0,
+        })
+    }
+
+    #[test]
+    fn migrate_version_0_world_state() {
+        let migrated = migrate(version_0()).unwrap();
+        assert_eq!(migrated["version"], json!(SCENE_VERSION));
+        assert!(migrated.get("params").is_none());
+        assert!(migrated.get("world").is_some());
+        for key in ["tick", "server_time", "paused", "time_scale"] {
+            assert!(migrated.get(key).is_none(), "{} 应被删除", key);
+        }
+        let scene: SceneFile = serde_json::from_value(migrated).unwrap();
+        assert_eq!(scene.bodies.len(), initial_scene().bodies.len());
+        assert_eq!(scene.world.gravity, initial_scene().params.gravity);
+        assert_eq!(scene.next_id, 5);
+    }
+
+    #[test]
+    fn joints_must_connect_existing_bodies() {
+        let mut scene = SceneFile::from_world(&initial_scene());
+        scene.joints.push(Joint {
+            body_a: 1,
+            body_b: 2,
+            length: 50.0,
+        });
+        assert!(scene.validate(&Limits::default()).is_ok());
+        scene.joints[0].body_b = 99;
+        assert!(scene.validate(&Limits::default()).is_err());
+        scene.joints[0].body_b = 1;
+        assert!(scene.validate(&Limits::default()).is_err());
+    }
+
+    #[test]
+    fn migrate_version_1_adds_next_id() {
+        let migrated = migrate(json!({ "version": 1, "bodies": [{ "id": 9 }, { "id": 3 }] })).unwrap();
+        assert_eq!(migrated["next_id"], json!(10));
+        let empty = migrate(json!({ "version": 1, "bodies": [] })).unwrap();
+        assert_eq!(empty["next_id"], json!(1));
+    }
+
+    #[test]
+    fn migrate_keeps_current_version() {
+        let current = serde_json::to_value(SceneFile::from_world(&initial_scene())).unwrap();
+        assert_eq!(migrate(current.clone()).unwrap(), current);
+    }
+
+    #[test]
+    fn migrate_rejects_newer_version_and_non_objects() {
+        assert!(migrate(json!({ "version": SCENE_VERSION + 1, "bodies": [] })).is_err());
+        assert!(migrate(json!({ "version": "1", "bodies": [] })).is_err());
+        assert!(migrate(json!([])).is_err());
+    }
+}
@@ -0,0 +1,39 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+// SIGINT（Ctrl+C）和 SIGTERM 的编号，Linux 和 Windows 的 C 运行库相同
+const SIGINT: i32 = 2;
+const SIGTERM: i32 = 15;
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// 收到退出信号后置位。信号处理函数中只能做原子操作，保存和退出由轮询线程完成
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" {
+    // 标准库本身就链接了 C 运行库，直接使用其中的 signal 和 _exit，不需要额外的依赖
+    fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    fn _exit(status: i32) -> !;
+}
+
+extern "C" fn on_signal(_signum: i32) {
+    // 保存卡住时再按一次 Ctrl+C 立即退出
+    if REQUESTED.swap(true, Ordering::SeqCst) {
+        unsafe { _exit(130) }
+    }
+}
+
+// 收到 SIGINT 或 SIGTERM 时在后台线程中执行 on_exit，然后退出进程
+pub fn install(on_exit: impl FnOnce() + Send + 'static) {
+    unsafe {
+        signal(SIGINT, on_signal);
+        signal(SIGTERM, on_signal);
+    }
+    thread::spawn(move || {
+        while !REQUESTED.load(Ordering::SeqCst) {
+            thread::sleep(POLL_INTERVAL);
+        }
+        on_exit();
+        std::process::exit(0);
+    });
+}
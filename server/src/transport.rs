@@ -0,0 +1,300 @@
+use common::lock::LockExt;
+use common::transport::{
+    decode_packet, encode_packet, Delivery, LossySocket, MessageReceiver, MessageSender, ReliableState, TransportKind,
+    HANDSHAKE, HEADER_LEN, MAINTENANCE_INTERVAL, MAX_DATAGRAM, PACKET_DISCONNECT, PACKET_KEEPALIVE, PEER_TIMEOUT,
+};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// 对端不再读取、接收窗口填满时，一次写入最多阻塞这么久
+pub const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+// 发送失败是否说明对端已经断开或超时。其他错误（例如 UDP 发送缓冲区已满）只影响这一条消息
+pub fn is_disconnect(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::NotConnected
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::TimedOut
+    )
+}
+
+// 不经过发送端的锁直接断开连接。发送端可能正阻塞在写入上，看门狗用它释放连接
+pub type Closer = Box<dyn Fn() + Send>;
+
+pub struct Connection {
+    pub sender: Box<dyn MessageSender>,
+    pub receiver: Box<dyn MessageReceiver>,
+    pub closer: Closer,
+    pub peer: SocketAddr,
+}
+
+// 关闭 TCP 流的 Closer，持有流的另一个句柄
+pub fn stream_closer(stream: &TcpStream) -> io::Result<Closer> {
+    let stream = stream.try_clone()?;
+    Ok(Box::new(move || {
+        let _ = stream.shutdown(Shutdown::Both);
+    }))
+}
+
+// 写入超时时流中可能只写了半条消息，之后的数据都无法解析，只能断开
+pub fn check_write(stream: &TcpStream, result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+            let _ = stream.shutdown(Shutdown::Both);
+            Err(io::Error::new(io::ErrorKind::TimedOut, "发送超时，对端没有读取数据"))
+        }
+        result => result,
+    }
+}
+
+pub trait Listener: Send {
+    fn accept(&mut self) -> io::Result<Connection>;
+}
+
+pub fn bind(kind: TransportKind, addr: &str, packet_loss: f32) -> io::Result<Box<dyn Listener>> {
+    match kind {
+        TransportKind::Tcp => Ok(Box::new(TcpTransportListener {
+            listener: TcpListener::bind(addr)?,
+        })),
+        TransportKind::Udp => Ok(Box::new(UdpTransportListener::bind(addr, packet_loss)?)),
+    }
+}
+
+// ---------------- TCP ----------------
+
+struct TcpTransportListener {
+    listener: TcpListener,
+}
+
+impl Listener for TcpTransportListener {
+    fn accept(&mut self) -> io::Result<Connection> {
+        let (stream, peer) = self.listener.accept()?;
+        let _ = stream.set_nodelay(true);
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let reader = BufReader::new(stream.try_clone()?);
+        Ok(Connection {
+            closer: stream_closer(&stream)?,
+            sender: Box::new(TcpSender { stream }),
+            receiver: Box::new(TcpReceiver { reader }),
+            peer,
+        })
+    }
+}
+
+struct TcpSender {
+    stream: TcpStream,
+}
+
+impl MessageSender for TcpSender {
+    fn send(&mut self, message: &str, _delivery: Delivery) -> io::Result<()> {
+        let result = write_line(&mut self.stream, message);
+        check_write(&self.stream, result)
+    }
+
+    fn close(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+fn write_line(stream: &mut TcpStream, message: &str) -> io::Result<()> {
+    stream.write_all(message.as_bytes())?;
+    stream.write_all(b"\n")?;
+    stream.flush()
+}
+
+struct TcpReceiver {
+    reader: BufReader<TcpStream>,
+}
+
+impl MessageReceiver for TcpReceiver {
+    fn recv(&mut self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            if !line.trim().is_empty() {
+                return Ok(Some(line.trim().to_string()));
+            }
+        }
+    }
+}
+
+// ---------------- UDP ----------------
+
+struct UdpPeer {
+    state: ReliableState,
+    inbox: Sender<String>,
+}
+
+type PeerMap = Arc<Mutex<HashMap<SocketAddr, UdpPeer>>>;
+
+struct UdpTransportListener {
+    incoming: Receiver<Connection>,
+}
+
+impl UdpTransportListener {
+    fn bind(addr: &str, packet_loss: f32) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        let recv_socket = socket.try_clone()?;
+        let socket = Arc::new(LossySocket::new(socket, packet_loss));
+        let peers: PeerMap = Arc::new(Mutex::new(HashMap::new()));
+        let (incoming_tx, incoming) = mpsc::channel();
+
+        let recv_peers = peers.clone();
+        let recv_lossy = socket.clone();
+        thread::spawn(move || {
+            udp_receive_loop(recv_socket, recv_lossy, recv_peers, incoming_tx);
+        });
+
+        let maintenance_peers = peers.clone();
+        let maintenance_socket = socket.clone();
+        thread::spawn(move || {
+            udp_maintenance_loop(maintenance_socket, maintenance_peers);
+        });
+
+        Ok(Self { incoming })
+    }
+}
+
+impl Listener for UdpTransportListener {
+    fn accept(&mut self) -> io::Result<Connection> {
+        self.incoming
+            .recv()
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "UDP 接收线程已退出"))
+    }
+}
+
+fn udp_receive_loop(
+    socket: UdpSocket,
+    lossy: Arc<LossySocket>,
+    peers: PeerMap,
+    incoming: Sender<Connection>,
+) {
+    let mut buf = vec![0u8; MAX_DATAGRAM + HEADER_LEN];
+    loop {
+        let (len, addr) = match socket.recv_from(&mut buf) {
+            Ok(result) => result,
+            Err(_) => continue,
+        };
+        let Some((kind, seq, payload)) = decode_packet(&buf[..len]) else {
+            continue;
+        };
+
+        let mut peer_map = peers.locked();
+        if kind == PACKET_DISCONNECT {
+            peer_map.remove(&addr);
+            continue;
+        }
+        if let Entry::Vacant(entry) = peer_map.entry(addr) {
+            // 只有带握手内容的保活包才登记新对端。已断开的旧连接迟到的包、扫描和伪造地址的包都不会占用连接
+            if kind != PACKET_KEEPALIVE || payload != HANDSHAKE {
+                continue;
+            }
+            let (inbox, inbox_rx) = mpsc::channel();
+            entry.insert(UdpPeer { state: ReliableState::new(), inbox });
+            let closer_socket = lossy.clone();
+            let closer_peers = peers.clone();
+            let connection = Connection {
+                sender: Box::new(UdpPeerSender {
+                    socket: lossy.clone(),
+                    peers: peers.clone(),
+                    addr,
+                }),
+                receiver: Box::new(UdpPeerReceiver { inbox: inbox_rx }),
+                closer: Box::new(move || close_peer(&closer_socket, &closer_peers, addr)),
+                peer: addr,
+            };
+            let _ = incoming.send(connection);
+        }
+        let Some(peer) = peer_map.get_mut(&addr) else {
+            continue;
+        };
+        let (delivered, ack) = peer.state.receive(kind, seq, payload);
+        for message in delivered {
+            let _ = peer.inbox.send(message);
+        }
+        if let Some(ack) = ack {
+            let _ = lossy.send_to(&ack, addr);
+        }
+    }
+}
+
+// 定时重发未确认的可靠消息，并清理长时间无响应的对端
+fn udp_maintenance_loop(socket: Arc<LossySocket>, peers: PeerMap) {
+    loop {
+        thread::sleep(MAINTENANCE_INTERVAL);
+        let now = Instant::now();
+        let mut peers = peers.locked();
+        peers.retain(|addr, peer| {
+            if now.duration_since(peer.state.last_heard()) > PEER_TIMEOUT {
+                info!("UDP 对端 {} 超时", addr);
+                return false;
+            }
+            for packet in peer.state.due_resends(now) {
+                let _ = socket.send_to(&packet, *addr);
+            }
+            true
+        });
+    }
+}
+
+struct UdpPeerSender {
+    socket: Arc<LossySocket>,
+    peers: PeerMap,
+    addr: SocketAddr,
+}
+
+impl MessageSender for UdpPeerSender {
+    fn send(&mut self, message: &str, delivery: Delivery) -> io::Result<()> {
+        let packets = {
+            let mut peers = self.peers.locked();
+            match peers.get_mut(&self.addr) {
+                Some(peer) => peer.state.wrap(message, delivery)?,
+                None => return Err(io::Error::new(io::ErrorKind::NotConnected, "UDP 对端已断开")),
+            }
+        };
+        for packet in &packets {
+            self.socket.send_to(packet, self.addr)?;
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) {
+        close_peer(&self.socket, &self.peers, self.addr);
+    }
+}
+
+// 移除对端会丢弃其收件箱，接收端随之返回 None
+fn close_peer(socket: &LossySocket, peers: &PeerMap, addr: SocketAddr) {
+    if peers.locked().remove(&addr).is_some() {
+        let _ = socket.send_to(&encode_packet(PACKET_DISCONNECT, 0, &[]), addr);
+    }
+}
+
+impl Drop for UdpPeerSender {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+struct UdpPeerReceiver {
+    inbox: Receiver<String>,
+}
+
+impl MessageReceiver for UdpPeerReceiver {
+    fn recv(&mut self) -> io::Result<Option<String>> {
+        Ok(self.inbox.recv().ok())
+    }
+}
@@ -0,0 +1,394 @@
+use common::physics::{BodyProperties, ClientMessage, LabelAnchor, RigidBody, Shape, Vec2, WORLD_HEIGHT, WORLD_WIDTH};
+use std::collections::HashMap;
+use std::time::Instant;
+
+const MAX_TEXT_LEN: usize = 256;
+const MAX_CHAT_LEN: usize = 200;
+const MAX_LABEL_LEN: usize = 64;
+const MIN_TIME_SCALE: f32 = 0.05;
+const MAX_TIME_SCALE: f32 = 8.0;
+const MAX_STEPS_PER_REQUEST: u32 = 600;
+const MAX_IDLE_TIMEOUT_SECS: u64 = 3600;
+
+// 客户端输入的限制，可通过环境变量调整
+#[derive(Debug, Clone)]
+pub struct Limits {
+    pub min_size: f32,
+    pub max_size: f32,
+    pub min_mass: f32,
+    pub max_mass: f32,
+    pub max_impulse: f32,
+    pub max_speed: f32,
+    pub max_angular_speed: f32,
+    pub max_bodies_per_client: usize,
+    pub max_bodies_per_world: usize,
+    pub command_rate: f32, // 每种消息每秒允许的条数
+    pub spawn_rate: f32,   // 添加物体每秒允许的次数
+    pub chat_rate: f32,    // 聊天每秒允许的条数
+    pub max_rooms: usize,  // 客户端新建房间后服务器上最多的房间数（含大厅）
+    pub max_rooms_per_client: usize, // 每个客户端同时存在的、由其新建的房间数
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            min_size: 2.0,
+            max_size: 400.0,
+            min_mass: 0.01,
+            max_mass: 1000.0,
+            max_impulse: 100_000.0,
+            max_speed: 5000.0,
+            max_angular_speed: 100.0,
+            max_bodies_per_client: 50,
+            max_bodies_per_world: 300,
+            command_rate: 30.0,
+            spawn_rate: 5.0,
+            chat_rate: 2.0,
+            max_rooms: 20,
+            max_rooms_per_client: 2,
+        }
+    }
+}
+
+impl Limits {
+    // 例如 SANDBOX_MAX_BODIES_PER_CLIENT=20 SANDBOX_MAX_BODIES_PER_WORLD=100 SANDBOX_SPAWN_RATE=2
+    pub fn from_env() -> Self {
+        fn env<T: std::str::FromStr>(key: &str, default: T) -> T {
+            std::env::var(key)
+                .ok()
+                .and_then(|s| s.trim().parse().ok())
+                .unwrap_or(default)
+        }
+        let default = Self::default();
+        Self {
+            min_size: env("SANDBOX_MIN_SIZE", default.min_size),
+            max_size: env("SANDBOX_MAX_SIZE", default.max_size),
+            min_mass: env("SANDBOX_MIN_MASS", default.min_mass),
+            max_mass: env("SANDBOX_MAX_MASS", default.max_mass),
+            max_impulse: env("SANDBOX_MAX_IMPULSE", default.max_impulse),
+            max_speed: env("SANDBOX_MAX_SPEED", default.max_speed),
+            max_angular_speed: env("SANDBOX_MAX_ANGULAR_SPEED", default.max_angular_speed),
+            max_bodies_per_client: env("SANDBOX_MAX_BODIES_PER_CLIENT", default.max_bodies_per_client),
+            max_bodies_per_world: env("SANDBOX_MAX_BODIES_PER_WORLD", default.max_bodies_per_world),
+            command_rate: env("SANDBOX_COMMAND_RATE", default.command_rate),
+            spawn_rate: env("SANDBOX_SPAWN_RATE", default.spawn_rate),
+            chat_rate: env("SANDBOX_CHAT_RATE", default.chat_rate),
+            max_rooms: env("SANDBOX_MAX_ROOMS", default.max_rooms),
+            max_rooms_per_client: env("SANDBOX_MAX_ROOMS_PER_CLIENT", default.max_rooms_per_client),
+        }
+    }
+
+    // 检查消息各字段是否合法，不合法的消息不会被执行
+    pub fn validate(&self, message: &ClientMessage) -> Result<(), String> {
+        match message {
+            ClientMessage::Hello { name, token, .. } => {
+                check_name(name)?;
+                if let Some(token) = token {
+                    check_text("口令", token)?;
+                }
+            }
+            ClientMessage::SaveScene { name } | ClientMessage::LoadScene { name } => {
+                check_text("场景名", name)?;
+            }
+            ClientMessage::Chat { text } => {
+                check_message_text("聊天消息", text, MAX_CHAT_LEN)?;
+            }
+            ClientMessage::AddLabel { text, anchor } => {
+                check_message_text("标注", text, MAX_LABEL_LEN)?;
+                match anchor {
+                    LabelAnchor::Body { offset, .. } => check_vec("标注偏移", *offset)?,
+                    LabelAnchor::Point { position } => check_position(*position)?,
+                }
+            }
+            ClientMessage::UpdatePresence { cursor, drag_start } => {
+                check_vec("光标", *cursor)?;
+                if let Some(start) = drag_start {
+                    check_vec("拖拽起点", *start)?;
+                }
+            }
+            ClientMessage::ApplyImpulse { impulse, .. } => {
+                check_vec("冲量", *impulse)?;
+                if impulse.length() > self.max_impulse {
+                    return Err(format!("冲量大小不能超过 {}", self.max_impulse));
+                }
+            }
+            ClientMessage::AddRectangle { position, width, height, mass } => {
+                check_position(*position)?;
+                self.check_size("宽度", *width)?;
+                self.check_size("高度", *height)?;
+                self.check_mass(*mass)?;
+            }
+            ClientMessage::AddCircle { position, radius, mass } => {
+                check_position(*position)?;
+                self.check_size("半径", *radius)?;
+                self.check_mass(*mass)?;
+            }
+            ClientMessage::CreateRoom { name, config } => {
+                check_text("房间名", name)?;
+                if let Some(config) = config {
+                    if !(1..=MAX_IDLE_TIMEOUT_SECS).contains(&config.idle_timeout_secs) {
+                        return Err(format!("空闲关闭时间必须在 1 到 {} 秒之间", MAX_IDLE_TIMEOUT_SECS));
+                    }
+                }
+            }
+            ClientMessage::JoinRoom { name } => {
+                check_text("房间名", name)?;
+            }
+            ClientMessage::SetBodyProperties { properties, .. } => {
+                self.check_properties(properties)?;
+            }
+            ClientMessage::StepSimulation { steps } => {
+                if *steps == 0 || *steps > MAX_STEPS_PER_REQUEST {
+                    return Err(format!("单步步数必须在 1 到 {} 之间", MAX_STEPS_PER_REQUEST));
+                }
+            }
+            ClientMessage::SetTimeScale { time_scale } => {
+                check_range("时间倍率", *time_scale, MIN_TIME_SCALE, MAX_TIME_SCALE)?;
+            }
+            ClientMessage::RemoveBodiesInRect { min, max } => {
+                check_vec("区域", *min)?;
+                check_vec("区域", *max)?;
+            }
+            ClientMessage::Ping { .. }
+            | ClientMessage::ListRooms
+            | ClientMessage::ListUsers
+            | ClientMessage::SetPermissions { .. }
+            | ClientMessage::SetPaused { .. }
+            | ClientMessage::RemoveLabel { .. }
+            | ClientMessage::ListScenes
+            | ClientMessage::RemoveBody { .. }
+            | ClientMessage::RemoveOwnedBodies
+            | ClientMessage::ResetWorld
+            | ClientMessage::Rewind { .. }
+            | ClientMessage::Subscribe { .. } => {}
+        }
+        Ok(())
+    }
+
+    // 添加物体前检查客户端和世界的物体数量上限
+    pub fn check_capacity(&self, bodies: &[RigidBody], client_id: usize) -> Result<(), String> {
+        if bodies.len() >= self.max_bodies_per_world {
+            return Err(format!("房间内物体已达上限 {}", self.max_bodies_per_world));
+        }
+        let owned = bodies.iter().filter(|b| b.owner == Some(client_id)).count();
+        if owned >= self.max_bodies_per_client {
+            return Err(format!("每个客户端最多添加 {} 个物体", self.max_bodies_per_client));
+        }
+        Ok(())
+    }
+
+    // 场景文件中的物体按修改属性的同样规则检查
+    pub fn check_body(&self, body: &RigidBody) -> Result<(), String> {
+        let properties = BodyProperties {
+            position: Some(body.position),
+            angle: Some(body.angle),
+            velocity: Some(body.velocity),
+            angular_velocity: Some(body.angular_velocity),
+            mass: Some(body.mass),
+            shape: Some(body.shape),
+            material: Some(body.material),
+            body_type: Some(body.body_type),
+        };
+        self.check_properties(&properties)
+            .map_err(|e| format!("物体 {}: {}", body.id, e))
+    }
+
+    fn check_properties(&self, properties: &BodyProperties) -> Result<(), String> {
+        if let Some(position) = properties.position {
+            check_position(position)?;
+        }
+        if let Some(angle) = properties.angle {
+            if !angle.is_finite() {
+                return Err("角度必须是有限数值".to_string());
+            }
+        }
+        if let Some(velocity) = properties.velocity {
+            check_vec("速度", velocity)?;
+            if velocity.length() > self.max_speed {
+                return Err(format!("速度大小不能超过 {}", self.max_speed));
+            }
+        }
+        if let Some(angular_velocity) = properties.angular_velocity {
+            let max = self.max_angular_speed;
+            check_range("角速度", angular_velocity, -max, max)?;
+        }
+        if let Some(mass) = properties.mass {
+            self.check_mass(mass)?;
+        }
+        match properties.shape {
+            Some(Shape::Circle { radius }) => self.check_size("半径", radius)?,
+            Some(Shape::Rectangle { width, height }) => {
+                self.check_size("宽度", width)?;
+                self.check_size("高度", height)?;
+            }
+            None => {}
+        }
+        if let Some(material) = properties.material {
+            check_range("弹性系数", material.restitution, 0.0, 1.0)?;
+            check_range("摩擦系数", material.friction, 0.0, 2.0)?;
+        }
+        Ok(())
+    }
+
+    fn check_size(&self, what: &str, value: f32) -> Result<(), String> {
+        check_range(what, value, self.min_size, self.max_size)
+    }
+
+    fn check_mass(&self, value: f32) -> Result<(), String> {
+        check_range("质量", value, self.min_mass, self.max_mass)
+    }
+}
+
+fn check_range(what: &str, value: f32, min: f32, max: f32) -> Result<(), String> {
+    // NaN 与任何数比较都为 false，会在这里被拒绝
+    if (min..=max).contains(&value) {
+        Ok(())
+    } else {
+        Err(format!("{}必须在 {} 到 {} 之间", what, min, max))
+    }
+}
+
+fn check_vec(what: &str, v: Vec2) -> Result<(), String> {
+    if v.x.is_finite() && v.y.is_finite() {
+        Ok(())
+    } else {
+        Err(format!("{}必须是有限数值", what))
+    }
+}
+
+fn check_position(position: Vec2) -> Result<(), String> {
+    check_vec("位置", position)?;
+    if (0.0..=WORLD_WIDTH).contains(&position.x) && (0.0..=WORLD_HEIGHT).contains(&position.y) {
+        Ok(())
+    } else {
+        Err(format!("位置必须在世界范围 {}x{} 内", WORLD_WIDTH, WORLD_HEIGHT))
+    }
+}
+
+fn check_text(what: &str, text: &str) -> Result<(), String> {
+    if text.len() > MAX_TEXT_LEN {
+        Err(format!("{}过长", what))
+    } else {
+        Ok(())
+    }
+}
+
+// 名字会出现在服务器日志和用户列表中，不能含换行等控制字符
+fn check_name(name: &str) -> Result<(), String> {
+    check_text("名字", name)?;
+    if name.chars().any(char::is_control) {
+        return Err("名字不能包含换行等控制字符".to_string());
+    }
+    Ok(())
+}
+
+// 聊天和标注：去掉首尾空白后不能为空，按字符数限制长度
+fn check_message_text(what: &str, text: &str, max_chars: usize) -> Result<(), String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(format!("{}不能为空", what));
+    }
+    if text.chars().count() > max_chars {
+        return Err(format!("{}不能超过 {} 个字符", what, max_chars));
+    }
+    if text.chars().any(|c| c.is_control()) {
+        return Err(format!("{}不能包含控制字符", what));
+    }
+    Ok(())
+}
+
+// 令牌桶：容量为一秒的配额，按速率持续补充
+struct TokenBucket {
+    tokens: f32,
+    last_refill: Instant,
+}
+
+// 每个客户端一个，按消息类型分别限速
+pub struct RateLimiter {
+    buckets: HashMap<&'static str, TokenBucket>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self { buckets: HashMap::new() }
+    }
+
+    // 消耗一个令牌，超出速率时返回错误
+    pub fn check(&mut self, message: &ClientMessage, limits: &Limits) -> Result<(), String> {
+        let kind = message_kind(message);
+        let rate = match message {
+            ClientMessage::AddRectangle { .. } | ClientMessage::AddCircle { .. } => limits.spawn_rate,
+            ClientMessage::Chat { .. } => limits.chat_rate,
+            _ => limits.command_rate,
+        };
+        let now = Instant::now();
+        let bucket = self.buckets.entry(kind).or_insert(TokenBucket {
+            tokens: rate.max(1.0),
+            last_refill: now,
+        });
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f32();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(rate.max(1.0));
+        bucket.last_refill = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(format!("{} 请求过于频繁，每秒最多 {} 次", kind, rate))
+        }
+    }
+}
+
+fn message_kind(message: &ClientMessage) -> &'static str {
+    match message {
+        ClientMessage::Hello { .. } => "Hello",
+        ClientMessage::Ping { .. } => "Ping",
+        ClientMessage::ApplyImpulse { .. } => "ApplyImpulse",
+        ClientMessage::AddRectangle { .. } => "AddRectangle",
+        ClientMessage::AddCircle { .. } => "AddCircle",
+        ClientMessage::ListRooms => "ListRooms",
+        ClientMessage::ListUsers => "ListUsers",
+        ClientMessage::UpdatePresence { .. } => "UpdatePresence",
+        ClientMessage::Chat { .. } => "Chat",
+        ClientMessage::SaveScene { .. } => "SaveScene",
+        ClientMessage::LoadScene { .. } => "LoadScene",
+        ClientMessage::ListScenes => "ListScenes",
+        ClientMessage::Subscribe { .. } => "Subscribe",
+        ClientMessage::AddLabel { .. } => "AddLabel",
+        ClientMessage::RemoveLabel { .. } => "RemoveLabel",
+        ClientMessage::CreateRoom { .. } => "CreateRoom",
+        ClientMessage::JoinRoom { .. } => "JoinRoom",
+        ClientMessage::SetPermissions { .. } => "SetPermissions",
+        ClientMessage::SetBodyProperties { .. } => "SetBodyProperties",
+        ClientMessage::SetPaused { .. } => "SetPaused",
+        ClientMessage::StepSimulation { .. } => "StepSimulation",
+        ClientMessage::SetTimeScale { .. } => "SetTimeScale",
+        ClientMessage::RemoveBody { .. } => "RemoveBody",
+        ClientMessage::RemoveOwnedBodies => "RemoveOwnedBodies",
+        ClientMessage::RemoveBodiesInRect { .. } => "RemoveBodiesInRect",
+        ClientMessage::ResetWorld => "ResetWorld",
+        ClientMessage::Rewind { .. } => "Rewind",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hello(name: &str) -> ClientMessage {
+        ClientMessage::Hello {
+            name: name.to_string(),
+            token: None,
+            spectator: false,
+            color: None,
+        }
+    }
+
+    #[test]
+    fn names_must_not_contain_control_characters() {
+        let limits = Limits::default();
+        assert!(limits.validate(&hello("小明")).is_ok());
+        for name in ["小明\n客户端 2 断开连接", "a\rb", "a\tb", "a\u{1b}[31mb", "a\u{85}b"] {
+            assert!(limits.validate(&hello(name)).is_err(), "{:?}", name);
+        }
+    }
+}
@@ -0,0 +1,364 @@
+use common::lock::LockExt;
+use common::transport::{Delivery, MessageReceiver, MessageSender};
+use crate::transport::{self, Connection, Listener, WRITE_TIMEOUT};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// RFC 6455 握手使用的固定 GUID
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const MAX_MESSAGE_SIZE: u64 = 1 << 20;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+// HTTP 请求行和请求头的总长度上限
+const MAX_REQUEST_SIZE: u64 = 8 * 1024;
+// 关闭帧的状态码：协议错误
+const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+// 浏览器查看器页面，普通 HTTP GET 请求时直接返回
+const VIEWER_HTML: &str = include_str!("../assets/viewer.html");
+
+// WebSocket 监听器：同一端口既提供查看器页面，也接受 WebSocket 升级。
+// 每个连接在自己的线程中握手，迟迟不发请求的客户端不会挡住其他人
+pub struct WebSocketListener {
+    incoming: Receiver<Connection>,
+}
+
+impl WebSocketListener {
+    pub fn bind(addr: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let (incoming_tx, incoming) = mpsc::channel();
+        thread::spawn(move || websocket_accept_loop(listener, incoming_tx));
+        Ok(Self { incoming })
+    }
+}
+
+impl Listener for WebSocketListener {
+    fn accept(&mut self) -> io::Result<Connection> {
+        self.incoming
+            .recv()
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "WebSocket 监听线程已退出"))
+    }
+}
+
+fn websocket_accept_loop(listener: TcpListener, incoming: Sender<Connection>) {
+    loop {
+        let (stream, peer) = match listener.accept() {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("WebSocket 接受连接失败: {}", e);
+                continue;
+            }
+        };
+        let incoming = incoming.clone();
+        thread::spawn(move || match connect(stream, peer) {
+            Ok(Some(connection)) => {
+                let _ = incoming.send(connection);
+            }
+            Ok(None) => {}
+            Err(e) => warn!("WebSocket 握手失败 ({}): {}", peer, e),
+        });
+    }
+}
+
+fn connect(stream: TcpStream, peer: SocketAddr) -> io::Result<Option<Connection>> {
+    let Some(stream) = handshake(stream)? else {
+        return Ok(None);
+    };
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let writer = Arc::new(Mutex::new(stream.try_clone()?));
+    Ok(Some(Connection {
+        sender: Box::new(WebSocketSender { stream: writer.clone() }),
+        closer: transport::stream_closer(&stream)?,
+        receiver: Box::new(WebSocketReceiver {
+            reader: BufReader::new(stream),
+            writer,
+        }),
+        peer,
+    }))
+}
+
+// 读取 HTTP 请求：升级请求返回握手完成的连接，其余请求返回页面后关闭
+fn handshake(mut stream: TcpStream) -> io::Result<Option<TcpStream>> {
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?.take(MAX_REQUEST_SIZE));
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut key = None;
+    let mut upgrade = false;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            // 读到上限或连接关闭时还没有遇到空行
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "HTTP 请求头过大或不完整"));
+        }
+        if header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let name = name.trim().to_ascii_lowercase();
+            let value = value.trim();
+            if name == "sec-websocket-key" {
+                key = Some(value.to_string());
+            } else if name == "upgrade" && value.eq_ignore_ascii_case("websocket") {
+                upgrade = true;
+            }
+        }
+    }
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+    match key {
+        Some(key) if upgrade => {
+            let accept = base64_encode(&sha1(format!("{}{}", key, WEBSOCKET_GUID).as_bytes()));
+            let response = format!(
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                accept
+            );
+            stream.write_all(response.as_bytes())?;
+            stream.set_read_timeout(None)?;
+            Ok(Some(stream))
+        }
+        _ => {
+            let response = if path == "/" || path == "/index.html" {
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    VIEWER_HTML.len(),
+                    VIEWER_HTML
+                )
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+            };
+            stream.write_all(response.as_bytes())?;
+            Ok(None)
+        }
+    }
+}
+
+fn write_frame(stream: &mut TcpStream, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    // 服务器发出的帧不加掩码
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x80 | opcode);
+    if payload.len() < 126 {
+        frame.push(payload.len() as u8);
+    } else if payload.len() <= u16::MAX as usize {
+        frame.push(126);
+        frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    } else {
+        frame.push(127);
+        frame.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+    }
+    frame.extend_from_slice(payload);
+    stream.write_all(&frame)?;
+    stream.flush()
+}
+
+struct WebSocketSender {
+    stream: Arc<Mutex<TcpStream>>,
+}
+
+impl MessageSender for WebSocketSender {
+    fn send(&mut self, message: &str, _delivery: Delivery) -> io::Result<()> {
+        let mut stream = self.stream.locked();
+        let result = write_frame(&mut stream, OPCODE_TEXT, message.as_bytes());
+        transport::check_write(&stream, result)
+    }
+
+    fn close(&mut self) {
+        let mut stream = self.stream.locked();
+        let _ = write_frame(&mut stream, OPCODE_CLOSE, &[]);
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+}
+
+struct WebSocketReceiver {
+    reader: BufReader<TcpStream>,
+    writer: Arc<Mutex<TcpStream>>,
+}
+
+impl WebSocketReceiver {
+    // 读取一帧，返回 (FIN, opcode, 去掩码后的载荷)
+    fn read_frame(&mut self) -> io::Result<(bool, u8, Vec<u8>)> {
+        let mut header = [0u8; 2];
+        self.reader.read_exact(&mut header)?;
+        let fin = header[0] & 0x80 != 0;
+        let opcode = header[0] & 0x0F;
+        let masked = header[1] & 0x80 != 0;
+        let mut len = (header[1] & 0x7F) as u64;
+        if len == 126 {
+            let mut ext = [0u8; 2];
+            self.reader.read_exact(&mut ext)?;
+            len = u16::from_be_bytes(ext) as u64;
+        } else if len == 127 {
+            let mut ext = [0u8; 8];
+            self.reader.read_exact(&mut ext)?;
+            len = u64::from_be_bytes(ext);
+        }
+        if len > MAX_MESSAGE_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "WebSocket 帧过大"));
+        }
+        // RFC 6455 第 5.1 节：客户端发出的帧必须加掩码，否则服务器应关闭连接
+        if !masked {
+            let mut stream = self.writer.locked();
+            let _ = write_frame(&mut stream, OPCODE_CLOSE, &CLOSE_PROTOCOL_ERROR.to_be_bytes());
+            let _ = stream.shutdown(Shutdown::Both);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "客户端帧没有加掩码"));
+        }
+        let mut mask = [0u8; 4];
+        self.reader.read_exact(&mut mask)?;
+        let mut payload = vec![0u8; len as usize];
+        self.reader.read_exact(&mut payload)?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+        Ok((fin, opcode, payload))
+    }
+}
+
+impl MessageReceiver for WebSocketReceiver {
+    fn recv(&mut self) -> io::Result<Option<String>> {
+        let mut message = Vec::new();
+        loop {
+            let (fin, opcode, payload) = match self.read_frame() {
+                Ok(frame) => frame,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e),
+            };
+            match opcode {
+                OPCODE_TEXT | OPCODE_BINARY | OPCODE_CONTINUATION => {
+                    message.extend_from_slice(&payload);
+                    if message.len() as u64 > MAX_MESSAGE_SIZE {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "WebSocket 消息过大"));
+                    }
+                    if fin {
+                        let text = String::from_utf8_lossy(&message).trim().to_string();
+                        if text.is_empty() {
+                            message.clear();
+                            continue;
+                        }
+                        return Ok(Some(text));
+                    }
+                }
+                OPCODE_PING => {
+                    let mut stream = self.writer.locked();
+                    write_frame(&mut stream, OPCODE_PONG, &payload)?;
+                }
+                OPCODE_PONG => {}
+                OPCODE_CLOSE => {
+                    let mut stream = self.writer.locked();
+                    let _ = write_frame(&mut stream, OPCODE_CLOSE, &payload);
+                    return Ok(None);
+                }
+                _ => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "未知的 WebSocket 操作码"));
+                }
+            }
+        }
+    }
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    let bit_len = (data.len() as u64).wrapping_mul(8);
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&bit_len.to_be_bytes());
+
+    for chunk in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for i in 0..16 {
+            w[i] = u32::from_be_bytes([chunk[i * 4], chunk[i * 4 + 1], chunk[i * 4 + 2], chunk[i * 4 + 3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        h[0] = h[0].wrapping_add(a);
+        h[1] = h[1].wrapping_add(b);
+        h[2] = h[2].wrapping_add(c);
+        h[3] = h[3].wrapping_add(d);
+        h[4] = h[4].wrapping_add(e);
+    }
+
+    let mut digest = [0u8; 20];
+    for (i, word) in h.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | b[2] as u32;
+        out.push(ALPHABET[(n >> 18) as usize & 63] as char);
+        out.push(ALPHABET[(n >> 12) as usize & 63] as char);
+        out.push(if chunk.len() > 1 { ALPHABET[(n >> 6) as usize & 63] as char } else { '=' });
+        out.push(if chunk.len() > 2 { ALPHABET[n as usize & 63] as char } else { '=' });
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha1_known_digests() {
+        let hex = |digest: [u8; 20]| digest.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        assert_eq!(hex(sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(hex(sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    }
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode(b"foobar"), "Zm9vYmFy");
+    }
+
+    // RFC 6455 第 1.3 节的示例
+    #[test]
+    fn accept_key_matches_rfc_example() {
+        let key = "dGhlIHNhbXBsZSBub25jZQ==";
+        let accept = base64_encode(&sha1(format!("{}{}", key, WEBSOCKET_GUID).as_bytes()));
+        assert_eq!(accept, "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+}